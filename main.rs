@@ -8,7 +8,7 @@ use std::sync::Arc;
 use std::time::{Duration as StdDuration, Instant};
 use tokio::net::UdpSocket;
 use tokio::sync::Mutex;
-use tokio::time::{sleep, Duration};
+use tokio::time::sleep;
 
 // --- Map Object Definitions and Loading ---
 #[derive(Deserialize, Debug)]
@@ -21,11 +21,45 @@ struct MapObject {
     height: f32,
 }
 
-// Load the map_data.json file at compile time.
-static MAP_OBJECTS: Lazy<Vec<MapObject>> = Lazy::new(|| {
-    let json_str = include_str!("./assets/map_data.json");
-    serde_json::from_str(json_str).expect("Failed to parse map_data.json")
-});
+// Object types a map file may contain: this server's plain goals, and the team goals used by
+// the ublike server's maps so the same files load in both
+const KNOWN_OBJECT_TYPES: [&str; 6] = ["wall", "goal", "goal_red", "goal_blue", "goal_yellow", "goal_green"];
+
+// Resolve the map file from `--map <file>`, then MAP_FILE, then assets/map_data.json.
+fn map_path_from_env() -> String {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(pos) = args.iter().position(|arg| arg == "--map") {
+        if let Some(path) = args.get(pos + 1) {
+            return path.clone();
+        }
+    }
+    std::env::var("MAP_FILE").unwrap_or_else(|_| "assets/map_data.json".to_string())
+}
+
+// Load and validate a map file at startup.
+fn load_map(path: &str) -> Result<Vec<MapObject>, String> {
+    let json_str = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read map file {}: {}", path, e))?;
+    let objects: Vec<MapObject> = serde_json::from_str(&json_str)
+        .map_err(|e| format!("Failed to parse map file {}: {}", path, e))?;
+
+    for (index, obj) in objects.iter().enumerate() {
+        if !KNOWN_OBJECT_TYPES.contains(&obj.obj_type.as_str()) {
+            return Err(format!("{}: object {} has unknown type '{}'", path, index, obj.obj_type));
+        }
+        if !(obj.x.is_finite() && obj.y.is_finite() && obj.width.is_finite() && obj.height.is_finite()) {
+            return Err(format!("{}: object {} has non-finite coordinates", path, index));
+        }
+        if obj.width <= 0.0 || obj.height <= 0.0 {
+            return Err(format!("{}: object {} has non-positive size", path, index));
+        }
+    }
+    if !objects.iter().any(|obj| obj.obj_type.starts_with("goal")) {
+        return Err(format!("{}: map has no goals", path));
+    }
+
+    Ok(objects)
+}
 
 // --- Ping Message Types ---
 #[derive(Serialize, Deserialize)]
@@ -89,6 +123,7 @@ struct Player {
 
 #[derive(Debug, Serialize)]
 struct GameStateSnapshot {
+    tick: u64, // simulation tick this snapshot was taken after
     time: u64, // server timestamp in ms
     players: HashMap<u32, ShipState>,
     ball: Ball,
@@ -100,10 +135,12 @@ struct Game {
     clients: HashMap<u32, SocketAddr>,
     addr_to_id: HashMap<SocketAddr, u32>,
     next_id: u32,
+    map: Arc<Vec<MapObject>>,
+    tick: u64,
 }
 
 impl Game {
-    fn new() -> Self {
+    fn new(map: Arc<Vec<MapObject>>) -> Self {
         Self {
             ball: Ball {
                 x: 400.0,
@@ -119,11 +156,94 @@ impl Game {
             clients: HashMap::new(),
             addr_to_id: HashMap::new(),
             next_id: 1,
+            map,
+            tick: 0,
+        }
+    }
+}
+
+// --- Fixed-Rate Tick Scheduling ---
+// The simulation runs at TICK_RATE ticks per second and sends snapshots at SNAPSHOT_RATE,
+// both defaulting to 30. Elapsed time is accumulated and consumed one tick at a time, so
+// the game runs in real time even when a tick is delayed by lock contention.
+//
+// This is a copy of ublike's TickScheduler (ublike/tick.rs, where it is tested). ublike builds
+// only a binary, so this server can't depend on it; keep the two in step.
+const DEFAULT_TICK_RATE: u32 = 30;
+const MAX_CATCH_UP_TICKS: u32 = 5;
+
+// Tick length the movement and shot constants were tuned for.
+const TUNING_DT: f32 = 0.1;
+
+fn rate_from_env(name: &str) -> Option<u32> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .filter(|rate| *rate > 0)
+}
+
+struct TickScheduler {
+    tick_duration: StdDuration,
+    tick_rate: u32,
+    snapshot_rate: u32,
+    accumulator: StdDuration,
+    last_time: Instant,
+    snapshot_accumulator: u32,
+}
+
+impl TickScheduler {
+    fn new(tick_rate: u32, snapshot_rate: u32) -> Self {
+        Self {
+            tick_duration: StdDuration::from_secs(1) / tick_rate,
+            tick_rate,
+            snapshot_rate: snapshot_rate.min(tick_rate),
+            accumulator: StdDuration::ZERO,
+            last_time: Instant::now(),
+            snapshot_accumulator: 0,
+        }
+    }
+
+    // Wait until the next tick is due; returns immediately while catching up.
+    async fn next_tick(&mut self) {
+        while !self.advance(Instant::now()) {
+            sleep(self.tick_duration - self.accumulator).await;
+        }
+    }
+
+    // Add the time since the last call to the backlog and take one tick from it if one is due.
+    fn advance(&mut self, now: Instant) -> bool {
+        self.accumulator += now - self.last_time;
+        self.last_time = now;
+
+        let max_backlog = self.tick_duration * MAX_CATCH_UP_TICKS;
+        if self.accumulator > max_backlog {
+            self.accumulator = max_backlog;
+        }
+        if self.accumulator >= self.tick_duration {
+            self.accumulator -= self.tick_duration;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Whether the tick that just ran should be followed by a snapshot.
+    fn snapshot_due(&mut self) -> bool {
+        self.snapshot_accumulator += self.snapshot_rate;
+        if self.snapshot_accumulator >= self.tick_rate {
+            self.snapshot_accumulator -= self.tick_rate;
+            true
+        } else {
+            false
         }
     }
 }
 
-static GLOBAL_GAME: Lazy<Arc<Mutex<Game>>> = Lazy::new(|| Arc::new(Mutex::new(Game::new())));
+static GLOBAL_GAME: Lazy<Arc<Mutex<Game>>> = Lazy::new(|| {
+    let path = map_path_from_env();
+    let map = load_map(&path).unwrap_or_else(|e| panic!("{}", e));
+    Arc::new(Mutex::new(Game::new(Arc::new(map))))
+});
 
 #[derive(Debug, Deserialize)]
 struct InputMessage {
@@ -140,11 +260,6 @@ struct InputMessage {
 
 // --- Collision Helper Functions and Constants ---
 
-// AABB collision check (used for players, etc.)
-fn aabb_collision(x1: f32, y1: f32, w1: f32, h1: f32, x2: f32, y2: f32, w2: f32, h2: f32) -> bool {
-    x1 < x2 + w2 && x1 + w1 > x2 && y1 < y2 + h2 && y1 + h1 > y2
-}
-
 const SHIP_WIDTH: f32 = 40.0;
 const SHIP_HEIGHT: f32 = 40.0;
 
@@ -254,7 +369,7 @@ fn resolve_ship_collision(ship: &mut Ship, velocity: &mut (f32, f32), wall: &Map
 
 #[tokio::main]
 async fn main() {
-    println!("Loaded map objects: {:?}", *MAP_OBJECTS);
+    println!("Loaded map objects: {:?}", GLOBAL_GAME.lock().await.map);
     let socket = Arc::new(
         UdpSocket::bind(("0.0.0.0", 8080))
             .await
@@ -273,15 +388,24 @@ async fn main() {
 }
 
 async fn game_update_loop(socket: Arc<UdpSocket>) {
-    let fixed_dt = 0.1;
+    let tick_rate = rate_from_env("TICK_RATE").unwrap_or(DEFAULT_TICK_RATE);
+    let snapshot_rate = rate_from_env("SNAPSHOT_RATE").unwrap_or(tick_rate);
+    let mut scheduler = TickScheduler::new(tick_rate, snapshot_rate);
+    println!("Tick rate: {} Hz, snapshot rate: {} Hz", tick_rate, scheduler.snapshot_rate);
+    let fixed_dt = 1.0 / tick_rate as f32;
     let sub_steps = 5;
     let sub_dt = fixed_dt / sub_steps as f32;
     let game_width = 2000.0;
     let game_height = 1200.0;
     let player_timeout = StdDuration::from_secs(10);
     loop {
+        scheduler.next_tick().await;
+        let send_snapshot = scheduler.snapshot_due();
+
         let (snapshot_bytes, recipients) = {
             let mut game = GLOBAL_GAME.lock().await;
+            let map = game.map.clone();
+            game.tick += 1;
 
             // Update cooldowns.
             if game.ball.grab_cooldown > 0.0 {
@@ -353,7 +477,7 @@ async fn game_update_loop(socket: Arc<UdpSocket>) {
 
                 player.velocity.0 += ax * fixed_dt;
                 player.velocity.1 += ay * fixed_dt;
-                let friction = 0.8;
+                let friction = 0.8f32.powf(fixed_dt / TUNING_DT);
                 player.velocity.0 *= friction;
                 player.velocity.1 *= friction;
                 let speed = (player.velocity.0.powi(2) + player.velocity.1.powi(2)).sqrt();
@@ -371,7 +495,7 @@ async fn game_update_loop(socket: Arc<UdpSocket>) {
             // --- Ship-Wall Collision Resolution ---
             // For each player ship, resolve collisions against every wall.
             for player in game.players.values_mut() {
-                for wall in MAP_OBJECTS.iter().filter(|w| w.obj_type == "wall") {
+                for wall in map.iter().filter(|w| w.obj_type == "wall") {
                     resolve_ship_collision(&mut player.ship, &mut player.velocity, wall);
                 }
             }
@@ -447,7 +571,7 @@ async fn game_update_loop(socket: Arc<UdpSocket>) {
                     if game.ball.active && !game.ball.grabbed {
                         game.ball.x += game.ball.vx * sub_dt;
                         game.ball.y += game.ball.vy * sub_dt;
-                        let friction = 0.989;
+                        let friction = 0.989f32.powf(sub_dt / (TUNING_DT / 5.0));
                         game.ball.vx *= friction;
                         game.ball.vy *= friction;
 
@@ -475,7 +599,7 @@ async fn game_update_loop(socket: Arc<UdpSocket>) {
                     let max_iterations = 5;
                     loop {
                         let mut collision_occurred = false;
-                        for wall in MAP_OBJECTS.iter().filter(|w| w.obj_type == "wall") {
+                        for wall in map.iter().filter(|w| w.obj_type == "wall") {
                             let ball_left = game.ball.x - BALL_WIDTH / 2.0;
                             let ball_right = game.ball.x + BALL_WIDTH / 2.0;
                             let ball_top = game.ball.y - BALL_HEIGHT / 2.0;
@@ -574,7 +698,7 @@ async fn game_update_loop(socket: Arc<UdpSocket>) {
                             let ship_mass = 1.0;
                             let ball_mass = 0.5;
                             let base_shot_force = 1400.0;
-                            let dt = fixed_dt;
+                            let dt = TUNING_DT;
 
                             let aim_norm = (dx / mag, dy / mag);
                             let impulse_base = (
@@ -645,6 +769,7 @@ async fn game_update_loop(socket: Arc<UdpSocket>) {
                 );
             }
             let snapshot = json!(GameStateSnapshot {
+                tick: game.tick,
                 time: now,
                 players: players_snapshot,
                 ball: game.ball.clone(),
@@ -654,13 +779,13 @@ async fn game_update_loop(socket: Arc<UdpSocket>) {
             (snapshot_bytes, recipients)
         };
 
-        for addr in recipients {
-            if let Err(e) = socket.send_to(&snapshot_bytes, addr).await {
-                eprintln!("Failed to send snapshot to {}: {:?}", addr, e);
+        if send_snapshot {
+            for addr in recipients {
+                if let Err(e) = socket.send_to(&snapshot_bytes, addr).await {
+                    eprintln!("Failed to send snapshot to {}: {:?}", addr, e);
+                }
             }
         }
-
-        sleep(Duration::from_millis((fixed_dt * 700.0) as u64)).await;
     }
 }
 
@@ -0,0 +1,369 @@
+// This module keeps player accounts and their skill ratings.
+//
+// Accounts live in a JSON file (ACCOUNTS_FILE, default `accounts.json`) that is loaded at startup
+// and rewritten in the background (see persist.rs) whenever an account is created or rated, so
+// ratings survive restarts. A client
+// logs in on `/lobby` with `{"type": "login", "name": "..."}` to create an account or
+// `{"type": "login", "token": "..."}` to resume one, and gets its token back; passing the token
+// as `?token=...` on a game socket plays the connection under that account.
+//
+// Ratings are Elo: when a match ends, every team with rated players is scored against every
+// other such team (win 1, draw 0.5, loss 0) using the teams' average ratings, and each player
+// moves by their K factor times the team's result against expectation. Everyone logged in when
+// the match kicked off is rated, and a player who left before the end takes a loss. New accounts
+// use a larger K factor for their first few games so they settle quickly. Bots and anonymous
+// players are never rated and don't count as opponents. An account can only play once in a game.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use crate::chat::CHAT_FILTER;
+use crate::persist::JsonFile;
+use crate::sim::player::Team;
+
+const DEFAULT_ACCOUNTS_FILE: &str = "accounts.json";
+
+pub const DEFAULT_RATING: f64 = 1500.0;
+
+// Games played before an account's rating counts as established
+const PROVISIONAL_GAMES: u32 = 10;
+const PROVISIONAL_K: f64 = 40.0;
+const ESTABLISHED_K: f64 = 20.0;
+
+const MIN_NAME_LEN: usize = 3;
+const MAX_NAME_LEN: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub id: String,
+    pub name: String,
+    pub token: String, // Secret the client logs in with
+    pub rating: f64,
+    pub games_played: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub created_at: u64, // ms since the epoch
+}
+
+impl Account {
+    fn k_factor(&self) -> f64 {
+        if self.games_played < PROVISIONAL_GAMES { PROVISIONAL_K } else { ESTABLISHED_K }
+    }
+}
+
+// What other clients may see about an account (everything but the token)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountInfo {
+    pub id: String,
+    pub name: String,
+    pub rating: i32,
+    pub games_played: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub provisional: bool,
+}
+
+impl From<&Account> for AccountInfo {
+    fn from(account: &Account) -> Self {
+        Self {
+            id: account.id.clone(),
+            name: account.name.clone(),
+            rating: account.rating.round() as i32,
+            games_played: account.games_played,
+            wins: account.wins,
+            losses: account.losses,
+            draws: account.draws,
+            provisional: account.games_played < PROVISIONAL_GAMES,
+        }
+    }
+}
+
+// A logged-in player in a finished match
+#[derive(Debug, Clone)]
+pub struct MatchParticipant {
+    pub account_id: String,
+    pub team: Team,
+    pub left: bool, // Left before the end, which counts as a loss
+}
+
+pub struct AccountStore {
+    file: JsonFile,
+    accounts: HashMap<String, Account>, // By account id
+    tokens: HashMap<String, String>,    // Token -> account id
+}
+
+impl AccountStore {
+    // Load the accounts saved at `path`; a missing file starts an empty store
+    pub fn load(path: PathBuf) -> Self {
+        let accounts: Vec<Account> = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                println!("Failed to parse {} ({}), starting with no accounts", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        println!("Loaded {} accounts from {}", accounts.len(), path.display());
+
+        let tokens = accounts.iter().map(|account| (account.token.clone(), account.id.clone())).collect();
+        let accounts = accounts.into_iter().map(|account| (account.id.clone(), account)).collect();
+        Self { file: JsonFile::new(path), accounts, tokens }
+    }
+
+    // Write every account back to the file
+    fn save(&mut self) {
+        let mut accounts: Vec<&Account> = self.accounts.values().collect();
+        accounts.sort_by_key(|account| account.created_at);
+        let json = match serde_json::to_string_pretty(&accounts) {
+            Ok(json) => json,
+            Err(e) => {
+                println!("Failed to serialize accounts: {}", e);
+                return;
+            }
+        };
+        self.file.save(json);
+    }
+
+    // Create an account with the given name and a fresh token
+    pub fn register(&mut self, name: &str) -> Result<&Account, String> {
+        let name = name.trim();
+        if name.len() < MIN_NAME_LEN || name.len() > MAX_NAME_LEN {
+            return Err(format!("Names must be {} to {} characters long", MIN_NAME_LEN, MAX_NAME_LEN));
+        }
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err("Names may only contain letters, digits, '_' and '-'".to_string());
+        }
+        if CHAT_FILTER.filter(name) != name {
+            return Err("That name isn't allowed".to_string());
+        }
+        if self.accounts.values().any(|account| account.name.eq_ignore_ascii_case(name)) {
+            return Err(format!("The name {} is taken", name));
+        }
+
+        let account = Account {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            name: name.to_string(),
+            token: uuid::Uuid::new_v4().simple().to_string(),
+            rating: DEFAULT_RATING,
+            games_played: 0,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+            created_at: chrono::Utc::now().timestamp_millis() as u64,
+        };
+        println!("Created account {} ({})", account.name, account.id);
+
+        let id = account.id.clone();
+        self.tokens.insert(account.token.clone(), id.clone());
+        self.accounts.insert(id.clone(), account);
+        self.save();
+        Ok(&self.accounts[&id])
+    }
+
+    pub fn authenticate(&self, token: &str) -> Option<&Account> {
+        self.tokens.get(token).and_then(|id| self.accounts.get(id))
+    }
+
+    pub fn info(&self, account_id: &str) -> Option<AccountInfo> {
+        self.accounts.get(account_id).map(AccountInfo::from)
+    }
+
+    // Highest rated accounts that have played at least one game
+    pub fn leaderboard(&self, limit: usize) -> Vec<AccountInfo> {
+        let mut accounts: Vec<&Account> = self.accounts.values()
+            .filter(|account| account.games_played > 0)
+            .collect();
+        accounts.sort_by(|a, b| b.rating.total_cmp(&a.rating).then_with(|| a.name.cmp(&b.name)));
+        accounts.into_iter().take(limit).map(AccountInfo::from).collect()
+    }
+
+    // Rate a finished match and save the new ratings
+    pub fn record_match(&mut self, participants: &[MatchParticipant], winners: &[Team], draw: bool) {
+        if self.rate_match(participants, winners, draw) {
+            self.save();
+        }
+    }
+
+    // Update the ratings and records of a finished match's players, returning whether anyone was
+    // rated. `winners` are the teams sharing the top score (a draw if there's more than one).
+    // Players who left lose to every other team.
+    fn rate_match(&mut self, participants: &[MatchParticipant], winners: &[Team], draw: bool) -> bool {
+        let rated: Vec<&MatchParticipant> = participants.iter()
+            .filter(|participant| self.accounts.contains_key(&participant.account_id))
+            .collect();
+        let mut teams: Vec<(Team, Vec<f64>)> = Vec::new();
+        for participant in &rated {
+            let rating = self.accounts[&participant.account_id].rating;
+            match teams.iter_mut().find(|(team, _)| *team == participant.team) {
+                Some((_, ratings)) => ratings.push(rating),
+                None => teams.push((participant.team, vec![rating])),
+            }
+        }
+        if teams.len() < 2 {
+            return false;
+        }
+
+        let averages: Vec<(Team, f64)> = teams.iter()
+            .map(|(team, ratings)| (*team, ratings.iter().sum::<f64>() / ratings.len() as f64))
+            .collect();
+
+        // Each player's result against expectation, averaged over their team's opponents
+        let mut changes: Vec<(String, f64, MatchOutcome)> = Vec::new();
+        for participant in rated {
+            let average = averages.iter()
+                .find(|(team, _)| *team == participant.team)
+                .map_or(DEFAULT_RATING, |(_, average)| *average);
+            let won = !participant.left && winners.contains(&participant.team);
+            let mut surprise = 0.0;
+            for (other, other_average) in averages.iter().filter(|(team, _)| *team != participant.team) {
+                let actual = match (won, winners.contains(other)) {
+                    _ if participant.left => 0.0,
+                    (true, false) => 1.0,
+                    (false, true) => 0.0,
+                    _ => 0.5,
+                };
+                surprise += actual - expected_score(average, *other_average);
+            }
+            surprise /= (averages.len() - 1) as f64;
+
+            let outcome = match (won, draw) {
+                (true, false) => MatchOutcome::Win,
+                (true, true) => MatchOutcome::Draw,
+                (false, _) => MatchOutcome::Loss,
+            };
+            changes.push((participant.account_id.clone(), surprise, outcome));
+        }
+
+        for (id, surprise, outcome) in changes {
+            if let Some(account) = self.accounts.get_mut(&id) {
+                let before = account.rating;
+                account.rating += account.k_factor() * surprise;
+                account.games_played += 1;
+                match outcome {
+                    MatchOutcome::Win => account.wins += 1,
+                    MatchOutcome::Draw => account.draws += 1,
+                    MatchOutcome::Loss => account.losses += 1,
+                }
+                println!("Rating for {}: {:.0} -> {:.0} ({:?})", account.name, before, account.rating, outcome);
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum MatchOutcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+// Chance (0..1) that a side rated `rating` beats one rated `opponent`
+fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+// Global account store, loaded from ACCOUNTS_FILE on first use
+pub static ACCOUNTS: Lazy<Arc<Mutex<AccountStore>>> = Lazy::new(|| {
+    let path = std::env::var("ACCOUNTS_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_ACCOUNTS_FILE));
+    Arc::new(Mutex::new(AccountStore::load(path)))
+});
+
+// The account a game connection's `?token=` belongs to, if any
+pub async fn account_for_token(token: Option<&String>) -> Option<AccountInfo> {
+    let token = token?;
+    ACCOUNTS.lock().await.authenticate(token).map(AccountInfo::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A store that is never saved, holding accounts with the given ratings and games played
+    fn store(accounts: &[(&str, f64, u32)]) -> AccountStore {
+        let accounts = accounts.iter()
+            .map(|(id, rating, games_played)| (id.to_string(), Account {
+                id: id.to_string(),
+                name: id.to_string(),
+                token: format!("token-{}", id),
+                rating: *rating,
+                games_played: *games_played,
+                wins: 0,
+                losses: 0,
+                draws: 0,
+                created_at: 0,
+            }))
+            .collect();
+        AccountStore { file: JsonFile::new(PathBuf::new()), accounts, tokens: HashMap::new() }
+    }
+
+    fn player(account_id: &str, team: Team) -> MatchParticipant {
+        MatchParticipant { account_id: account_id.to_string(), team, left: false }
+    }
+
+    fn rating(store: &AccountStore, id: &str) -> f64 {
+        store.accounts[id].rating
+    }
+
+    #[test]
+    fn even_match_moves_winner_and_loser_by_half_k() {
+        let mut store = store(&[("red", 1500.0, 0), ("blue", 1500.0, 0)]);
+        assert!(store.rate_match(&[player("red", Team::Red), player("blue", Team::Blue)], &[Team::Red], false));
+
+        assert_eq!(rating(&store, "red"), 1500.0 + PROVISIONAL_K / 2.0);
+        assert_eq!(rating(&store, "blue"), 1500.0 - PROVISIONAL_K / 2.0);
+        assert_eq!((store.accounts["red"].wins, store.accounts["blue"].losses), (1, 1));
+        assert_eq!(store.accounts["red"].games_played, 1);
+    }
+
+    #[test]
+    fn draw_moves_the_stronger_side_down() {
+        let mut store = store(&[("strong", 1700.0, 0), ("weak", 1300.0, 0)]);
+        let players = [player("strong", Team::Red), player("weak", Team::Blue)];
+        store.rate_match(&players, &[Team::Red, Team::Blue], true);
+
+        let expected = expected_score(1700.0, 1300.0);
+        assert!((rating(&store, "strong") - (1700.0 + PROVISIONAL_K * (0.5 - expected))).abs() < 1e-9);
+        assert!((rating(&store, "weak") - (1300.0 - PROVISIONAL_K * (0.5 - expected))).abs() < 1e-9);
+        assert_eq!((store.accounts["strong"].draws, store.accounts["weak"].draws), (1, 1));
+    }
+
+    #[test]
+    fn established_accounts_move_by_the_smaller_k() {
+        let mut store = store(&[("new", 1500.0, PROVISIONAL_GAMES - 1), ("old", 1500.0, PROVISIONAL_GAMES)]);
+        store.rate_match(&[player("new", Team::Red), player("old", Team::Blue)], &[Team::Red], false);
+
+        assert_eq!(rating(&store, "new"), 1500.0 + PROVISIONAL_K / 2.0);
+        assert_eq!(rating(&store, "old"), 1500.0 - ESTABLISHED_K / 2.0);
+    }
+
+    #[test]
+    fn leaver_takes_a_loss_even_when_their_team_wins() {
+        let mut store = store(&[("stayed", 1500.0, 0), ("left", 1500.0, 0), ("blue", 1500.0, 0)]);
+        let mut leaver = player("left", Team::Red);
+        leaver.left = true;
+        store.rate_match(&[player("stayed", Team::Red), leaver, player("blue", Team::Blue)], &[Team::Red], false);
+
+        assert_eq!(store.accounts["stayed"].wins, 1);
+        assert_eq!(store.accounts["left"].losses, 1);
+        assert_eq!(rating(&store, "left"), 1500.0 - PROVISIONAL_K / 2.0);
+        assert_eq!(store.accounts["blue"].losses, 1);
+    }
+
+    #[test]
+    fn fewer_than_two_rated_sides_rates_no_one() {
+        let mut store = store(&[("red1", 1500.0, 0), ("red2", 1500.0, 0)]);
+        // Teammates alone, and an opponent with no account, make only one rated side
+        let players = [player("red1", Team::Red), player("red2", Team::Red), player("guest", Team::Blue)];
+        assert!(!store.rate_match(&players, &[Team::Red], false));
+
+        assert_eq!(rating(&store, "red1"), 1500.0);
+        assert_eq!(store.accounts["red2"].games_played, 0);
+    }
+}
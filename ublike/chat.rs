@@ -0,0 +1,286 @@
+// This module handles text chat: team and all chat in games, and the global chat on `/lobby`.
+//
+// In a game, a player sends `{"type": "chat", "channel": "team" | "all", "text": "..."}` or a
+// quick-chat preset with `{"type": "quick_chat", "channel": ..., "preset": "pass"}`, and the
+// message goes out on the reliable channel as a `chat` event to their team or to everyone
+// (spectators can only chat to everyone). Lobby clients send `{"type": "chat", "text": "..."}`
+// and every lobby client gets a `chat_message`.
+//
+// Every message is checked the same way: muted senders are refused (see moderation.rs), text is
+// limited to MAX_CHAT_CHARS, each sender gets a burst of CHAT_BURST messages refilled at
+// CHAT_RATE_PER_SEC, and free text and player names go through the chat filter. The filter is
+// anything implementing `ChatFilter`; the built-in one masks blocked words, from a short default
+// list or the file named by CHAT_WORDLIST (one word per line), and CHAT_FILTER=off turns
+// filtering off.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::Mutex;
+use crate::dual_connection::{DualConnectionManager, MessageType};
+use crate::game::{now_ms, Game};
+use crate::moderation::MODERATION;
+
+pub const MAX_CHAT_CHARS: usize = 200;
+
+// Messages a sender can fire off at once, and how fast they come back
+const CHAT_BURST: f64 = 4.0;
+const CHAT_RATE_PER_SEC: f64 = 0.5;
+
+// Quick-chat presets by id, sent without typing (and so not filtered)
+const QUICK_CHAT: &[(&str, &str)] = &[
+    ("pass", "Pass!"),
+    ("defend", "Defend!"),
+    ("shoot", "Shoot!"),
+    ("on_my_way", "On my way!"),
+    ("nice_shot", "Nice shot!"),
+    ("thanks", "Thanks!"),
+    ("sorry", "Sorry!"),
+];
+
+const DEFAULT_BLOCKED_WORDS: &[&str] = &["fuck", "fucking", "shit", "bitch", "cunt", "asshole", "bastard", "dick"];
+
+// Something that cleans up chat text before it's delivered
+pub trait ChatFilter: Send + Sync {
+    fn filter(&self, text: &str) -> String;
+}
+
+// Lets every message through unchanged
+pub struct NoFilter;
+
+impl ChatFilter for NoFilter {
+    fn filter(&self, text: &str) -> String {
+        text.to_string()
+    }
+}
+
+// Masks whole words on a block list with asterisks, ignoring case
+pub struct WordListFilter {
+    words: HashSet<String>,
+}
+
+impl WordListFilter {
+    pub fn new<I: IntoIterator<Item = String>>(words: I) -> Self {
+        Self {
+            words: words.into_iter().map(|word| word.trim().to_lowercase()).filter(|word| !word.is_empty()).collect(),
+        }
+    }
+}
+
+impl ChatFilter for WordListFilter {
+    fn filter(&self, text: &str) -> String {
+        let mut filtered = String::with_capacity(text.len());
+        let mut word = String::new();
+        let flush = |word: &mut String, filtered: &mut String| {
+            if self.words.contains(&word.to_lowercase()) {
+                filtered.extend(word.chars().map(|_| '*'));
+            } else {
+                filtered.push_str(word);
+            }
+            word.clear();
+        };
+        for c in text.chars() {
+            if c.is_alphanumeric() {
+                word.push(c);
+            } else {
+                flush(&mut word, &mut filtered);
+                filtered.push(c);
+            }
+        }
+        flush(&mut word, &mut filtered);
+        filtered
+    }
+}
+
+// The filter picked by CHAT_FILTER and CHAT_WORDLIST
+pub static CHAT_FILTER: Lazy<Box<dyn ChatFilter>> = Lazy::new(|| {
+    if std::env::var("CHAT_FILTER").is_ok_and(|value| value == "off") {
+        println!("Chat filter disabled");
+        return Box::new(NoFilter);
+    }
+    let words: Vec<String> = match std::env::var("CHAT_WORDLIST") {
+        Ok(path) => match std::fs::read_to_string(&path) {
+            Ok(text) => text.lines().map(str::to_string).collect(),
+            Err(e) => {
+                println!("Failed to read chat word list {} ({}), using the default list", path, e);
+                DEFAULT_BLOCKED_WORDS.iter().map(|word| word.to_string()).collect()
+            }
+        },
+        Err(_) => DEFAULT_BLOCKED_WORDS.iter().map(|word| word.to_string()).collect(),
+    };
+    println!("Chat filter blocking {} words", words.len());
+    Box::new(WordListFilter::new(words))
+});
+
+// Token bucket limiting how often one sender can chat
+#[derive(Debug, Clone)]
+pub struct ChatLimiter {
+    tokens: f64,
+    last_refill: u64, // Server time in ms
+}
+
+impl ChatLimiter {
+    pub fn new() -> Self {
+        Self { tokens: CHAT_BURST, last_refill: now_ms() }
+    }
+
+    // Spend a message, or refuse if the sender is out of them
+    pub fn take(&mut self, now_ms: u64) -> Result<(), String> {
+        let elapsed = now_ms.saturating_sub(self.last_refill) as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * CHAT_RATE_PER_SEC).min(CHAT_BURST);
+        self.last_refill = now_ms;
+        if self.tokens < 1.0 {
+            return Err("You're sending messages too fast".to_string());
+        }
+        self.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+// Check a typed message's length and filter it. Control characters are dropped.
+pub fn clean_text(text: &str) -> Result<String, String> {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Messages can't be empty".to_string());
+    }
+    if text.chars().count() > MAX_CHAT_CHARS {
+        return Err(format!("Messages can be at most {} characters", MAX_CHAT_CHARS));
+    }
+    Ok(CHAT_FILTER.filter(text))
+}
+
+// Filter a display name like chat text, since it goes out with every message the player sends
+pub fn clean_name(name: &str) -> String {
+    let name: String = name.chars().filter(|c| !c.is_control()).collect();
+    CHAT_FILTER.filter(name.trim())
+}
+
+// The presets as sent to clients in `init`
+pub fn quick_chat_presets() -> serde_json::Value {
+    QUICK_CHAT.iter().map(|(id, text)| json!({ "id": id, "text": text })).collect()
+}
+
+fn quick_chat_text(preset: &str) -> Result<&'static str, String> {
+    QUICK_CHAT.iter()
+        .find(|(id, _)| *id == preset)
+        .map(|(_, text)| *text)
+        .ok_or_else(|| format!("Unknown quick chat '{}'", preset))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatChannel {
+    #[default]
+    All,
+    Team,
+}
+
+// Chat sent by a player or spectator in a game
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatRequest {
+    Chat {
+        #[serde(default)]
+        channel: ChatChannel,
+        text: String,
+    },
+    QuickChat {
+        #[serde(default)]
+        channel: ChatChannel,
+        preset: String,
+    },
+}
+
+// Deliver a chat message from a player or spectator in a game. The error is sent back to the sender.
+pub async fn handle_game_chat(client_id: u32, request: ChatRequest, limiter: &mut ChatLimiter, game: &Arc<Mutex<Game>>, dual_mgr: &DualConnectionManager) -> Result<(), String> {
+    let identity = dual_mgr.identity(client_id).await.unwrap_or_default();
+    if let Some(mute) = MODERATION.lock().await.mute_for(&identity, Some(&dual_mgr.game_id)) {
+        return Err(mute.describe("You are muted"));
+    }
+
+    let (channel, text, quick) = match request {
+        ChatRequest::Chat { channel, text } => (channel, clean_text(&text)?, false),
+        ChatRequest::QuickChat { channel, preset } => (channel, quick_chat_text(&preset)?.to_string(), true),
+    };
+    limiter.take(now_ms())?;
+
+    let (name, team, teammates) = {
+        let game_lock = game.lock().await;
+        match game_lock.players.get(&client_id) {
+            Some(player) => {
+                let teammates: Vec<u32> = game_lock.players.values()
+                    .filter(|other| other.team == player.team && !game_lock.is_bot(other.id))
+                    .map(|other| other.id)
+                    .collect();
+                (player.display_name.clone(), Some(player.team), teammates)
+            }
+            None if channel == ChatChannel::Team => return Err("Spectators can only chat to everyone".to_string()),
+            None => (format!("Spectator_{}", client_id), None, Vec::new()),
+        }
+    };
+
+    let message = json!({
+        "type": "chat",
+        "channel": channel,
+        "from": client_id,
+        "name": name,
+        "team": team,
+        "text": text,
+        "quick": quick
+    });
+    match channel {
+        ChatChannel::All => dual_mgr.broadcast_message(MessageType::Chat, message, None).await,
+        ChatChannel::Team => {
+            for teammate in teammates {
+                let _ = dual_mgr.send_to_client(teammate, MessageType::Chat, message.clone()).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limiter_allows_a_burst_then_refills() {
+        let mut limiter = ChatLimiter { tokens: CHAT_BURST, last_refill: 0 };
+        for _ in 0..CHAT_BURST as usize {
+            assert!(limiter.take(0).is_ok());
+        }
+        assert!(limiter.take(0).is_err());
+
+        // One message comes back every 1 / CHAT_RATE_PER_SEC seconds
+        let refill_ms = (1000.0 / CHAT_RATE_PER_SEC) as u64;
+        assert!(limiter.take(refill_ms - 100).is_err());
+        assert!(limiter.take(refill_ms + 100).is_ok());
+        assert!(limiter.take(refill_ms + 100).is_err());
+
+        // A long silence only refills up to the burst
+        let later = refill_ms * 100;
+        for _ in 0..CHAT_BURST as usize {
+            assert!(limiter.take(later).is_ok());
+        }
+        assert!(limiter.take(later).is_err());
+    }
+
+    #[test]
+    fn word_filter_masks_whole_words_ignoring_case() {
+        let filter = WordListFilter::new(vec!["darn".to_string(), " Heck ".to_string(), String::new()]);
+        assert_eq!(filter.filter("Darn it, HECK!"), "**** it, ****!");
+        assert_eq!(filter.filter("darned heckler"), "darned heckler");
+        assert_eq!(filter.filter(""), "");
+    }
+
+    #[test]
+    fn typed_text_is_trimmed_and_length_checked() {
+        assert_eq!(clean_text("  hello\u{7}  ").unwrap(), "hello");
+        assert!(clean_text(" \n\t ").is_err());
+        assert!(clean_text(&"a".repeat(MAX_CHAT_CHARS)).is_ok());
+        assert!(clean_text(&"a".repeat(MAX_CHAT_CHARS + 1)).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+// This module tracks which snapshots each client has acknowledged so the server can send
+// only what changed since then instead of the full world state every tick.
+//
+// Every snapshot carries a game-wide `snapshot_id`. Clients ack the snapshots they receive
+// with `{"type": "snapshot_ack", "snapshot_id": N}` (or a binary ack frame, see protocol.rs).
+// Each snapshot is sent as a delta against the newest acked snapshot that is still in the
+// client's history. A full keyframe is sent instead when the client hasn't acked anything yet,
+// when its acked baseline has aged out of the history (acks went missing), and at least every
+// KEYFRAME_INTERVAL snapshots. Clients that never ack simply keep receiving full snapshots.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use serde_json::{json, Map, Value};
+use crate::game::GameStateSnapshot;
+
+// Snapshots remembered per client as possible baselines (about half a second of updates)
+const HISTORY_LEN: usize = 32;
+
+// Maximum number of snapshots between two keyframes
+const KEYFRAME_INTERVAL: u32 = 120;
+
+pub struct SnapshotHistory {
+    sent: VecDeque<Arc<GameStateSnapshot>>,
+    acked_id: Option<u32>,
+    last_keyframe_id: Option<u32>,
+}
+
+impl SnapshotHistory {
+    pub fn new() -> Self {
+        Self {
+            sent: VecDeque::with_capacity(HISTORY_LEN),
+            acked_id: None,
+            last_keyframe_id: None,
+        }
+    }
+
+    // Forget every baseline, e.g. when the client switches encodings
+    pub fn clear(&mut self) {
+        self.sent.clear();
+        self.acked_id = None;
+        self.last_keyframe_id = None;
+    }
+
+    // Record a client ack. Acks for snapshots older than the current baseline or no longer
+    // in the history are ignored.
+    pub fn ack(&mut self, snapshot_id: u32) -> bool {
+        if self.acked_id.is_some_and(|acked| snapshot_id <= acked) {
+            return false;
+        }
+        if !self.sent.iter().any(|snapshot| snapshot.snapshot_id == snapshot_id) {
+            return false;
+        }
+        self.acked_id = Some(snapshot_id);
+        true
+    }
+
+    // Pick the baseline to encode `snapshot` against (None means send a keyframe) and
+    // remember the snapshot as sent
+    pub fn next_baseline(&mut self, snapshot: &Arc<GameStateSnapshot>) -> Option<Arc<GameStateSnapshot>> {
+        let keyframe_due = self.last_keyframe_id
+            .is_none_or(|id| snapshot.snapshot_id.wrapping_sub(id) >= KEYFRAME_INTERVAL);
+
+        let baseline = if keyframe_due {
+            None
+        } else {
+            self.acked_id.and_then(|acked| {
+                self.sent.iter().find(|sent| sent.snapshot_id == acked).cloned()
+            })
+        };
+
+        if baseline.is_none() {
+            self.last_keyframe_id = Some(snapshot.snapshot_id);
+        }
+
+        self.sent.push_back(snapshot.clone());
+        while self.sent.len() > HISTORY_LEN {
+            self.sent.pop_front();
+        }
+
+        baseline
+    }
+}
+
+// Fields of `current` whose value differs from `baseline` (every field if there is no baseline)
+fn changed_fields(baseline: Option<&Value>, current: &Value) -> Map<String, Value> {
+    let current = match current.as_object() {
+        Some(current) => current,
+        None => return Map::new(),
+    };
+    let baseline = baseline.and_then(|base| base.as_object());
+
+    current.iter()
+        .filter(|(key, value)| baseline.and_then(|base| base.get(*key)) != Some(*value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+// Build a JSON delta of `snapshot` against `baseline`. Players and projectiles are keyed by id
+// and only carry the fields that changed; new entities carry all of their fields.
+pub fn json_delta(baseline: &GameStateSnapshot, snapshot: &GameStateSnapshot) -> Value {
+    let mut delta = json!({
+        "type": "game_state_delta",
+        "tick": snapshot.tick,
+        "snapshot_id": snapshot.snapshot_id,
+        "baseline_id": baseline.snapshot_id,
+        "time": snapshot.time
+    });
+
+    // Players
+    let mut players = Map::new();
+    for (id, state) in &snapshot.players {
+        let current = serde_json::to_value(state).unwrap_or(Value::Null);
+        let base = baseline.players.get(id).and_then(|base| serde_json::to_value(base).ok());
+        let changed = changed_fields(base.as_ref(), &current);
+        if !changed.is_empty() {
+            players.insert(id.to_string(), Value::Object(changed));
+        }
+    }
+    let removed_players: Vec<u32> = baseline.players.keys()
+        .filter(|id| !snapshot.players.contains_key(id))
+        .copied()
+        .collect();
+
+    // Ball
+    let ball = changed_fields(
+        serde_json::to_value(&baseline.ball).ok().as_ref(),
+        &serde_json::to_value(&snapshot.ball).unwrap_or(Value::Null),
+    );
+
+    // Projectiles
+    let mut projectiles = Map::new();
+    for projectile in &snapshot.projectiles {
+        let current = serde_json::to_value(projectile).unwrap_or(Value::Null);
+        let base = baseline.projectiles.iter()
+            .find(|base| base.id == projectile.id)
+            .and_then(|base| serde_json::to_value(base).ok());
+        let changed = changed_fields(base.as_ref(), &current);
+        if !changed.is_empty() {
+            projectiles.insert(projectile.id.to_string(), Value::Object(changed));
+        }
+    }
+    let removed_projectiles: Vec<u32> = baseline.projectiles.iter()
+        .filter(|base| !snapshot.projectiles.iter().any(|p| p.id == base.id))
+        .map(|base| base.id)
+        .collect();
+
+    let fields = delta.as_object_mut().expect("delta is an object");
+    if !players.is_empty() {
+        fields.insert("players".to_string(), Value::Object(players));
+    }
+    if !removed_players.is_empty() {
+        fields.insert("removed_players".to_string(), json!(removed_players));
+    }
+    if !ball.is_empty() {
+        fields.insert("ball".to_string(), Value::Object(ball));
+    }
+    if !projectiles.is_empty() {
+        fields.insert("projectiles".to_string(), Value::Object(projectiles));
+    }
+    if !removed_projectiles.is_empty() {
+        fields.insert("removed_projectiles".to_string(), json!(removed_projectiles));
+    }
+
+    // Scores
+    let scores = [
+        ("team1_score", baseline.team1_score, snapshot.team1_score),
+        ("team2_score", baseline.team2_score, snapshot.team2_score),
+        ("team3_score", baseline.team3_score, snapshot.team3_score),
+        ("team4_score", baseline.team4_score, snapshot.team4_score),
+    ];
+    for (key, before, after) in scores {
+        if before != after {
+            fields.insert(key.to_string(), json!(after));
+        }
+    }
+
+    // Match phase and clock
+    if baseline.match_state != snapshot.match_state {
+        fields.insert("match_state".to_string(), json!(snapshot.match_state));
+    }
+
+    delta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::sim::ball::Ball;
+    use crate::sim::game::Projectile;
+    use crate::sim::match_state::MatchState;
+    use crate::sim::player::{ShipState, Team};
+
+    fn ship(x: f32) -> ShipState {
+        ShipState { x, y: 100.0, seq: 1, fuel: 100.0, team: Team::Red, display_name: String::new(), rocket_cooldown: 0.0 }
+    }
+
+    fn snapshot(snapshot_id: u32) -> Arc<GameStateSnapshot> {
+        Arc::new(GameStateSnapshot {
+            tick: snapshot_id as u64,
+            snapshot_id,
+            time: snapshot_id as u64 * 16,
+            players: HashMap::new(),
+            ball: Ball {
+                x: 1000.0,
+                y: 600.0,
+                vx: 0.0,
+                vy: 0.0,
+                active: true,
+                grabbed: false,
+                grab_cooldown: 0.0,
+                owner: None,
+                last_shooter: None,
+                shot_clock: 0.0,
+                pickup_cooldown: 0.0,
+                exclusive_team: None,
+            },
+            projectiles: Vec::new(),
+            team1_score: 0,
+            team2_score: 0,
+            team3_score: 0,
+            team4_score: 0,
+            match_state: MatchState::default(),
+        })
+    }
+
+    // Send snapshots `from..=to` through the history, returning the baseline id picked for each
+    fn send(history: &mut SnapshotHistory, from: u32, to: u32) -> Vec<Option<u32>> {
+        (from..=to)
+            .map(|id| history.next_baseline(&snapshot(id)).map(|baseline| baseline.snapshot_id))
+            .collect()
+    }
+
+    #[test]
+    fn stale_and_unknown_acks_are_ignored() {
+        let mut history = SnapshotHistory::new();
+        send(&mut history, 1, 5);
+        assert!(!history.ack(9));
+        assert!(history.ack(4));
+        assert!(!history.ack(3));
+        assert!(!history.ack(4));
+        assert_eq!(send(&mut history, 6, 6), [Some(4)]);
+    }
+
+    #[test]
+    fn keyframes_go_out_until_acked_and_every_keyframe_interval() {
+        let mut history = SnapshotHistory::new();
+        assert_eq!(send(&mut history, 1, 3), [None, None, None]);
+        history.ack(3);
+
+        // A client acking every snapshot gets deltas against the previous one until the next
+        // keyframe is due
+        for id in 4..=3 + KEYFRAME_INTERVAL {
+            let expected = if id == 3 + KEYFRAME_INTERVAL { None } else { Some(id - 1) };
+            assert_eq!(send(&mut history, id, id), [expected], "snapshot {}", id);
+            assert!(history.ack(id));
+        }
+        assert_eq!(send(&mut history, 4 + KEYFRAME_INTERVAL, 4 + KEYFRAME_INTERVAL), [Some(3 + KEYFRAME_INTERVAL)]);
+    }
+
+    #[test]
+    fn baseline_that_aged_out_of_the_history_falls_back_to_a_keyframe() {
+        let mut history = SnapshotHistory::new();
+        send(&mut history, 1, 2);
+        history.ack(2);
+        let last = 2 + HISTORY_LEN as u32;
+        let baselines = send(&mut history, 3, last + 1);
+        assert_eq!(baselines[baselines.len() - 2], Some(2));
+        assert_eq!(baselines.last(), Some(&None));
+
+        // Once the client acks something recent, deltas resume against it
+        assert!(history.ack(last + 1));
+        assert_eq!(send(&mut history, last + 2, last + 2), [Some(last + 1)]);
+    }
+
+    #[test]
+    fn json_delta_carries_changes_and_removals() {
+        let mut baseline = snapshot(1);
+        let mut current = snapshot(2);
+        {
+            let baseline = Arc::get_mut(&mut baseline).unwrap();
+            baseline.players.extend([(1, ship(10.0)), (2, ship(20.0)), (3, ship(30.0))]);
+            baseline.projectiles.extend([Projectile::new(7, 0.0, 0.0, 1.0, 0.0, 1), Projectile::new(8, 5.0, 5.0, 1.0, 0.0, 2)]);
+            let current = Arc::get_mut(&mut current).unwrap();
+            current.players.extend([(1, ship(10.0)), (2, ship(25.0)), (4, ship(40.0))]);
+            current.projectiles.push(Projectile::new(8, 5.0, 5.0, 1.0, 0.0, 2));
+            current.team2_score = 1;
+        }
+        let delta = json_delta(&baseline, &current);
+
+        assert_eq!(delta["baseline_id"], 1);
+        assert_eq!(delta["snapshot_id"], 2);
+        let players = delta["players"].as_object().unwrap();
+        assert_eq!(players.keys().collect::<Vec<_>>(), ["2", "4"]);
+        assert_eq!(players["2"], json!({ "x": 25.0 }));
+        assert_eq!(players["4"]["x"], 40.0);
+        assert_eq!(delta["removed_players"], json!([3]));
+        assert_eq!(delta["removed_projectiles"], json!([7]));
+        assert_eq!(delta["team2_score"], 1);
+        assert!(delta.get("projectiles").is_none() && delta.get("ball").is_none());
+        assert!(delta.get("team1_score").is_none() && delta.get("match_state").is_none());
+    }
+}
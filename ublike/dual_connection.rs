@@ -2,10 +2,17 @@
 use serde::{Serialize, Deserialize};
 use tokio::sync::Mutex;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::collections::HashMap;
 use warp::ws::{WebSocket, Message};
 use futures::{SinkExt, stream::SplitSink};
 use bytes::Bytes;
+use crate::delta::{json_delta, SnapshotHistory};
+use crate::game::GameStateSnapshot;
+use crate::protocol::{encode_delta, WireFormat};
+use crate::sim::player::Team;
+use crate::moderation::{BanTarget, Identity};
+use crate::tickets::constant_time_eq;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -45,11 +52,51 @@ pub enum MessageType {
     Explosion,
     #[serde(rename = "countdown")]
     Countdown,
+    #[serde(rename = "wire_format")]
+    WireFormat,
+    #[serde(rename = "name_table")]
+    NameTable,
+    #[serde(rename = "spectator_camera")]
+    SpectatorCamera,
+    #[serde(rename = "match_phase")]
+    MatchPhase,
+    #[serde(rename = "match_end")]
+    MatchEnd,
+    #[serde(rename = "scoreboard")]
+    Scoreboard,
+    #[serde(rename = "player_reconnecting")]
+    PlayerReconnecting,
+    #[serde(rename = "player_reconnected")]
+    PlayerReconnected,
+    #[serde(rename = "host_changed")]
+    HostChanged,
+    #[serde(rename = "game_settings")]
+    GameSettings,
+    #[serde(rename = "kicked")]
+    Kicked,
+    #[serde(rename = "chat")]
+    Chat,
 }
 
-// WebTransport channel for ultra-low latency critical data
+// What a spectator's camera is doing; snapshots carry the whole world either way
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpectatorCamera {
+    Free,
+    Follow(u32), // Player id
+}
+
+impl SpectatorCamera {
+    pub fn to_json(self) -> serde_json::Value {
+        match self {
+            SpectatorCamera::Free => serde_json::json!({ "type": "spectator_camera", "mode": "free" }),
+            SpectatorCamera::Follow(player_id) => serde_json::json!({ "type": "spectator_camera", "mode": "follow", "player_id": player_id }),
+        }
+    }
+}
+
+// WebTransport channel for ultra-low latency critical data (sent as unreliable datagrams)
 pub struct WebTransportChannel {
-    pub send_stream: Arc<Mutex<wtransport::SendStream>>,
+    pub connection: wtransport::Connection,
 }
 
 pub struct DualConnection {
@@ -57,7 +104,81 @@ pub struct DualConnection {
     pub reliable_channel: Arc<Mutex<SplitSink<WebSocket, Message>>>,
     pub fast_channel: Option<Arc<Mutex<SplitSink<WebSocket, Message>>>>, // Legacy WebSocket fast channel
     pub webtransport_channel: Option<WebTransportChannel>, // Ultra-low latency WebTransport
-    pub last_fast_message_time: std::time::Instant,
+    pub wire_format: WireFormat, // Snapshot encoding negotiated after init
+    pub known_names: HashMap<u32, String>, // Display names already sent to a binary client
+    pub snapshot_history: SnapshotHistory, // Snapshots sent and acked, for delta encoding
+    pub spectator: Option<SpectatorCamera>, // Set for spectators, who have no ship in the game
+    pub identity: Identity, // Session, account and address the client connected with, for bans
+    pub fast_secret: String, // Sent in `init`; a fast channel or WebTransport session must present it
+    pub resume_token: String, // Sent in `init`; lets a new connection take over this player after a drop
+    pub connected_at: std::time::Instant, // When this connection opened, for picking a new host
+}
+
+// Secrets handed to a player's client in its `init` message
+pub struct ConnectionSecrets {
+    pub fast_secret: String,
+    pub resume_token: String,
+}
+
+// A player whose connection dropped, kept in the game until they resume or their grace period ends
+#[derive(Debug, Clone)]
+pub struct PendingResume {
+    pub player_id: u32,
+    pub identity: Identity,
+    claimed: bool, // A connection is being upgraded to take this player back
+}
+
+// A player's place in a game, held for a connection before its socket is upgraded so two
+// connections can't both take the last one. Dropping it before the connection joins (the upgrade
+// failed or the connection was refused) hands the slot back.
+#[derive(Debug)]
+pub struct PlayerSlot {
+    player_count: Arc<AtomicUsize>,
+    joined: bool,
+}
+
+impl Drop for PlayerSlot {
+    fn drop(&mut self) {
+        if !self.joined {
+            self.player_count.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// A dropped player claimed by a connection that is being upgraded. Nobody else can claim them
+// until this is finished or dropped; dropping it (the upgrade failed or the connection was
+// refused) lets the player be claimed again.
+pub struct ResumeClaim {
+    dual_mgr: Arc<DualConnectionManager>,
+    resume_token: String,
+    pub player_id: u32,
+    pub identity: Identity,
+    finished: bool,
+}
+
+impl ResumeClaim {
+    // Take the player back for the new connection, which keeps the slot they held. Returns None
+    // if their grace period ended or they were kicked since the claim was made.
+    pub async fn finish(mut self) -> Option<PlayerSlot> {
+        self.finished = true;
+        self.dual_mgr.resumes.lock().await.remove(&self.resume_token)?;
+        Some(PlayerSlot { player_count: self.dual_mgr.player_count.clone(), joined: false })
+    }
+}
+
+impl Drop for ResumeClaim {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let dual_mgr = self.dual_mgr.clone();
+        let resume_token = std::mem::take(&mut self.resume_token);
+        tokio::spawn(async move {
+            if let Some(resume) = dual_mgr.resumes.lock().await.get_mut(&resume_token) {
+                resume.claimed = false;
+            }
+        });
+    }
 }
 
 impl DualConnection {
@@ -67,39 +188,63 @@ impl DualConnection {
             reliable_channel: reliable_ws,
             fast_channel: None,
             webtransport_channel: None,
-            last_fast_message_time: std::time::Instant::now(),
+            wire_format: WireFormat::Json,
+            known_names: HashMap::new(),
+            snapshot_history: SnapshotHistory::new(),
+            spectator: None,
+            identity: Identity::default(),
+            fast_secret: uuid::Uuid::new_v4().simple().to_string(),
+            resume_token: uuid::Uuid::new_v4().simple().to_string(),
+            connected_at: std::time::Instant::now(),
         }
     }
     
-    pub fn add_fast_channel(&mut self, fast_ws: SplitSink<WebSocket, Message>) {
-        self.fast_channel = Some(Arc::new(Mutex::new(fast_ws)));
+    fn check_fast_secret(&self, secret: &str) -> Result<(), &'static str> {
+        if constant_time_eq(self.fast_secret.as_bytes(), secret.as_bytes()) {
+            Ok(())
+        } else {
+            Err("Wrong fast channel secret")
+        }
+    }
+    
+    pub fn add_fast_channel(&mut self, fast_ws: Arc<Mutex<SplitSink<WebSocket, Message>>>) {
+        self.fast_channel = Some(fast_ws);
         println!("Fast channel established for client {}", self.client_id);
     }
     
-    pub fn add_webtransport_channel(&mut self, send_stream: wtransport::SendStream) {
+    pub fn add_webtransport_channel(&mut self, connection: wtransport::Connection) {
         self.webtransport_channel = Some(WebTransportChannel {
-            send_stream: Arc::new(Mutex::new(send_stream)),
+            connection,
         });
         println!("⚡ WebTransport ultra-low latency channel established for client {}", self.client_id);
     }
     
     pub async fn send_message(&self, message_type: &MessageType, data: serde_json::Value) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let message_str = data.to_string();
-        
+        self.send_payload(message_type, Bytes::from(message_str.clone()), Message::text(message_str)).await
+    }
+    
+    pub async fn send_binary(&self, message_type: &MessageType, frame: Vec<u8>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.send_payload(message_type, Bytes::from(frame.clone()), Message::binary(frame)).await
+    }
+    
+    // Route an already-encoded payload: `datagram` is used for WebTransport, `ws_message` for the WebSocket channels
+    async fn send_payload(&self, message_type: &MessageType, datagram: Bytes, ws_message: Message) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         match message_type {
             // CRITICAL DATA: Use WebTransport for ultra-low latency (5-15ms)
             MessageType::Input | MessageType::PositionUpdate | MessageType::BallPosition | MessageType::ProjectileUpdate => {
-                // Priority 1: WebTransport (ultra-low latency)
+                // Priority 1: WebTransport datagram (ultra-low latency)
                 if let Some(wt_channel) = &self.webtransport_channel {
-                    let bytes = Bytes::from(message_str.clone());
-                    let mut stream = wt_channel.send_stream.lock().await;
-                    match stream.write_all(&bytes).await {
+                    match wt_channel.connection.send_datagram(datagram) {
                         Ok(_) => {
                             // Successfully sent via WebTransport
                             return Ok(());
                         }
-                        Err(_) => {
-                            println!("⚠️ WebTransport failed for client {}, trying fast channel fallback", self.client_id);
+                        Err(wtransport::error::SendDatagramError::TooLarge) => {
+                            // Larger payloads (e.g. busy projectile updates) go over the WebSocket channels instead
+                        }
+                        Err(e) => {
+                            println!("⚠️ WebTransport failed for client {} ({}), trying fast channel fallback", self.client_id, e);
                         }
                     }
                 }
@@ -107,7 +252,7 @@ impl DualConnection {
                 // Priority 2: Fast WebSocket channel (if available)
                 if let Some(fast_channel) = &self.fast_channel {
                     let mut channel = fast_channel.lock().await;
-                    if let Err(_) = channel.send(Message::text(message_str.clone())).await {
+                    if channel.send(ws_message.clone()).await.is_err() {
                         println!("Fast channel failed for client {}, using reliable fallback", self.client_id);
                     } else {
                         return Ok(());
@@ -116,12 +261,12 @@ impl DualConnection {
                 
                 // Priority 3: Reliable WebSocket (final fallback)
                 let mut reliable = self.reliable_channel.lock().await;
-                reliable.send(Message::text(message_str)).await?;
+                reliable.send(ws_message).await?;
             }
             _ => {
                 // NON-CRITICAL DATA: Always use reliable WebSocket for safety
                 let mut reliable = self.reliable_channel.lock().await;
-                reliable.send(Message::text(message_str)).await?;
+                reliable.send(ws_message).await?;
             }
         }
         
@@ -132,10 +277,6 @@ impl DualConnection {
         self.fast_channel.is_some()
     }
     
-    pub fn has_webtransport_channel(&self) -> bool {
-        self.webtransport_channel.is_some()
-    }
-    
     pub fn get_connection_type(&self) -> &'static str {
         if self.webtransport_channel.is_some() {
             "WebTransport + WebSocket"
@@ -148,41 +289,220 @@ impl DualConnection {
 }
 
 pub struct DualConnectionManager {
+    pub game_id: String, // Game the connections are for, which game-wide bans are scoped to
     connections: Arc<Mutex<HashMap<u32, DualConnection>>>,
+    player_count: Arc<AtomicUsize>, // Both counts are kept outside the lock so the lobby can read them synchronously
+    spectator_count: AtomicUsize,
+    team_reservations: Mutex<HashMap<String, Team>>, // Teams the matchmaker put accounts on, by account id
+    resumes: Mutex<HashMap<String, PendingResume>>,  // Dropped players by resume token; they still count as players
+    match_roster: Mutex<HashMap<String, Team>>,      // Accounts playing at kickoff and their teams, for rating
 }
 
 impl DualConnectionManager {
-    pub fn new() -> Self {
+    pub fn new(game_id: &str) -> Self {
         Self {
+            game_id: game_id.to_string(),
             connections: Arc::new(Mutex::new(HashMap::new())),
+            player_count: Arc::new(AtomicUsize::new(0)),
+            spectator_count: AtomicUsize::new(0),
+            team_reservations: Mutex::new(HashMap::new()),
+            resumes: Mutex::new(HashMap::new()),
+            match_roster: Mutex::new(HashMap::new()),
         }
     }
     
-    pub async fn add_reliable_connection(&self, client_id: u32, ws: Arc<Mutex<SplitSink<WebSocket, Message>>>) {
+    // Hold a player slot for a connection about to join, unless `max_players` slots are taken.
+    // Checking and taking the slot is one atomic step.
+    pub fn reserve_player_slot(&self, max_players: usize) -> Option<PlayerSlot> {
+        self.player_count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| (count < max_players).then_some(count + 1))
+            .ok()?;
+        Some(PlayerSlot { player_count: self.player_count.clone(), joined: false })
+    }
+    
+    // The player keeps `slot` until their connection is removed
+    pub async fn add_reliable_connection(&self, client_id: u32, ws: Arc<Mutex<SplitSink<WebSocket, Message>>>, mut slot: PlayerSlot) -> ConnectionSecrets {
         let connection = DualConnection::new(client_id, ws);
+        let secrets = ConnectionSecrets {
+            fast_secret: connection.fast_secret.clone(),
+            resume_token: connection.resume_token.clone(),
+        };
         self.connections.lock().await.insert(client_id, connection);
+        slot.joined = true;
         println!("Reliable connection established for client {}", client_id);
+        secrets
+    }
+    
+    pub async fn add_spectator_connection(&self, client_id: u32, ws: Arc<Mutex<SplitSink<WebSocket, Message>>>) -> String {
+        let mut connection = DualConnection::new(client_id, ws);
+        connection.spectator = Some(SpectatorCamera::Free);
+        let fast_secret = connection.fast_secret.clone();
+        self.connections.lock().await.insert(client_id, connection);
+        self.spectator_count.fetch_add(1, Ordering::Relaxed);
+        println!("Spectator connection established for client {}", client_id);
+        fast_secret
+    }
+    
+    pub async fn is_spectator(&self, client_id: u32) -> bool {
+        self.connections.lock().await
+            .get(&client_id)
+            .is_some_and(|connection| connection.spectator.is_some())
+    }
+    
+    pub async fn spectator_ids(&self) -> Vec<u32> {
+        self.connections.lock().await.values()
+            .filter(|connection| connection.spectator.is_some())
+            .map(|connection| connection.client_id)
+            .collect()
+    }
+    
+    // Connected players (not spectators), longest connected first
+    pub async fn players_by_connection_time(&self) -> Vec<u32> {
+        let connections = self.connections.lock().await;
+        let mut players: Vec<&DualConnection> = connections.values()
+            .filter(|connection| connection.spectator.is_none())
+            .collect();
+        players.sort_by_key(|connection| (connection.connected_at, connection.client_id));
+        players.into_iter().map(|connection| connection.client_id).collect()
+    }
+    
+    pub async fn set_identity(&self, client_id: u32, identity: Identity) {
+        if let Some(connection) = self.connections.lock().await.get_mut(&client_id) {
+            connection.identity = identity;
+        }
+    }
+    
+    // Who a client is, whether it's connected or a dropped player waiting to resume
+    pub async fn identity(&self, client_id: u32) -> Option<Identity> {
+        if let Some(connection) = self.connections.lock().await.get(&client_id) {
+            return Some(connection.identity.clone());
+        }
+        self.resumes.lock().await.values()
+            .find(|resume| resume.player_id == client_id)
+            .map(|resume| resume.identity.clone())
+    }
+    
+    // Clients (connected or waiting to resume) a ban on `target` applies to
+    pub async fn clients_matching(&self, target: &BanTarget) -> Vec<u32> {
+        let mut clients: Vec<u32> = self.connections.lock().await.values()
+            .filter(|connection| target.matches(&connection.identity))
+            .map(|connection| connection.client_id)
+            .collect();
+        clients.extend(self.resumes.lock().await.values()
+            .filter(|resume| target.matches(&resume.identity))
+            .map(|resume| resume.player_id));
+        clients
+    }
+    
+    // Account ids of the clients that logged in, connected or waiting to resume, by client id
+    pub async fn account_ids(&self) -> HashMap<u32, String> {
+        let mut account_ids: HashMap<u32, String> = self.connections.lock().await.values()
+            .filter_map(|connection| connection.identity.account_id.clone().map(|id| (connection.client_id, id)))
+            .collect();
+        account_ids.extend(self.resumes.lock().await.values()
+            .filter_map(|resume| resume.identity.account_id.clone().map(|id| (resume.player_id, id))));
+        account_ids
+    }
+    
+    // Whether a player logged in to `account_id` is in the game, connected or waiting to resume
+    pub async fn has_player_account(&self, account_id: &str) -> bool {
+        let connected = self.connections.lock().await.values()
+            .any(|connection| connection.spectator.is_none() && connection.identity.account_id.as_deref() == Some(account_id));
+        connected || self.resumes.lock().await.values()
+            .any(|resume| resume.identity.account_id.as_deref() == Some(account_id))
+    }
+    
+    // Remember who is playing the match that just kicked off
+    pub async fn set_match_roster(&self, roster: HashMap<String, Team>) {
+        *self.match_roster.lock().await = roster;
+    }
+    
+    pub async fn take_match_roster(&self) -> HashMap<String, Team> {
+        std::mem::take(&mut *self.match_roster.lock().await)
+    }
+    
+    pub async fn reserve_team(&self, account_id: String, team: Team) {
+        self.team_reservations.lock().await.insert(account_id, team);
     }
     
-    pub async fn add_fast_connection(&self, client_id: u32, ws: SplitSink<WebSocket, Message>) {
+    pub async fn reserved_team(&self, account_id: &str) -> Option<Team> {
+        self.team_reservations.lock().await.get(account_id).copied()
+    }
+    
+    pub fn player_count(&self) -> usize {
+        self.player_count.load(Ordering::Relaxed)
+    }
+    
+    pub fn spectator_count(&self) -> usize {
+        self.spectator_count.load(Ordering::Relaxed)
+    }
+    
+    pub async fn set_spectator_camera(&self, client_id: u32, camera: SpectatorCamera) {
         let mut connections = self.connections.lock().await;
-        if let Some(connection) = connections.get_mut(&client_id) {
-            connection.add_fast_channel(ws);
-        } else {
-            println!("Warning: Fast connection for client {} but no reliable connection found", client_id);
+        if let Some(spectator) = connections.get_mut(&client_id).and_then(|connection| connection.spectator.as_mut()) {
+            *spectator = camera;
+        }
+    }
+    
+    // Switch spectators following a player who left back to the free camera and tell them
+    pub async fn release_followers(&self, player_id: u32) {
+        let mut connections = self.connections.lock().await;
+        for connection in connections.values_mut() {
+            if connection.spectator == Some(SpectatorCamera::Follow(player_id)) {
+                connection.spectator = Some(SpectatorCamera::Free);
+                let _ = connection.send_message(&MessageType::SpectatorCamera, SpectatorCamera::Free.to_json()).await;
+            }
+        }
+    }
+    
+    // Attach a fast channel to a client, if it knows the client's secret and the client doesn't
+    // have a fast channel already
+    pub async fn add_fast_connection(&self, client_id: u32, secret: &str, ws: Arc<Mutex<SplitSink<WebSocket, Message>>>) -> Result<(), &'static str> {
+        let mut connections = self.connections.lock().await;
+        let connection = connections.get_mut(&client_id).ok_or("No reliable connection for this client")?;
+        connection.check_fast_secret(secret)?;
+        if connection.fast_channel.is_some() {
+            return Err("Client already has a fast channel");
         }
+        connection.add_fast_channel(ws);
+        Ok(())
+    }
+    
+    pub async fn remove_fast_connection(&self, client_id: u32) {
+        if let Some(connection) = self.connections.lock().await.get_mut(&client_id) {
+            connection.fast_channel = None;
+        }
+    }
+    
+    // Whether a WebTransport session may be bound to a client, checked before accepting it
+    pub async fn authorize_webtransport(&self, client_id: u32, secret: &str) -> Result<(), &'static str> {
+        let connections = self.connections.lock().await;
+        let connection = connections.get(&client_id).ok_or("No reliable connection for this client")?;
+        connection.check_fast_secret(secret)?;
+        if connection.webtransport_channel.is_some() {
+            return Err("Client already has a WebTransport session");
+        }
+        Ok(())
     }
     
-    pub async fn add_webtransport_connection(&self, client_id: u32, send_stream: wtransport::SendStream) {
+    pub async fn add_webtransport_connection(&self, client_id: u32, session: wtransport::Connection) {
         let mut connections = self.connections.lock().await;
         if let Some(connection) = connections.get_mut(&client_id) {
-            connection.add_webtransport_channel(send_stream);
+            connection.add_webtransport_channel(session);
             println!("🚀 Client {} upgraded to WebTransport ultra-low latency!", client_id);
         } else {
             println!("Warning: WebTransport connection for client {} but no reliable connection found", client_id);
         }
     }
     
+    pub async fn remove_webtransport_connection(&self, client_id: u32) {
+        let mut connections = self.connections.lock().await;
+        if let Some(connection) = connections.get_mut(&client_id) {
+            connection.webtransport_channel = None;
+            println!("WebTransport channel removed for client {}, falling back to {}", client_id, connection.get_connection_type());
+        }
+    }
+    
     pub async fn broadcast_message(&self, message_type: MessageType, data: serde_json::Value, exclude_client: Option<u32>) {
         let connections = self.connections.lock().await;
         
@@ -206,11 +526,151 @@ impl DualConnectionManager {
         Ok(())
     }
     
+    pub async fn set_wire_format(&self, client_id: u32, format: WireFormat) {
+        let mut connections = self.connections.lock().await;
+        if let Some(connection) = connections.get_mut(&client_id) {
+            connection.wire_format = format;
+            // A client switching formats may have dropped its name table, so resend it
+            connection.known_names.clear();
+            connection.snapshot_history.clear();
+            println!("Client {} switched snapshot encoding to {}", client_id, format.name());
+        }
+    }
+    
+    pub async fn ack_snapshot(&self, client_id: u32, snapshot_id: u32) {
+        let mut connections = self.connections.lock().await;
+        if let Some(connection) = connections.get_mut(&client_id) {
+            connection.snapshot_history.ack(snapshot_id);
+        }
+    }
+    
+    // Send one tick's snapshot in whichever encoding the client negotiated, as a delta against
+    // the client's acked baseline when there is one and as the given full snapshot otherwise.
+    // Binary clients first receive any display names they haven't seen yet as a reliable name table.
+    pub async fn send_snapshot(&self, client_id: u32, snapshot: &Arc<GameStateSnapshot>, snapshot_json: &serde_json::Value, snapshot_binary: &[u8], names: &HashMap<u32, String>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut connections = self.connections.lock().await;
+        let connection = match connections.get_mut(&client_id) {
+            Some(connection) => connection,
+            None => return Ok(()),
+        };
+        
+        let baseline = connection.snapshot_history.next_baseline(snapshot);
+        
+        match connection.wire_format {
+            WireFormat::Json => {
+                let message = match &baseline {
+                    Some(baseline) => json_delta(baseline, snapshot),
+                    None => snapshot_json.clone(),
+                };
+                connection.send_message(&MessageType::GameState, message).await?;
+            }
+            WireFormat::Binary => {
+                let changed: HashMap<u32, String> = names.iter()
+                    .filter(|(id, name)| connection.known_names.get(id) != Some(name))
+                    .map(|(id, name)| (*id, name.clone()))
+                    .collect();
+                
+                if !changed.is_empty() {
+                    let name_table = serde_json::json!({
+                        "type": "name_table",
+                        "names": changed
+                    });
+                    connection.send_message(&MessageType::NameTable, name_table).await?;
+                    connection.known_names.extend(changed);
+                }
+                
+                let frame = match &baseline {
+                    Some(baseline) => encode_delta(baseline, snapshot),
+                    None => snapshot_binary.to_vec(),
+                };
+                connection.send_binary(&MessageType::GameState, frame).await?;
+            }
+        }
+        Ok(())
+    }
+    
     pub async fn remove_client(&self, client_id: u32) {
-        self.connections.lock().await.remove(&client_id);
+        let removed = self.connections.lock().await.remove(&client_id);
+        match removed.map(|connection| connection.spectator.is_some()) {
+            Some(true) => self.spectator_count.fetch_sub(1, Ordering::Relaxed),
+            Some(false) => self.player_count.fetch_sub(1, Ordering::Relaxed),
+            None => 0,
+        };
         println!("Removed all connections for client {}", client_id);
     }
     
+    // Drop a player's connections but keep their slot, so they can resume with the token from
+    // their `init` message. Returns the token.
+    pub async fn suspend_client(&self, client_id: u32) -> Option<String> {
+        let connection = self.connections.lock().await.remove(&client_id)?;
+        let resume = PendingResume {
+            player_id: client_id,
+            identity: connection.identity.clone(),
+            claimed: false,
+        };
+        self.resumes.lock().await.insert(connection.resume_token.clone(), resume);
+        println!("Connections for client {} suspended until it resumes", client_id);
+        Some(connection.resume_token)
+    }
+    
+    // Claim the dropped player a resume token belongs to for a new connection, if they're still
+    // waiting and no other connection has claimed them
+    pub async fn claim_resume(self: &Arc<Self>, resume_token: &str) -> Option<ResumeClaim> {
+        let mut resumes = self.resumes.lock().await;
+        let resume = resumes.get_mut(resume_token).filter(|resume| !resume.claimed)?;
+        resume.claimed = true;
+        Some(ResumeClaim {
+            dual_mgr: self.clone(),
+            resume_token: resume_token.to_string(),
+            player_id: resume.player_id,
+            identity: resume.identity.clone(),
+            finished: false,
+        })
+    }
+    
+    // Give up on a dropped player whose grace period ended, even if a connection has claimed
+    // them. Returns whether they were still waiting (false if they resumed in the meantime).
+    pub async fn expire_resume(&self, resume_token: &str) -> bool {
+        let expired = self.resumes.lock().await.remove(resume_token).is_some();
+        if expired {
+            self.player_count.fetch_sub(1, Ordering::Relaxed);
+        }
+        expired
+    }
+    
+    // Close a client's connections for good, telling it why. A player waiting to resume loses
+    // their place too.
+    pub async fn kick_client(&self, client_id: u32, reason: &str) {
+        let removed = self.connections.lock().await.remove(&client_id);
+        if let Some(connection) = removed {
+            if connection.spectator.is_some() {
+                self.spectator_count.fetch_sub(1, Ordering::Relaxed);
+            } else {
+                self.player_count.fetch_sub(1, Ordering::Relaxed);
+            }
+            let kicked = serde_json::json!({
+                "type": "kicked",
+                "reason": reason
+            });
+            let _ = connection.send_message(&MessageType::Kicked, kicked).await;
+            let _ = connection.reliable_channel.lock().await.close().await;
+            if let Some(fast_channel) = &connection.fast_channel {
+                let _ = fast_channel.lock().await.close().await;
+            }
+            if let Some(webtransport) = &connection.webtransport_channel {
+                webtransport.connection.close(0u32.into(), reason.as_bytes());
+            }
+        }
+        
+        let mut resumes = self.resumes.lock().await;
+        let waiting = resumes.len();
+        resumes.retain(|_, resume| resume.player_id != client_id);
+        if resumes.len() < waiting {
+            self.player_count.fetch_sub(1, Ordering::Relaxed);
+        }
+        println!("Kicked client {}: {}", client_id, reason);
+    }
+    
     pub async fn get_stats(&self) -> (usize, usize) {
         let connections = self.connections.lock().await;
         let total_clients = connections.len();
@@ -0,0 +1,192 @@
+// This module handles the commands only a game's host may send, and hands the host role on
+// when the host leaves.
+//
+// The first human to join a game becomes its host. Every command is checked against the
+// sender's `is_host` flag here on the server, so a client can't act as host just by sending the
+// message. Admins (see moderation.rs) may send them in any game. When the host drops or leaves,
+// the role passes to the player who has been connected the longest and everyone is told with a
+// `host_changed` event.
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+use std::sync::Arc;
+use crate::dual_connection::{DualConnectionManager, MessageType};
+use crate::game::Game;
+use crate::map::MAP_REGISTRY;
+use crate::moderation::{is_admin, BanKind, Sanction, MODERATION};
+use crate::sim::match_state::MatchSettings;
+use crate::websocket::remove_disconnected_player;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostCommand {
+    ResetGame,
+    Kick { player_id: u32 },
+    Ban {
+        player_id: u32,
+        #[serde(default)]
+        by: Option<BanKind>, // Ban the player's lobby session, account or IP address (see BanKind::default_target)
+        #[serde(default)]
+        duration_secs: Option<u64>, // For the rest of the game if not given
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    Mute {
+        player_id: u32,
+        #[serde(default)]
+        duration_secs: Option<u64>,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    Unmute { player_id: u32 },
+    ChangeMap { map_id: String },
+    StartMatch,
+    LockTeams { locked: bool },
+    ChangeSettings { match_settings: MatchSettings },
+}
+
+// Carry out a host command from `player_id`. The error is sent back to the sender.
+pub async fn handle_host_command(player_id: u32, command: HostCommand, game: &Arc<Mutex<Game>>, dual_mgr: &DualConnectionManager) -> Result<(), String> {
+    let sender = dual_mgr.identity(player_id).await.unwrap_or_default();
+    let mut game_lock = game.lock().await;
+    let is_host = game_lock.players.get(&player_id).is_some_and(|player| player.is_host);
+    if !is_host && !is_admin(sender.account_id.as_deref()) {
+        println!("Player {} is not host, ignoring {:?}", player_id, command);
+        return Err("Only the host can do that".to_string());
+    }
+    println!("Host {} sent {:?}", player_id, command);
+
+    match command {
+        HostCommand::ResetGame => game_lock.reset_game(),
+        HostCommand::Kick { player_id: target } => {
+            check_target(&game_lock, player_id, target)?;
+            kick_from_game(&mut game_lock, dual_mgr, target, "Kicked by the host").await;
+        }
+        HostCommand::Ban { player_id: target, by, duration_secs, reason } => {
+            check_target(&game_lock, player_id, target)?;
+            let identity = dual_mgr.identity(target).await.unwrap_or_default();
+            let ban_target = match by {
+                Some(kind) => kind.target(&identity),
+                None => BanKind::default_target(&identity),
+            };
+            let ban_target = ban_target.ok_or_else(|| format!("Player {} can't be banned that way", target))?;
+            let ban = Sanction::new(ban_target, Some(dual_mgr.game_id.clone()), reason, issuer(&game_lock, player_id), duration_secs);
+            let message = ban.describe("You have been banned from this game");
+            MODERATION.lock().await.ban(ban);
+            kick_from_game(&mut game_lock, dual_mgr, target, &message).await;
+        }
+        HostCommand::Mute { player_id: target, duration_secs, reason } => {
+            check_target(&game_lock, player_id, target)?;
+            // Mute the account if they have one, so a new session doesn't get around it
+            let identity = dual_mgr.identity(target).await.unwrap_or_default();
+            let mute_target = [BanKind::Account, BanKind::Session, BanKind::Ip].into_iter()
+                .find_map(|kind| kind.target(&identity))
+                .ok_or_else(|| format!("Player {} can't be muted", target))?;
+            let mute = Sanction::new(mute_target, Some(dual_mgr.game_id.clone()), reason, issuer(&game_lock, player_id), duration_secs);
+            MODERATION.lock().await.mute(mute);
+        }
+        HostCommand::Unmute { player_id: target } => {
+            let identity = dual_mgr.identity(target).await
+                .ok_or_else(|| format!("No player {} to unmute", target))?;
+            let mut moderation = MODERATION.lock().await;
+            let mut lifted = false;
+            for kind in [BanKind::Account, BanKind::Session, BanKind::Ip] {
+                if let Some(mute_target) = kind.target(&identity) {
+                    lifted |= moderation.unmute(&mute_target, Some(&dual_mgr.game_id));
+                }
+            }
+            if !lifted {
+                return Err(format!("Player {} isn't muted", target));
+            }
+        }
+        HostCommand::ChangeMap { map_id } => {
+            let map = MAP_REGISTRY.get(&map_id).ok_or_else(|| format!("Unknown map '{}'", map_id))?;
+            game_lock.switch_map(map);
+            broadcast_settings(&game_lock, dual_mgr).await;
+        }
+        HostCommand::StartMatch => {
+            if !game_lock.start_match_now() {
+                return Err("The match has already started".to_string());
+            }
+        }
+        HostCommand::LockTeams { locked } => {
+            game_lock.teams_locked = locked;
+            broadcast_settings(&game_lock, dual_mgr).await;
+        }
+        HostCommand::ChangeSettings { match_settings } => {
+            // Durations and scores apply from the next half or goal
+            game_lock.match_settings = match_settings.sanitized();
+            broadcast_settings(&game_lock, dual_mgr).await;
+        }
+    }
+    Ok(())
+}
+
+// Hosts can kick, ban and mute other humans in their game
+fn check_target(game: &Game, host_id: u32, target: u32) -> Result<(), String> {
+    if target == host_id {
+        return Err("You can't do that to yourself".to_string());
+    }
+    if !game.players.contains_key(&target) || game.is_bot(target) {
+        return Err(format!("No player {}", target));
+    }
+    Ok(())
+}
+
+// Who a host's ban or mute is recorded as coming from
+fn issuer(game: &Game, host_id: u32) -> String {
+    match game.players.get(&host_id) {
+        Some(player) => format!("{} (host)", player.display_name),
+        None => format!("player {} (host)", host_id),
+    }
+}
+
+// Take a player or spectator out of the game for good, closing their connections with `reason`.
+// Holding the game lock keeps a kicked player's connection from being suspended for a resume
+// while it closes.
+pub async fn kick_from_game(game: &mut Game, dual_mgr: &DualConnectionManager, client_id: u32, reason: &str) {
+    dual_mgr.kick_client(client_id, reason).await;
+    migrate_host(game, dual_mgr, client_id).await;
+    remove_disconnected_player(game, dual_mgr, client_id).await;
+}
+
+// Tell everyone in the game about a change the host made to the room
+async fn broadcast_settings(game: &Game, dual_mgr: &DualConnectionManager) {
+    let settings = json!({
+        "type": "game_settings",
+        "map_id": game.map.id,
+        "teams_locked": game.teams_locked,
+        "match_settings": game.match_settings
+    });
+    dual_mgr.broadcast_message(MessageType::GameSettings, settings, None).await;
+}
+
+// Pass the host role on if `leaving_id` has it. Call after the leaving player's connection has
+// been suspended or removed. With nobody else connected the game is left without a host until
+// a player joins or resumes.
+pub async fn migrate_host(game: &mut Game, dual_mgr: &DualConnectionManager, leaving_id: u32) {
+    match game.players.get_mut(&leaving_id) {
+        Some(player) if player.is_host => player.is_host = false,
+        _ => return,
+    }
+
+    let next_host = dual_mgr.players_by_connection_time().await
+        .into_iter()
+        .find(|id| *id != leaving_id && game.players.contains_key(id) && !game.is_bot(*id));
+    let Some(next_host) = next_host else {
+        println!("Host {} left with no one to take over", leaving_id);
+        return;
+    };
+    if let Some(player) = game.players.get_mut(&next_host) {
+        player.is_host = true;
+    }
+    println!("Host {} left, player {} is the new host", leaving_id, next_host);
+
+    let host_changed = json!({
+        "type": "host_changed",
+        "host_id": next_host,
+        "previous_host_id": leaving_id
+    });
+    dual_mgr.broadcast_message(MessageType::HostChanged, host_changed, None).await;
+}
@@ -1,14 +1,46 @@
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use warp::ws::{Message, WebSocket};
 use futures::{StreamExt};
 use futures::SinkExt;
-use warp::Filter;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use once_cell::sync::Lazy;
 use tokio::sync::mpsc;
-use crate::game::{Game};
+use crate::accounts::{AccountInfo, ACCOUNTS};
+use crate::chat::{clean_name, clean_text, ChatLimiter};
+use crate::game::{new_game, now_ms, record_match_result, Game, DEFAULT_GAME_ID, GLOBAL_GAME};
+use crate::dual_connection::{DualConnectionManager, PlayerSlot};
+use crate::host::kick_from_game;
+use crate::map::MAP_REGISTRY;
+use crate::matchmaking::{MatchQueue, QueuedPlayer};
+use crate::moderation::{check_not_banned, forget_closed_game, is_admin, BanTarget, Identity, Sanction, MODERATION};
+use crate::sim::bot::{BotDifficulty, BotFill};
+use crate::sim::match_state::MatchSettings;
+use crate::tickets::{constant_time_eq, JoinTicket};
+use crate::tick::{TickScheduler, TICK_CONFIG};
+
+// Accounts shown on the leaderboard
+const LEADERBOARD_SIZE: usize = 20;
+
+// How often the match queue is checked for players who can be matched
+const MATCHMAKING_INTERVAL_MS: u64 = 1000;
+
+// Private room join codes: letters and digits that can't be mistaken for each other
+const JOIN_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const JOIN_CODE_LEN: usize = 6;
+
+// Wrong room passwords a lobby client can send before it has to wait PASSWORD_LOCKOUT_MS
+const MAX_PASSWORD_FAILURES: u32 = 5;
+const PASSWORD_LOCKOUT_MS: u64 = 30_000;
+
+// Outgoing message queue of one lobby client
+type LobbySender = Arc<Mutex<mpsc::UnboundedSender<Result<Message, warp::Error>>>>;
+
+// What a redeemed join ticket connects the socket to, with the slot held for a player
+type TicketedGame = (Arc<Mutex<Game>>, Arc<DualConnectionManager>, JoinTicket, Option<PlayerSlot>);
 
 // Structure to represent a game instance
 pub struct GameInstance {
@@ -16,17 +48,68 @@ pub struct GameInstance {
     pub name: String,
     pub host_id: String,
     pub game: Arc<Mutex<Game>>,
-    pub player_count: usize,
+    pub dual_mgr: Arc<DualConnectionManager>, // Connections for this game only, so client ids can't collide across rooms
     pub max_players: usize,
     pub is_public: bool,
+    pub join_code: Option<String>, // Private rooms can only be joined with this, not their id
+    pub password: Option<RoomPassword>,
+    pub redeemed_tickets: HashMap<String, u64>, // Nonces of tickets already used, until they expire
     pub port: Option<u16>,
+    pub map_id: String,
+    pub map_rotation: Vec<String>,
+    pub bot_fill: Option<BotFill>,
+    pub match_settings: MatchSettings,
+}
+
+impl GameInstance {
+    // Players actually connected to the game (bots and spectators aren't counted)
+    pub fn player_count(&self) -> usize {
+        self.dual_mgr.player_count()
+    }
+    
+    pub fn is_full(&self) -> bool {
+        self.player_count() >= self.max_players
+    }
+}
+
+// A room's password, kept only as a salted hash
+pub struct RoomPassword {
+    salt: [u8; 16],
+    hash: [u8; 32],
+}
+
+impl RoomPassword {
+    pub fn new(password: &str) -> Self {
+        let salt = rand::random();
+        Self { salt, hash: Self::digest(&salt, password) }
+    }
+
+    pub fn matches(&self, password: &str) -> bool {
+        constant_time_eq(&Self::digest(&self.salt, password), &self.hash)
+    }
+
+    fn digest(salt: &[u8], password: &str) -> [u8; 32] {
+        Sha256::new().chain_update(salt).chain_update(password.as_bytes()).finalize().into()
+    }
+}
+
+// Wrong passwords one lobby client has sent since it was last locked out
+#[derive(Debug, Default)]
+pub struct PasswordFailures {
+    count: u32,
+    locked_until: u64, // Server time in ms
 }
 
 // Structure to manage all game instances
 pub struct LobbyManager {
     pub games: HashMap<String, GameInstance>,
     pub next_game_id: u32,
-    pub clients: HashMap<String, Arc<Mutex<mpsc::UnboundedSender<Result<Message, warp::Error>>>>>,
+    pub clients: HashMap<String, LobbySender>,
+    pub logins: HashMap<String, String>, // Account id each logged-in lobby client played as
+    pub addresses: HashMap<String, IpAddr>, // Where each lobby client connected from, for IP bans
+    pub chat_limiters: HashMap<String, ChatLimiter>, // Lobby chat rate limit, per client
+    pub password_failures: HashMap<String, PasswordFailures>, // Wrong room passwords, per client
+    pub match_queue: MatchQueue,
 }
 
 // Message types for lobby communication
@@ -40,12 +123,31 @@ pub enum LobbyMessage {
         is_public: bool,
         #[serde(default)]
         display_name: String,
+        #[serde(default)]
+        map_id: Option<String>,
+        #[serde(default)]
+        map_rotation: Vec<String>,
+        #[serde(default)]
+        bot_difficulty: Option<String>, // "easy", "normal" or "hard" to fill empty team slots with bots
+        #[serde(default)]
+        bot_team_size: Option<u32>,
+        #[serde(default)]
+        match_settings: Option<MatchSettings>, // Overrides for any of the match rules
+        #[serde(default)]
+        password: Option<String>, // Required from everyone else who joins
     },
     #[serde(rename = "join_game")]
     JoinGame {
+        #[serde(default)]
         game_id: String,
         #[serde(default)]
+        join_code: Option<String>, // Needed instead of game_id for private rooms
+        #[serde(default)]
+        password: Option<String>,
+        #[serde(default)]
         display_name: String,
+        #[serde(default)]
+        spectate: bool, // Watch without taking a player slot
     },
     #[serde(rename = "list_games")]
     ListGames,
@@ -53,15 +155,24 @@ pub enum LobbyMessage {
     GameList {
         games: Vec<GameInfo>,
     },
+    #[serde(rename = "list_maps")]
+    ListMaps,
+    #[serde(rename = "map_list")]
+    MapList {
+        maps: Vec<String>,
+    },
     #[serde(rename = "game_created")]
     GameCreated {
         game_id: String,
         port: u16,
+        join_code: Option<String>, // For inviting others to a private room
+        ticket: String,            // Pass as ?ticket=... when connecting to the game
     },
     #[serde(rename = "game_joined")]
     GameJoined {
         game_id: String,
         port: u16,
+        ticket: String,
     },
     #[serde(rename = "error")]
     Error {
@@ -77,6 +188,88 @@ pub enum LobbyMessage {
     },
     #[serde(rename = "heartbeat")]
     Heartbeat,
+    #[serde(rename = "login")]
+    Login {
+        #[serde(default)]
+        token: Option<String>, // Resume an existing account
+        #[serde(default)]
+        name: Option<String>,  // Or create one with this name
+    },
+    #[serde(rename = "logged_in")]
+    LoggedIn {
+        account: AccountInfo,
+        token: String, // Pass as ?token=... when connecting to a game
+    },
+    #[serde(rename = "queue_for_match")]
+    QueueForMatch {
+        #[serde(default)]
+        ping_ms: Option<u32>, // Round trip to this server, to match players with similar pings
+    },
+    #[serde(rename = "leave_queue")]
+    LeaveQueue,
+    #[serde(rename = "queued")]
+    Queued {
+        players_waiting: usize,
+    },
+    #[serde(rename = "left_queue")]
+    LeftQueue,
+    #[serde(rename = "list_leaderboard")]
+    ListLeaderboard,
+    #[serde(rename = "leaderboard")]
+    Leaderboard {
+        players: Vec<AccountInfo>,
+    },
+    // Admin only: bans and mutes are server-wide unless a game_id is given
+    #[serde(rename = "ban")]
+    Ban {
+        target: BanTarget,
+        #[serde(default)]
+        game_id: Option<String>,
+        #[serde(default)]
+        duration_secs: Option<u64>, // Until lifted if not given
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    #[serde(rename = "unban")]
+    Unban {
+        target: BanTarget,
+        #[serde(default)]
+        game_id: Option<String>,
+    },
+    #[serde(rename = "mute")]
+    Mute {
+        target: BanTarget,
+        #[serde(default)]
+        game_id: Option<String>,
+        #[serde(default)]
+        duration_secs: Option<u64>,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    #[serde(rename = "unmute")]
+    Unmute {
+        target: BanTarget,
+        #[serde(default)]
+        game_id: Option<String>,
+    },
+    #[serde(rename = "list_bans")]
+    ListBans,
+    #[serde(rename = "ban_list")]
+    BanList {
+        bans: Vec<Sanction>,
+        mutes: Vec<Sanction>,
+    },
+    // Global lobby chat (see chat.rs)
+    #[serde(rename = "chat")]
+    Chat {
+        text: String,
+    },
+    #[serde(rename = "chat_message")]
+    ChatMessage {
+        name: String,
+        text: String,
+        sent_at: u64, // Server time in ms
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -85,7 +278,14 @@ pub struct GameInfo {
     pub name: String,
     pub player_count: usize,
     pub max_players: usize,
+    pub spectator_count: usize, // Spectators don't count towards max_players
     pub is_public: bool,
+    pub has_password: bool,
+    pub map_id: String,
+    pub map_rotation: Vec<String>,
+    pub bot_difficulty: Option<BotDifficulty>,
+    pub match_settings: MatchSettings,
+    pub match_phase: Option<String>, // Phase of the match being played, if the game isn't busy
 }
 
 impl LobbyManager {
@@ -94,26 +294,62 @@ impl LobbyManager {
             games: HashMap::new(),
             next_game_id: 1,
             clients: HashMap::new(),
+            logins: HashMap::new(),
+            addresses: HashMap::new(),
+            chat_limiters: HashMap::new(),
+            password_failures: HashMap::new(),
+            match_queue: MatchQueue::new(),
         }
     }
 
     // Create a new game instance
-    pub fn create_game(&mut self, name: String, max_players: usize, is_public: bool, host_id: String) -> String {
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_game(&mut self, name: String, max_players: usize, is_public: bool, host_id: String, map_id: Option<String>, map_rotation: Vec<String>, bot_fill: Option<BotFill>, match_settings: MatchSettings, password: Option<String>) -> Result<String, String> {
+        // Resolve every requested map up front so a typo doesn't leave a half-created room
+        let mut rotation = Vec::new();
+        for id in &map_rotation {
+            match MAP_REGISTRY.get(id) {
+                Some(map) => rotation.push(map),
+                None => return Err(format!("Unknown map in rotation: {}", id)),
+            }
+        }
+        
+        let map = match map_id.as_deref() {
+            Some(id) => MAP_REGISTRY.get(id).ok_or_else(|| format!("Unknown map: {}", id))?,
+            None => rotation.first().cloned().unwrap_or_else(|| MAP_REGISTRY.default_map()),
+        };
+        
         let game_id = format!("game_{}", self.next_game_id);
         self.next_game_id += 1;
 
-        println!("Creating new game: id={}, name={}, max_players={}, is_public={}", 
-                 game_id, name, max_players, is_public);
+        println!("Creating new game: id={}, name={}, max_players={}, is_public={}, map={}, rotation={:?}", 
+                 game_id, name, max_players, is_public, map.id, map_rotation);
+
+        // Start the rotation from the chosen map if it's part of it
+        let mut game = new_game(map.clone());
+        game.rotation_index = rotation.iter().position(|m| m.id == map.id).unwrap_or(0);
+        game.map_rotation = rotation;
+        game.bot_fill = bot_fill;
+        game.match_settings = match_settings;
+
+        let join_code = if is_public { None } else { Some(self.new_join_code()) };
 
         let game_instance = GameInstance {
             id: game_id.clone(),
             name,
             host_id,
-            game: Arc::new(Mutex::new(Game::new())),
-            player_count: 0,
+            game: Arc::new(Mutex::new(game)),
+            dual_mgr: Arc::new(DualConnectionManager::new(&game_id)),
             max_players,
             is_public,
+            join_code,
+            password: password.filter(|password| !password.is_empty()).map(|password| RoomPassword::new(&password)),
+            redeemed_tickets: HashMap::new(),
             port: None,
+            map_id: map.id.clone(),
+            map_rotation,
+            bot_fill,
+            match_settings,
         };
 
         self.games.insert(game_id.clone(), game_instance);
@@ -121,7 +357,7 @@ impl LobbyManager {
         // Log the current games
         println!("Current games: {}", self.games.keys().cloned().collect::<Vec<String>>().join(", "));
         
-        game_id
+        Ok(game_id)
     }
 
     // List available games
@@ -131,45 +367,149 @@ impl LobbyManager {
             .map(|game| GameInfo {
                 id: game.id.clone(),
                 name: game.name.clone(),
-                player_count: game.player_count,
+                player_count: game.player_count(),
                 max_players: game.max_players,
+                spectator_count: game.dual_mgr.spectator_count(),
                 is_public: game.is_public,
+                has_password: game.password.is_some(),
+                // The map changes with the rotation, so prefer the live value when the game isn't busy
+                map_id: game.game.try_lock()
+                    .map(|g| g.map.id.clone())
+                    .unwrap_or_else(|_| game.map_id.clone()),
+                map_rotation: game.map_rotation.clone(),
+                bot_difficulty: game.bot_fill.map(|fill| fill.difficulty),
+                // The host can change the settings mid-game
+                match_settings: game.game.try_lock()
+                    .map(|g| g.match_settings)
+                    .unwrap_or(game.match_settings),
+                match_phase: game.game.try_lock()
+                    .map(|g| g.match_state.phase.name().to_string())
+                    .ok(),
             })
             .collect::<Vec<GameInfo>>();
             
         println!("Listing games: found {} public games", games.len());
         for game in &games {
-            println!("  - Game: id={}, name={}, players={}/{}", 
-                     game.id, game.name, game.player_count, game.max_players);
+            println!("  - Game: id={}, name={}, players={}/{}, spectators={}", 
+                     game.id, game.name, game.player_count, game.max_players, game.spectator_count);
         }
         
         games
     }
 
-    pub fn join_game(&mut self, game_id: &str, _player_id: &str) -> Result<Arc<Mutex<Game>>, String> {
-        println!("Attempting to join game: {}", game_id);
+    // A fresh join code that no other room is using
+    fn new_join_code(&self) -> String {
+        loop {
+            let mut bits: u64 = rand::random();
+            let code: String = (0..JOIN_CODE_LEN)
+                .map(|_| {
+                    let c = JOIN_CODE_ALPHABET[(bits % JOIN_CODE_ALPHABET.len() as u64) as usize];
+                    bits /= JOIN_CODE_ALPHABET.len() as u64;
+                    c as char
+                })
+                .collect();
+            if !self.games.values().any(|game| game.join_code.as_deref() == Some(code.as_str())) {
+                return code;
+            }
+        }
+    }
+
+    // Find the game to join: private rooms by their join code, public ones by id (or code).
+    // Private rooms answer "not found" to their id so ids can't be used to get in.
+    fn find_joinable(&self, game_id: &str, join_code: Option<&str>) -> Option<&GameInstance> {
+        match join_code {
+            Some(code) => self.games.values()
+                .find(|game| game.join_code.as_deref().is_some_and(|own| own.eq_ignore_ascii_case(code.trim()))),
+            None => self.games.get(game_id).filter(|game| game.is_public),
+        }
+    }
+
+    // Check the room's code, password and capacity (spectators don't need a free slot) for the
+    // lobby client `session`, which is locked out for a while after too many wrong passwords.
+    // Returns the id of the game joined; the slot is taken once the game socket connects.
+    pub fn join_game(&mut self, session: &str, game_id: &str, join_code: Option<&str>, password: Option<&str>, spectator: bool, now_ms: u64) -> Result<String, String> {
+        println!("Attempting to join game: {} (code: {:?})", game_id, join_code);
         
-        if let Some(game) = self.games.get_mut(game_id) {
-            if game.player_count >= game.max_players {
-                println!("Game {} is full", game_id);
-                return Err("Game is full".to_string());
+        let game_id = match self.find_joinable(game_id, join_code) {
+            Some(game) => game.id.clone(),
+            None => {
+                println!("Game {} not found", game_id);
+                println!("Available games: {}", self.games.keys().cloned().collect::<Vec<String>>().join(", "));
+                return Err("Game not found".to_string());
             }
-            
-            game.player_count += 1;
-            println!("Joined game {}: player count now {}/{}", 
-                     game_id, game.player_count, game.max_players);
-            Ok(game.game.clone())
-        } else {
-            println!("Game {} not found", game_id);
-            println!("Available games: {}", self.games.keys().cloned().collect::<Vec<String>>().join(", "));
-            Err("Game not found".to_string())
+        };
+        let game = &self.games[&game_id];
+        
+        match (&game.password, password) {
+            (Some(_), None) => return Err("This game needs a password".to_string()),
+            (Some(expected), Some(given)) => {
+                let failures = self.password_failures.entry(session.to_string()).or_default();
+                if now_ms < failures.locked_until {
+                    return Err("Too many wrong passwords, try again later".to_string());
+                }
+                if !expected.matches(given) {
+                    failures.count += 1;
+                    if failures.count >= MAX_PASSWORD_FAILURES {
+                        println!("Lobby client {} sent too many wrong passwords", session);
+                        *failures = PasswordFailures { count: 0, locked_until: now_ms + PASSWORD_LOCKOUT_MS };
+                    }
+                    return Err("Wrong password".to_string());
+                }
+                self.password_failures.remove(session);
+            }
+            _ => {}
         }
+        
+        // Only a hint: the slot itself is reserved when the ticket is redeemed
+        if !spectator && game.is_full() {
+            println!("Game {} is full", game_id);
+            return Err("Game is full".to_string());
+        }
+        println!("Joined game {}: player count {}/{}", 
+                 game_id, game.player_count(), game.max_players);
+        Ok(game_id)
+    }
+
+    // Sign a ticket for one connection to a game by the given lobby client
+    pub fn issue_ticket(&self, game_id: &str, session: &str, spectator: bool) -> Option<String> {
+        self.games.get(game_id)?;
+        Some(JoinTicket::new(game_id, session, spectator, now_ms()).sign())
+    }
+
+    // Check a ticket presented by a game socket and use it up, returning the game to connect
+    // it to. A player's slot is reserved here, so players are turned away if the game filled up
+    // since the ticket was issued, however many tickets were handed out.
+    pub fn redeem_ticket(&mut self, game_id: &str, ticket: &str) -> Result<TicketedGame, String> {
+        let now = now_ms();
+        let ticket = JoinTicket::verify(ticket, game_id, now)?;
+        let game = self.games.get_mut(game_id).ok_or("Game not found")?;
+        
+        game.redeemed_tickets.retain(|_, expires_at| *expires_at >= now);
+        if game.redeemed_tickets.contains_key(&ticket.nonce) {
+            return Err("Join ticket has already been used".to_string());
+        }
+        let slot = match ticket.spectator {
+            true => None,
+            false => Some(game.dual_mgr.reserve_player_slot(game.max_players).ok_or("Game is full")?),
+        };
+        game.redeemed_tickets.insert(ticket.nonce.clone(), ticket.expires_at);
+        Ok((game.game.clone(), game.dual_mgr.clone(), ticket, slot))
     }
 
     // Remove a game instance
     pub fn remove_game(&mut self, game_id: &str) {
         println!("Removing game with ID: {}", game_id);
         self.games.remove(game_id);
+        forget_closed_game(game_id);
+    }
+    
+    // Who a lobby client is, for checking and enforcing bans
+    pub fn client_identity(&self, client_id: &str) -> Identity {
+        Identity {
+            session: Some(client_id.to_string()),
+            account_id: self.logins.get(client_id).cloned(),
+            ip: self.addresses.get(client_id).copied(),
+        }
     }
     
     // Check for and remove empty games
@@ -187,6 +527,7 @@ impl LobbyManager {
         for game_id in empty_games {
             println!("Cleanup: Removing empty game with ID: {}", game_id);
             self.games.remove(&game_id);
+            forget_closed_game(&game_id);
         }
         
         // Also clean up any disconnected clients
@@ -217,7 +558,7 @@ impl LobbyManager {
         } else {
             for (id, game) in &self.games {
                 println!("Game ID: {}, Name: {}, Players: {}/{}, Public: {}", 
-                         id, game.name, game.player_count, game.max_players, game.is_public);
+                         id, game.name, game.player_count(), game.max_players, game.is_public);
             }
         }
         println!("===================================");
@@ -232,7 +573,7 @@ impl LobbyManager {
         // Print details of each game
         for (id, game) in &self.games {
             println!("Game {}: Players={}, Max={}, Public={}", 
-                id, game.player_count, game.max_players, game.is_public);
+                id, game.player_count(), game.max_players, game.is_public);
         }
         println!("===========================");
     }
@@ -243,8 +584,8 @@ pub static LOBBY_MANAGER: Lazy<Arc<Mutex<LobbyManager>>> = Lazy::new(|| {
     Arc::new(Mutex::new(LobbyManager::new()))
 });
 
-// Handle a new lobby connection
-pub async fn handle_lobby_connection(ws: WebSocket, lobby: Arc<Mutex<LobbyManager>>) {
+// Handle a new lobby connection from `ip`
+pub async fn handle_lobby_connection(ws: WebSocket, lobby: Arc<Mutex<LobbyManager>>, ip: Option<IpAddr>) {
     let (ws_tx, mut ws_rx) = ws.split();
     
     // Generate a unique client ID
@@ -257,6 +598,9 @@ pub async fn handle_lobby_connection(ws: WebSocket, lobby: Arc<Mutex<LobbyManage
     {
         let mut lobby = lobby.lock().await;
         lobby.clients.insert(client_id.clone(), Arc::new(Mutex::new(tx)));
+        if let Some(ip) = ip {
+            lobby.addresses.insert(client_id.clone(), ip);
+        }
     }
     
     // Task to forward messages from the channel to the WebSocket
@@ -295,28 +639,68 @@ pub async fn handle_lobby_connection(ws: WebSocket, lobby: Arc<Mutex<LobbyManage
     {
         let mut lobby = lobby.lock().await;
         lobby.clients.remove(&client_id);
+        lobby.logins.remove(&client_id);
+        lobby.addresses.remove(&client_id);
+        lobby.chat_limiters.remove(&client_id);
+        lobby.password_failures.remove(&client_id);
+        lobby.match_queue.leave(&client_id);
     }
 }
 
 // Process lobby messages
 async fn process_lobby_message(message: LobbyMessage, client_id: &str, lobby: Arc<Mutex<LobbyManager>>) {
     match message {
-        LobbyMessage::CreateGame { name, max_players, is_public, display_name } => {
+        LobbyMessage::CreateGame { name, max_players, is_public, display_name, map_id, map_rotation, bot_difficulty, bot_team_size, match_settings, password } => {
             println!("Client {} is creating a game: {}", client_id, name);
             
-            // Find an available port
-            let port = find_available_port().await;
+            let bot_fill = match bot_difficulty.as_deref().map(|name| (name, BotDifficulty::from_name(name))) {
+                None => None,
+                Some((_, Some(difficulty))) => {
+                    let mut fill = BotFill::new(difficulty);
+                    if let Some(team_size) = bot_team_size {
+                        fill.team_size = team_size;
+                    }
+                    Some(fill)
+                }
+                Some((name, None)) => {
+                    send_to_client(
+                        client_id,
+                        LobbyMessage::Error {
+                            message: format!("Unknown bot difficulty: {}", name),
+                        },
+                        lobby.clone(),
+                    )
+                    .await;
+                    return;
+                }
+            };
             
-            let game_id = {
+            let create_result = {
                 let mut lobby_guard = lobby.lock().await;
-                lobby_guard.create_game(name, max_players, is_public, client_id.to_string())
+                lobby_guard.create_game(name, max_players, is_public, client_id.to_string(), map_id, map_rotation, bot_fill, match_settings.unwrap_or_default().sanitized(), password)
+            };
+            
+            let game_id = match create_result {
+                Ok(game_id) => game_id,
+                Err(e) => {
+                    send_to_client(
+                        client_id,
+                        LobbyMessage::Error {
+                            message: e,
+                        },
+                        lobby.clone(),
+                    )
+                    .await;
+                    return;
+                }
             };
             
             // Create a new game instance
-            let game = {
+            let (game, join_code, ticket) = {
                 let lobby_guard = lobby.lock().await;
-                if let Some(game_instance) = lobby_guard.games.get(&game_id) {
-                    game_instance.game.clone()
+                let ticket = lobby_guard.issue_ticket(&game_id, client_id, false);
+                if let (Some(game_instance), Some(ticket)) = (lobby_guard.games.get(&game_id), ticket) {
+                    (game_instance.game.clone(), game_instance.join_code.clone(), ticket)
                 } else {
                     eprintln!("Failed to get game instance after creation");
                     return;
@@ -326,25 +710,20 @@ async fn process_lobby_message(message: LobbyMessage, client_id: &str, lobby: Ar
             // Set the display name for the host player
             {
                 let mut game_lock = game.lock().await;
-                for (_, player) in game_lock.players.iter_mut() {
-                    player.set_display_name(display_name.clone());
-                    break; // Only set for the first player (host)
+                // Only set for the first player (host)
+                if let Some(player) = game_lock.players.values_mut().next() {
+                    player.set_display_name(clean_name(&display_name));
                 }
             }
             
-            // Update the port in the game instance
-            {
-                let mut lobby_guard = lobby.lock().await;
-                if let Some(game_instance) = lobby_guard.games.get_mut(&game_id) {
-                    game_instance.port = Some(port);
+            // Start the game server and its update loop
+            let port = match start_game_instance(&game_id, lobby.clone()).await {
+                Some(port) => port,
+                None => {
+                    eprintln!("Game {} disappeared before it could start", game_id);
+                    return;
                 }
-            }
-            
-            // Start the game server
-            tokio::spawn(game_server(port, game.clone()));
-            
-            // Start the game update loop
-            tokio::spawn(game_update_loop_for_instance(game));
+            };
             
             // Send the game created message back to the client
             send_to_client(
@@ -352,6 +731,8 @@ async fn process_lobby_message(message: LobbyMessage, client_id: &str, lobby: Ar
                 LobbyMessage::GameCreated {
                     game_id: game_id.clone(),
                     port,
+                    join_code,
+                    ticket,
                 },
                 lobby.clone(),
             )
@@ -363,22 +744,26 @@ async fn process_lobby_message(message: LobbyMessage, client_id: &str, lobby: Ar
                 lobby_guard.debug_print_games();
             }
         }
-        LobbyMessage::JoinGame { game_id, display_name } => {
+        LobbyMessage::JoinGame { game_id, join_code, password, display_name, spectate } => {
             println!("Client {} is joining game: {}", client_id, game_id);
             
             let join_result = {
                 let mut lobby_guard = lobby.lock().await;
-                lobby_guard.join_game(&game_id, client_id)
+                lobby_guard.join_game(client_id, &game_id, join_code.as_deref(), password.as_deref(), spectate, now_ms())
+                    .and_then(|game_id| {
+                        let ticket = lobby_guard.issue_ticket(&game_id, client_id, spectate).ok_or("Game not found")?;
+                        Ok((game_id.clone(), lobby_guard.games[&game_id].game.clone(), ticket))
+                    })
             };
             
             match join_result {
-                Ok(game) => {
+                Ok((game_id, game, ticket)) => {
                     // Set the display name for the joining player
                     {
                         let mut game_lock = game.lock().await;
                         for (_, player) in game_lock.players.iter_mut() {
                             if player.display_name == format!("Player {}", player.id) {
-                                player.set_display_name(display_name.clone());
+                                player.set_display_name(clean_name(&display_name));
                                 break;
                             }
                         }
@@ -401,6 +786,7 @@ async fn process_lobby_message(message: LobbyMessage, client_id: &str, lobby: Ar
                         LobbyMessage::GameJoined {
                             game_id: game_id.clone(),
                             port,
+                            ticket,
                         },
                         lobby.clone(),
                     )
@@ -437,10 +823,309 @@ async fn process_lobby_message(message: LobbyMessage, client_id: &str, lobby: Ar
                 lobby.clone()
             ).await;
         },
+        LobbyMessage::Login { token, name } => {
+            let login = {
+                let mut accounts = ACCOUNTS.lock().await;
+                match (token, name) {
+                    (Some(token), _) => accounts.authenticate(&token)
+                        .map(|account| (AccountInfo::from(account), account.token.clone()))
+                        .ok_or_else(|| "Unknown account token".to_string()),
+                    (None, Some(name)) => accounts.register(&name)
+                        .map(|account| (AccountInfo::from(account), account.token.clone())),
+                    (None, None) => Err("Log in with a token or pick a name".to_string()),
+                }
+            };
+            
+            // A banned account can't log in, even from a lobby connection that got in before the ban
+            let login = match login {
+                Ok((account, token)) => {
+                    let identity = Identity {
+                        account_id: Some(account.id.clone()),
+                        ..lobby.lock().await.client_identity(client_id)
+                    };
+                    check_not_banned(&identity, None).await.map(|_| (account, token))
+                }
+                Err(message) => Err(message),
+            };
+            
+            let reply = match login {
+                Ok((account, token)) => {
+                    println!("Client {} logged in as {} (rating {})", client_id, account.name, account.rating);
+                    lobby.lock().await.logins.insert(client_id.to_string(), account.id.clone());
+                    LobbyMessage::LoggedIn { account, token }
+                }
+                Err(message) => LobbyMessage::Error { message },
+            };
+            send_to_client(client_id, reply, lobby.clone()).await;
+        },
+        LobbyMessage::QueueForMatch { ping_ms } => {
+            let account_id = lobby.lock().await.logins.get(client_id).cloned();
+            let account = match account_id {
+                Some(account_id) => ACCOUNTS.lock().await.info(&account_id),
+                None => None,
+            };
+            let account = match account {
+                Some(account) => account,
+                None => {
+                    send_to_client(
+                        client_id,
+                        LobbyMessage::Error {
+                            message: "Log in to queue for rated matches".to_string(),
+                        },
+                        lobby.clone(),
+                    )
+                    .await;
+                    return;
+                }
+            };
+            
+            let players_waiting = lobby.lock().await.match_queue.join(QueuedPlayer {
+                client_id: client_id.to_string(),
+                account_id: account.id,
+                name: account.name.clone(),
+                rating: account.rating as f64,
+                ping_ms,
+                queued_at: now_ms(),
+            });
+            println!("{} queued for a match (rating {}, ping {:?}ms, {} waiting)", account.name, account.rating, ping_ms, players_waiting);
+            send_to_client(client_id, LobbyMessage::Queued { players_waiting }, lobby.clone()).await;
+            
+            run_matchmaking(lobby.clone()).await;
+        },
+        LobbyMessage::LeaveQueue => {
+            let left = lobby.lock().await.match_queue.leave(client_id);
+            if left {
+                send_to_client(client_id, LobbyMessage::LeftQueue, lobby.clone()).await;
+            }
+        },
+        LobbyMessage::ListLeaderboard => {
+            let players = ACCOUNTS.lock().await.leaderboard(LEADERBOARD_SIZE);
+            send_to_client(
+                client_id,
+                LobbyMessage::Leaderboard { players },
+                lobby.clone()
+            ).await;
+        },
+        LobbyMessage::ListMaps => {
+            send_to_client(
+                client_id,
+                LobbyMessage::MapList { maps: MAP_REGISTRY.ids() },
+                lobby.clone()
+            ).await;
+        },
+        LobbyMessage::Chat { text } => {
+            if let Err(message) = send_lobby_chat(client_id, &text, lobby.clone()).await {
+                send_to_client(client_id, LobbyMessage::Error { message }, lobby.clone()).await;
+            }
+        },
+        LobbyMessage::Ban { .. } | LobbyMessage::Unban { .. } | LobbyMessage::Mute { .. } | LobbyMessage::Unmute { .. } | LobbyMessage::ListBans => {
+            let admin = lobby.lock().await.logins.get(client_id).cloned();
+            if !is_admin(admin.as_deref()) {
+                send_to_client(
+                    client_id,
+                    LobbyMessage::Error {
+                        message: "Only admins can ban and mute players".to_string(),
+                    },
+                    lobby.clone(),
+                )
+                .await;
+                return;
+            }
+            let issued_by = format!("admin {}", admin.unwrap_or_default());
+            
+            match message {
+                LobbyMessage::Ban { target, game_id, duration_secs, reason } => {
+                    let ban = Sanction::new(target, game_id, reason, issued_by, duration_secs);
+                    MODERATION.lock().await.ban(ban.clone());
+                    enforce_ban(&ban, lobby.clone()).await;
+                }
+                LobbyMessage::Unban { target, game_id } => {
+                    MODERATION.lock().await.unban(&target, game_id.as_deref());
+                }
+                LobbyMessage::Mute { target, game_id, duration_secs, reason } => {
+                    let mute = Sanction::new(target, game_id, reason, issued_by, duration_secs);
+                    MODERATION.lock().await.mute(mute);
+                }
+                LobbyMessage::Unmute { target, game_id } => {
+                    MODERATION.lock().await.unmute(&target, game_id.as_deref());
+                }
+                _ => {}
+            }
+            
+            let (bans, mutes) = MODERATION.lock().await.active();
+            send_to_client(client_id, LobbyMessage::BanList { bans, mutes }, lobby.clone()).await;
+        },
         _ => {}
     }
 }
 
+// Start a game for every group of queued players that can be matched, and send each player
+// where to join it
+async fn run_matchmaking(lobby: Arc<Mutex<LobbyManager>>) {
+    loop {
+        let (game_id, formed, dual_mgr, tickets) = {
+            let mut lobby_guard = lobby.lock().await;
+            let formed = match lobby_guard.match_queue.find_match(now_ms()) {
+                Some(formed) => formed,
+                None => return,
+            };
+            
+            let names: Vec<&str> = formed.players.iter().map(|(player, _)| player.name.as_str()).collect();
+            println!("Matchmaking: matched {}", names.join(", "));
+            
+            // Queued matches are private rooms sized for the players in them
+            let host_id = formed.players[0].0.client_id.clone();
+            let created = lobby_guard.create_game(
+                "Ranked match".to_string(),
+                formed.players.len(),
+                false,
+                host_id,
+                None,
+                Vec::new(),
+                None,
+                MatchSettings::default(),
+                None,
+            );
+            let game_id = match created {
+                Ok(game_id) => game_id,
+                Err(e) => {
+                    eprintln!("Matchmaking: failed to create a game: {}", e);
+                    return;
+                }
+            };
+            let dual_mgr = match lobby_guard.games.get(&game_id) {
+                Some(instance) => instance.dual_mgr.clone(),
+                None => return,
+            };
+            let tickets: Vec<String> = formed.players.iter()
+                .filter_map(|(player, _)| lobby_guard.issue_ticket(&game_id, &player.client_id, false))
+                .collect();
+            (game_id, formed, dual_mgr, tickets)
+        };
+        
+        // Players land on the team the matchmaker balanced them into when they connect
+        for (player, team) in &formed.players {
+            dual_mgr.reserve_team(player.account_id.clone(), *team).await;
+        }
+        
+        let port = match start_game_instance(&game_id, lobby.clone()).await {
+            Some(port) => port,
+            None => return,
+        };
+        
+        for ((player, _), ticket) in formed.players.iter().zip(tickets) {
+            send_to_client(
+                &player.client_id,
+                LobbyMessage::GameJoined {
+                    game_id: game_id.clone(),
+                    port,
+                    ticket,
+                },
+                lobby.clone(),
+            )
+            .await;
+        }
+    }
+}
+
+// Check the match queue regularly, so players waiting for their windows to widen get matched
+pub async fn matchmaking_loop(lobby: Arc<Mutex<LobbyManager>>) {
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(MATCHMAKING_INTERVAL_MS));
+    loop {
+        interval.tick().await;
+        run_matchmaking(lobby.clone()).await;
+    }
+}
+
+// Give a newly created game a port and start its server and update loop
+async fn start_game_instance(game_id: &str, lobby: Arc<Mutex<LobbyManager>>) -> Option<u16> {
+    let port = find_available_port().await;
+    let (game, dual_mgr) = {
+        let mut lobby_guard = lobby.lock().await;
+        let game_instance = lobby_guard.games.get_mut(game_id)?;
+        game_instance.port = Some(port);
+        (game_instance.game.clone(), game_instance.dual_mgr.clone())
+    };
+    
+    tokio::spawn(game_server(port, game.clone()));
+    tokio::spawn(game_update_loop_for_instance(game_id.to_string(), game, dual_mgr));
+    Some(port)
+}
+
+// Send a lobby client's chat message to every lobby client, under their account name if they
+// logged in
+async fn send_lobby_chat(client_id: &str, text: &str, lobby: Arc<Mutex<LobbyManager>>) -> Result<(), String> {
+    let identity = lobby.lock().await.client_identity(client_id);
+    if let Some(mute) = MODERATION.lock().await.mute_for(&identity, None) {
+        return Err(mute.describe("You are muted"));
+    }
+    let text = clean_text(text)?;
+    
+    let name = match &identity.account_id {
+        Some(account_id) => ACCOUNTS.lock().await.info(account_id).map(|account| clean_name(&account.name)),
+        None => None,
+    };
+    let name = name.unwrap_or_else(|| format!("Guest_{}", &client_id[..4]));
+    
+    let mut lobby = lobby.lock().await;
+    let now = now_ms();
+    lobby.chat_limiters.entry(client_id.to_string())
+        .or_insert_with(ChatLimiter::new)
+        .take(now)?;
+    
+    let message = LobbyMessage::ChatMessage { name, text, sent_at: now };
+    if let Ok(json) = serde_json::to_string(&message) {
+        for client in lobby.clients.values() {
+            let _ = client.lock().await.send(Ok(Message::text(json.clone())));
+        }
+    }
+    Ok(())
+}
+
+// Close the connections a new ban applies to: players and spectators in the games it covers and,
+// for a server-wide ban, lobby clients
+async fn enforce_ban(ban: &Sanction, lobby: Arc<Mutex<LobbyManager>>) {
+    let covers = |game_id: &str| ban.game_id.as_deref().is_none_or(|id| id == game_id);
+    let mut games: Vec<(Arc<Mutex<Game>>, Arc<DualConnectionManager>)> = lobby.lock().await.games.values()
+        .filter(|instance| covers(&instance.id))
+        .map(|instance| (instance.game.clone(), instance.dual_mgr.clone()))
+        .collect();
+    if covers(DEFAULT_GAME_ID) {
+        games.push((GLOBAL_GAME.clone(), crate::DUAL_CONNECTION_MANAGER.clone()));
+    }
+    
+    let message = match &ban.game_id {
+        Some(_) => ban.describe("You have been banned from this game"),
+        None => ban.describe("You have been banned from this server"),
+    };
+    for (game, dual_mgr) in games {
+        let clients = dual_mgr.clients_matching(&ban.target).await;
+        if clients.is_empty() {
+            continue;
+        }
+        let mut game_lock = game.lock().await;
+        for client_id in clients {
+            kick_from_game(&mut game_lock, &dual_mgr, client_id, &message).await;
+        }
+    }
+    
+    if ban.game_id.is_some() {
+        return;
+    }
+    let lobby = lobby.lock().await;
+    for (client_id, client) in &lobby.clients {
+        if !ban.target.matches(&lobby.client_identity(client_id)) {
+            continue;
+        }
+        println!("Closing lobby connection of banned client {}", client_id);
+        let client = client.lock().await;
+        if let Ok(json) = serde_json::to_string(&LobbyMessage::Error { message: message.clone() }) {
+            let _ = client.send(Ok(Message::text(json)));
+        }
+        let _ = client.send(Ok(Message::close()));
+    }
+}
+
 // Send a message to a specific client
 async fn send_to_client(client_id: &str, message: LobbyMessage, lobby: Arc<Mutex<LobbyManager>>) {
     let clients = {
@@ -456,29 +1141,41 @@ async fn send_to_client(client_id: &str, message: LobbyMessage, lobby: Arc<Mutex
 }
 
 // Run a game update loop for a specific game instance
-pub async fn game_update_loop_for_instance(game: Arc<Mutex<Game>>) {
+pub async fn game_update_loop_for_instance(game_id: String, game: Arc<Mutex<Game>>, dual_mgr: Arc<DualConnectionManager>) {
     println!("Starting game instance update loop");
     
     // Start the game update loop in a separate task
     let game_for_loop = game.clone();
     tokio::spawn(async move {
         println!("Game update loop started");
-        let fixed_dt = 0.1; // Match main game's update rate
-        let sub_steps = 10; // Match main game's physics steps
-        let _sub_dt = fixed_dt / sub_steps as f32;
+        let fixed_dt = TICK_CONFIG.fixed_dt(); // Match main game's update rate
         let game_width = 2000.0;
         let game_height = 1200.0;
+        let mut scheduler = TickScheduler::new(&TICK_CONFIG);
         
-        // Counter for periodic cleanup checks
+        // Counter for periodic cleanup checks (every 15 seconds)
         let mut cleanup_counter = 0;
+        let cleanup_interval = TICK_CONFIG.tick_rate * 15;
         
         loop {
+            scheduler.next_tick().await;
+            
+            let mut match_result = None;
             {
                 let mut game = game_for_loop.lock().await;
+                game.balance_bots();
+                crate::replay::update_recording(&mut game, &game_id, fixed_dt, game_width, game_height);
                 
                 // Only update if there are active players
                 if !game.players.is_empty() {
-                    game.update(fixed_dt, game_width, game_height, None);
+                    let events = game.step(fixed_dt, game_width, game_height, now_ms());
+                    match_result = game.match_result(&dual_mgr, &events).await;
+                    game.broadcast_events(&dual_mgr, events).await;
+                    
+                    // Send snapshots and projectile positions the same way the default game does
+                    if scheduler.snapshot_due() {
+                        game.broadcast_state(&dual_mgr).await;
+                    }
                 }
                 
                 // Check if the game is empty
@@ -487,26 +1184,23 @@ pub async fn game_update_loop_for_instance(game: Arc<Mutex<Game>>) {
                 }
             }
             
+            // Rate a finished match now that the game's lock is released
+            if let Some(result) = match_result {
+                record_match_result(result).await;
+            }
+            
             // Periodically check for empty games in the lobby manager
             cleanup_counter += 1;
-            if cleanup_counter >= 200 {
+            if cleanup_counter >= cleanup_interval {
                 cleanup_counter = 0;
                 if let Ok(mut lobby) = LOBBY_MANAGER.try_lock() {
                     lobby.cleanup_empty_games();
                 }
             }
-            
-            // Match the main game's sleep timing
-            tokio::time::sleep(tokio::time::Duration::from_millis((fixed_dt * 700.0) as u64)).await;
         }
     });
 }
 
-// Helper function to provide the game instance to the WebSocket handler
-pub fn with_game_instance(game: Arc<Mutex<Game>>) -> impl warp::Filter<Extract = (Arc<Mutex<Game>>,), Error = std::convert::Infallible> + Clone {
-    warp::any().map(move || game.clone())
-}
-
 // Helper function to find an available port
 async fn find_available_port() -> u16 {
     // For simplicity, we'll use a fixed port for now
@@ -520,4 +1214,102 @@ async fn game_server(_port: u16, _game: Arc<Mutex<crate::game::Game>>) {
     // For now, we'll just log that we would start a server
     println!("Would start game server on port {}", _port);
     // We don't actually need to do anything here since we're using a single server for all games
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lobby_with_game(max_players: usize) -> (LobbyManager, String) {
+        let mut lobby = LobbyManager::new();
+        let game_id = lobby.create_game("Test".to_string(), max_players, true, "host".to_string(), None, Vec::new(), None, MatchSettings::default(), None).unwrap();
+        (lobby, game_id)
+    }
+
+    #[test]
+    fn ticket_can_only_be_redeemed_once() {
+        let (mut lobby, game_id) = lobby_with_game(4);
+        let ticket = lobby.issue_ticket(&game_id, "session", false).unwrap();
+        assert!(lobby.redeem_ticket(&game_id, &ticket).is_ok());
+        assert_eq!(lobby.redeem_ticket(&game_id, &ticket).err(), Some("Join ticket has already been used".to_string()));
+    }
+
+    #[test]
+    fn ticket_only_opens_its_own_game() {
+        let (mut lobby, game_id) = lobby_with_game(4);
+        let other_id = lobby.create_game("Other".to_string(), 4, true, "host".to_string(), None, Vec::new(), None, MatchSettings::default(), None).unwrap();
+        let ticket = lobby.issue_ticket(&game_id, "session", false).unwrap();
+        assert_eq!(lobby.redeem_ticket(&other_id, &ticket).err(), Some("Join ticket is for another game".to_string()));
+        assert!(lobby.issue_ticket("no_such_game", "session", false).is_none());
+    }
+
+    #[test]
+    fn redeeming_reserves_a_slot_until_the_connection_goes_away() {
+        let (mut lobby, game_id) = lobby_with_game(2);
+        let tickets: Vec<String> = (0..3).map(|_| lobby.issue_ticket(&game_id, "session", false).unwrap()).collect();
+        let first = lobby.redeem_ticket(&game_id, &tickets[0]).unwrap();
+        let _second = lobby.redeem_ticket(&game_id, &tickets[1]).unwrap();
+        assert_eq!(lobby.redeem_ticket(&game_id, &tickets[2]).err(), Some("Game is full".to_string()));
+
+        // Spectators don't take a slot
+        let spectator = lobby.issue_ticket(&game_id, "session", true).unwrap();
+        assert!(lobby.redeem_ticket(&game_id, &spectator).is_ok());
+
+        // A connection that never joins gives its slot back
+        drop(first);
+        let ticket = lobby.issue_ticket(&game_id, "session", false).unwrap();
+        assert!(lobby.redeem_ticket(&game_id, &ticket).is_ok());
+    }
+
+    fn private_game(lobby: &mut LobbyManager, password: Option<&str>) -> (String, String) {
+        let game_id = lobby.create_game("Private".to_string(), 4, false, "host".to_string(), None, Vec::new(), None, MatchSettings::default(), password.map(str::to_string)).unwrap();
+        let code = lobby.games[&game_id].join_code.clone().unwrap();
+        (game_id, code)
+    }
+
+    #[test]
+    fn private_rooms_are_only_found_by_their_code() {
+        let (mut lobby, public_id) = lobby_with_game(4);
+        let (private_id, code) = private_game(&mut lobby, None);
+
+        assert!(lobby.find_joinable(&private_id, None).is_none());
+        assert_eq!(lobby.find_joinable("", Some(&code.to_lowercase())).unwrap().id, private_id);
+        assert_eq!(lobby.find_joinable(&public_id, None).unwrap().id, public_id);
+        assert!(lobby.find_joinable(&public_id, Some("NOCODE")).is_none());
+        assert_eq!(lobby.join_game("session", &private_id, None, None, false, 0).err(), Some("Game not found".to_string()));
+    }
+
+    #[test]
+    fn join_codes_are_unique() {
+        let mut lobby = LobbyManager::new();
+        let codes: Vec<String> = (0..200).map(|_| private_game(&mut lobby, None).1).collect();
+        let unique: std::collections::HashSet<&String> = codes.iter().collect();
+        assert_eq!(unique.len(), codes.len());
+        assert!(codes.iter().all(|code| code.len() == JOIN_CODE_LEN && code.bytes().all(|c| JOIN_CODE_ALPHABET.contains(&c))));
+    }
+
+    #[test]
+    fn wrong_passwords_lock_the_session_out_for_a_while() {
+        let mut lobby = LobbyManager::new();
+        let (game_id, code) = private_game(&mut lobby, Some("hunter2"));
+        let mut join = |session: &str, password: Option<&str>, now: u64| lobby.join_game(session, &game_id, Some(&code), password, false, now);
+
+        assert_eq!(join("session", None, 0).err(), Some("This game needs a password".to_string()));
+        assert!(join("session", Some("hunter2"), 0).is_ok());
+        for _ in 0..MAX_PASSWORD_FAILURES {
+            assert_eq!(join("session", Some("guess"), 0).err(), Some("Wrong password".to_string()));
+        }
+        // Even the right password is refused until the lockout ends, but only for that session
+        assert!(join("session", Some("hunter2"), 1).is_err());
+        assert!(join("other", Some("hunter2"), 1).is_ok());
+        assert!(join("session", Some("hunter2"), PASSWORD_LOCKOUT_MS).is_ok());
+    }
+
+    #[test]
+    fn room_passwords_are_stored_salted() {
+        let first = RoomPassword::new("hunter2");
+        let second = RoomPassword::new("hunter2");
+        assert!(first.matches("hunter2") && second.matches("hunter2"));
+        assert!(!first.matches("hunter3") && !first.matches(""));
+        assert_ne!(first.hash, second.hash);
+    }
+}
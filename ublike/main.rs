@@ -1,9 +1,19 @@
+mod sim;
+mod accounts;
 mod game;
-mod player;
-mod ball;
-mod collision;
+mod map;
+mod protocol;
+mod delta;
+mod tick;
+mod replay;
 mod websocket;
 mod lobby;
+mod matchmaking;
+mod tickets;
+mod host;
+mod moderation;
+mod chat;
+mod persist;
 // mod webrtc_signaling; // Removed - WebTransport doesn't need complex signaling
 mod dual_connection;
 mod webtransport_relay;
@@ -11,9 +21,13 @@ mod webtransport_relay;
 use warp::Filter;
 use tokio::sync::Mutex;
 use std::sync::Arc;
-use crate::game::GLOBAL_GAME;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use crate::accounts::account_for_token;
+use crate::game::{DEFAULT_GAME_ID, GLOBAL_GAME};
 use crate::lobby::LOBBY_MANAGER;
-use crate::websocket::{handle_connection, handle_fast_connection, with_game};
+use crate::moderation::{check_not_banned, Identity};
+use crate::websocket::{handle_connection, handle_fast_connection, handle_spectator_connection, is_spectate_request, with_game, Seat};
 use crate::dual_connection::DualConnectionManager;
 // use crate::webrtc_datachannel::WebRTCDataChannelManager; // Removed - using WebTransport instead
 use once_cell::sync::Lazy;
@@ -21,14 +35,16 @@ use once_cell::sync::Lazy;
 // Global dual connection manager
 static DUAL_CONNECTION_MANAGER: Lazy<Arc<DualConnectionManager>> = Lazy::new(|| {
     println!("Initializing Dual Connection Manager...");
-    Arc::new(DualConnectionManager::new())
+    Arc::new(DualConnectionManager::new(DEFAULT_GAME_ID))
 });
 
 // WebRTC DataChannel manager removed - WebTransport provides ultra-low latency instead
 
 #[tokio::main]
 async fn main() {
-    println!("Loaded map objects: {:?}", *crate::game::MAP_OBJECTS);
+    println!("Loaded maps: {:?} (default: {})", crate::map::MAP_REGISTRY.ids(), crate::map::MAP_REGISTRY.default_map().id);
+    Lazy::force(&crate::accounts::ACCOUNTS);
+    Lazy::force(&crate::moderation::MODERATION);
     
     // Read port from environment variable, default to 8080
     let port: u16 = std::env::var("GAME_PORT")
@@ -40,14 +56,46 @@ async fn main() {
     
     // Create routes for both the lobby server and the default game server
     
-    // Default game server route (for backward compatibility)
+    // Default game server route (for backward compatibility); `?spectate=true` joins as a spectator,
+    // `?token=...` plays under an account (see accounts.rs) and `?resume=...` takes back a player
+    // whose connection dropped. Banned clients are refused (see moderation.rs).
     let game_ws_route = warp::path("ws")
         .and(warp::ws())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::addr::remote())
         .and(with_game(GLOBAL_GAME.clone()))
         .and(with_dual_manager(&DUAL_CONNECTION_MANAGER))
-        .map(|ws: warp::ws::Ws, game: Arc<Mutex<crate::game::Game>>, dual_mgr: Arc<DualConnectionManager>| {
-            println!("Default game connection request received");
-            ws.on_upgrade(move |socket| handle_connection(socket, game, dual_mgr))
+        .then(|ws: warp::ws::Ws, query: HashMap<String, String>, remote: Option<SocketAddr>, game: Arc<Mutex<crate::game::Game>>, dual_mgr: Arc<DualConnectionManager>| async move {
+            let spectate = is_spectate_request(&query);
+            let token = query.get("token").cloned();
+            println!("Default game connection request received (spectate: {})", spectate);
+            
+            // Claim a dropped player, or reserve a new player's slot, before upgrading, so no other
+            // connection can take them meanwhile. The default game has no player limit.
+            let seat = match query.get("resume") {
+                _ if spectate => None,
+                Some(resume_token) => match dual_mgr.claim_resume(resume_token).await {
+                    Some(claim) => Some(Seat::Resume(claim)),
+                    None => {
+                        let reason = "This player can no longer be resumed".to_string();
+                        println!("Rejected connection to the default game: {}", reason);
+                        return Box::new(warp::reply::with_status(reason, warp::http::StatusCode::FORBIDDEN)) as Box<dyn warp::Reply>;
+                    }
+                },
+                None => dual_mgr.reserve_player_slot(usize::MAX).map(Seat::New),
+            };
+            let origin = connection_origin(&query, remote, seat.as_ref(), None).await;
+            if let Err(reason) = check_not_banned(&origin, Some(&dual_mgr.game_id)).await {
+                println!("Rejected connection to the default game: {}", reason);
+                return Box::new(warp::reply::with_status(reason, warp::http::StatusCode::FORBIDDEN)) as Box<dyn warp::Reply>;
+            }
+            
+            Box::new(ws.on_upgrade(move |socket| async move {
+                match seat {
+                    Some(seat) => handle_connection(socket, game, dual_mgr, token, seat, origin).await,
+                    None => handle_spectator_connection(socket, game, dual_mgr, origin).await,
+                }
+            })) as Box<dyn warp::Reply>
         });
     
     // Fast channel route for low-latency data
@@ -60,72 +108,152 @@ async fn main() {
             ws.on_upgrade(move |socket| handle_fast_connection(socket, game, dual_mgr))
         });
     
-    // Game-specific route with game ID in the path
+    // Game-specific route with game ID in the path. Connections need the signed `?ticket=...` from
+    // the lobby's game_created/game_joined reply (see tickets.rs), or a `?resume=...` token for a
+    // player of this game whose connection dropped; `?spectate=true` and `?token=...` work as above.
     let game_specific_route = warp::path!("game" / String / "ws")
         .and(warp::ws())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::addr::remote())
         .and(with_lobby(LOBBY_MANAGER.clone()))
-        .and(with_dual_manager(&DUAL_CONNECTION_MANAGER))
-        .map(|game_id: String, ws: warp::ws::Ws, lobby: Arc<Mutex<crate::lobby::LobbyManager>>, dual_mgr: Arc<DualConnectionManager>| {
-            println!("Game-specific connection request for game ID: {}", game_id);
+        .then(|game_id: String, ws: warp::ws::Ws, query: HashMap<String, String>, remote: Option<SocketAddr>, lobby: Arc<Mutex<crate::lobby::LobbyManager>>| async move {
+            let instance = lobby.lock().await.games.get(&game_id)
+                .map(|instance| (instance.game.clone(), instance.dual_mgr.clone()));
+            
+            // A resume token is claimed before upgrading, so no other connection can take the
+            // player meanwhile; a stale one is refused rather than falling back to the ticket
+            let admitted = match (query.get("resume"), instance) {
+                (Some(resume_token), Some((game_instance, dual_mgr))) => match dual_mgr.claim_resume(resume_token).await {
+                    Some(claim) => {
+                        println!("Player {} resuming in game {}", claim.player_id, game_id);
+                        Ok((game_instance, dual_mgr, None, Some(Seat::Resume(claim))))
+                    }
+                    None => Err("This player can no longer be resumed".to_string()),
+                },
+                _ => {
+                    let ticket = query.get("ticket").map(String::as_str).unwrap_or_default();
+                    lobby.lock().await.redeem_ticket(&game_id, ticket).map(|(game_instance, dual_mgr, ticket, slot)| {
+                        println!("Game-specific connection request for game ID: {} from lobby client {}", game_id, ticket.session);
+                        (game_instance, dual_mgr, Some(ticket.session), slot.map(Seat::New))
+                    })
+                }
+            };
+            let admitted = match admitted {
+                Ok((game_instance, dual_mgr, session, seat)) => {
+                    let origin = connection_origin(&query, remote, seat.as_ref(), session).await;
+                    check_not_banned(&origin, Some(&game_id)).await
+                        .map(|_| (game_instance, dual_mgr, seat, origin))
+                }
+                Err(reason) => Err(reason),
+            };
+            let (game_instance, dual_mgr, seat, origin) = match admitted {
+                Ok(admitted) => admitted,
+                Err(reason) => {
+                    println!("Rejected connection to game {}: {}", game_id, reason);
+                    return Box::new(warp::reply::with_status(reason, warp::http::StatusCode::FORBIDDEN)) as Box<dyn warp::Reply>;
+                }
+            };
+            
+            // Spectator tickets come without a seat; a player asking to spectate gives theirs up
+            let seat = seat.filter(|_| !is_spectate_request(&query));
+            let token = query.get("token").cloned();
+            Box::new(ws.on_upgrade(move |socket| async move {
+                // Use the specific game instance (and its own connection manager) for this connection
+                match seat {
+                    Some(seat) => handle_connection(socket, game_instance, dual_mgr, token, seat, origin).await,
+                    None => handle_spectator_connection(socket, game_instance, dual_mgr, origin).await,
+                }
+            })) as Box<dyn warp::Reply>
+        });
+    
+    // Game-specific fast channel route, so the fast socket joins the same room's connection manager
+    let game_fast_route = warp::path!("game" / String / "fast")
+        .and(warp::ws())
+        .and(with_lobby(LOBBY_MANAGER.clone()))
+        .map(|game_id: String, ws: warp::ws::Ws, lobby: Arc<Mutex<crate::lobby::LobbyManager>>| {
+            println!("Game-specific fast channel request for game ID: {}", game_id);
             ws.on_upgrade(move |socket| {
-                // Find the game instance for this game ID
                 async move {
-                    println!("WebSocket connection upgraded for game ID: {}", game_id);
-                    let game_instance = {
+                    let instance = {
                         let lobby = lobby.lock().await;
-                        println!("Available games: {:?}", lobby.games.keys().collect::<Vec<_>>());
-                        match lobby.games.get(&game_id) {
-                            Some(instance) => {
-                                println!("Found game instance for ID: {}", game_id);
-                                instance.game.clone()
-                            }
-                            None => {
-                                // If game not found, log an error
-                                println!("ERROR: Game ID {} not found", game_id);
-                                // Return the global game as a fallback
-                                GLOBAL_GAME.clone()
-                            }
-                        }
+                        lobby.games.get(&game_id).map(|instance| (instance.game.clone(), instance.dual_mgr.clone()))
                     };
                     
-                    // Use the specific game instance for this connection
-                    handle_connection(socket, game_instance, dual_mgr).await
+                    match instance {
+                        Some((game_instance, dual_mgr)) => handle_fast_connection(socket, game_instance, dual_mgr).await,
+                        None => println!("ERROR: Fast channel requested for unknown game ID {}", game_id),
+                    }
                 }
             })
         });
     
-    // Lobby server route
+    // Lobby server route. Clients banned from the server by address, or by the account of an
+    // optional `?token=...`, are refused.
     let lobby_ws_route = warp::path("lobby")
         .and(warp::ws())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::addr::remote())
         .and(with_lobby(LOBBY_MANAGER.clone()))
-        .map(|ws: warp::ws::Ws, lobby: Arc<Mutex<crate::lobby::LobbyManager>>| {
-            ws.on_upgrade(move |socket| crate::lobby::handle_lobby_connection(socket, lobby))
+        .then(|ws: warp::ws::Ws, query: HashMap<String, String>, remote: Option<SocketAddr>, lobby: Arc<Mutex<crate::lobby::LobbyManager>>| async move {
+            let origin = Identity {
+                session: None,
+                account_id: account_for_token(query.get("token")).await.map(|account| account.id),
+                ip: remote.map(|remote| remote.ip()),
+            };
+            if let Err(reason) = check_not_banned(&origin, None).await {
+                println!("Rejected lobby connection: {}", reason);
+                return Box::new(warp::reply::with_status(reason, warp::http::StatusCode::FORBIDDEN)) as Box<dyn warp::Reply>;
+            }
+            Box::new(ws.on_upgrade(move |socket| crate::lobby::handle_lobby_connection(socket, lobby, origin.ip))) as Box<dyn warp::Reply>
         });
     
-    // WebTransport endpoint route (HTTP/3 endpoint simulation)
+    // WebTransport port (HTTP/3 over UDP), default 8443
+    let webtransport_port: u16 = std::env::var("WEBTRANSPORT_PORT")
+        .unwrap_or_else(|_| "8443".to_string())
+        .parse()
+        .unwrap_or(8443);
+    
+    // WebTransport discovery route - tells clients where the HTTP/3 server is and which
+    // certificate hash to pin when it's running with a self-signed certificate
     let webtransport_route = warp::path("webtransport")
         .and(warp::get())
-        .map(|| {
-            println!("WebTransport endpoint accessed - simulating WebTransport support");
-            warp::reply::with_status(
-                "WebTransport endpoint ready (simulated)",
-                warp::http::StatusCode::OK
-            )
+        .map(move || {
+            println!("WebTransport endpoint info requested");
+            warp::reply::json(&serde_json::json!({
+                "port": webtransport_port,
+                "cert_hash": crate::webtransport_relay::CERT_HASH.get(),
+            }))
+        });
+    
+    // Saved replays and the replay viewer
+    let replay_list_route = warp::path!("replays")
+        .and(warp::get())
+        .map(|| warp::reply::json(&crate::replay::list_replays()));
+    
+    let replay_viewer_route = warp::path!("replays" / String / "ws")
+        .and(warp::ws())
+        .map(|name: String, ws: warp::ws::Ws| {
+            println!("Replay viewer connection request for {}", name);
+            ws.on_upgrade(move |socket| crate::replay::handle_replay_viewer(socket, name))
         });
     
     // Combine routes
     let routes = game_ws_route
         .or(fast_ws_route)
         .or(game_specific_route)
+        .or(game_fast_route)
         .or(lobby_ws_route)
         .or(webtransport_route)
+        .or(replay_list_route)
+        .or(replay_viewer_route)
         .with(warp::cors().allow_any_origin());
     
     println!("WebSocket server listening on ws://0.0.0.0:{}", port);
     println!("Fast channel route available at ws://0.0.0.0:{}/fast", port);
     println!("Lobby server available at ws://0.0.0.0:{}/lobby", port);
-    println!("Game-specific endpoints available at ws://0.0.0.0:{}/game/{{GAME_ID}}/ws", port);
-    println!("WebTransport ultra-low latency server starting on https://0.0.0.0:8443");
+    println!("Game-specific endpoints available at ws://0.0.0.0:{}/game/{{GAME_ID}}/ws and /game/{{GAME_ID}}/fast", port);
+    println!("Replays available at http://0.0.0.0:{}/replays and ws://0.0.0.0:{}/replays/{{NAME}}/ws", port, port);
+    println!("WebTransport ultra-low latency server starting on https://0.0.0.0:{}", webtransport_port);
     
     // Start the main game loop for the default game instance in a separate task
     tokio::spawn(async {
@@ -134,6 +262,9 @@ async fn main() {
         crate::game::game_update_loop(DUAL_CONNECTION_MANAGER.clone()).await;
     });
     
+    // Match players waiting in the lobby's match queue
+    tokio::spawn(crate::lobby::matchmaking_loop(LOBBY_MANAGER.clone()));
+    
     // Start a periodic task to clean up empty games (only for lobby-managed games)
     let lobby_for_cleanup = LOBBY_MANAGER.clone();
     tokio::spawn(async move {
@@ -154,7 +285,11 @@ async fn main() {
     
     // Start WebTransport Relay Server for ultra-low latency (inspired by snek game)
     tokio::spawn(async move {
-        let mut webtransport_relay = crate::webtransport_relay::WebTransportRelay::new(8443);
+        let mut webtransport_relay = crate::webtransport_relay::WebTransportRelay::new(
+            webtransport_port,
+            DUAL_CONNECTION_MANAGER.clone(),
+            LOBBY_MANAGER.clone(),
+        );
         
         if let Err(e) = webtransport_relay.start().await {
             eprintln!("❌ Failed to start WebTransport Relay: {}", e);
@@ -167,6 +302,21 @@ async fn main() {
         .await;
 }
 
+// Who a game connection comes from, for checking bans: its address, the lobby session its ticket
+// was issued to, and the account of its `?token=...`. A resuming player keeps the session and
+// account they connected with first.
+async fn connection_origin(query: &HashMap<String, String>, remote: Option<SocketAddr>, seat: Option<&Seat>, session: Option<String>) -> Identity {
+    let ip = remote.map(|remote| remote.ip());
+    match seat {
+        Some(Seat::Resume(claim)) => Identity { ip, ..claim.identity.clone() },
+        _ => Identity {
+            session,
+            account_id: account_for_token(query.get("token")).await.map(|account| account.id),
+            ip,
+        },
+    }
+}
+
 // Helper function to provide the lobby manager to the WebSocket handler
 pub fn with_lobby(lobby: Arc<Mutex<crate::lobby::LobbyManager>>) -> impl warp::Filter<Extract = (Arc<Mutex<crate::lobby::LobbyManager>>,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || lobby.clone())
@@ -0,0 +1,149 @@
+// This module handles loading arena maps from disk at startup.
+//
+// Maps live as JSON files in a directory (one file per map, the file stem is the map id)
+// using the same array-of-objects format that editor.py exports. The directory is taken
+// from the `--maps <dir>` command line flag, then the MAP_DIR environment variable, and
+// falls back to `./maps`, or to the `maps` directory next to this crate's sources when the
+// server isn't started from there.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use once_cell::sync::Lazy;
+use crate::sim::map::GameMap;
+
+// All maps loaded from the map directory, keyed by map id
+pub struct MapRegistry {
+    maps: HashMap<String, Arc<GameMap>>,
+    default_id: String,
+}
+
+impl MapRegistry {
+    pub fn load_dir(dir: &Path, default_id: Option<&str>) -> Result<Self, String> {
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read map directory {}: {}", dir.display(), e))?;
+
+        let mut maps = HashMap::new();
+        for entry in entries {
+            let path = entry.map_err(|e| format!("Failed to read map directory entry: {}", e))?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+
+            let map = GameMap::load_file(&path)?;
+            println!("Loaded map '{}' ({} objects) from {}", map.id, map.objects.len(), path.display());
+            maps.insert(map.id.clone(), Arc::new(map));
+        }
+
+        if maps.is_empty() {
+            return Err(format!("No maps found in {}", dir.display()));
+        }
+
+        let default_id = match default_id {
+            Some(id) if maps.contains_key(id) => id.to_string(),
+            Some(id) => return Err(format!("Default map '{}' not found in {}", id, dir.display())),
+            None if maps.contains_key("soccer") => "soccer".to_string(),
+            None => {
+                let mut ids: Vec<&String> = maps.keys().collect();
+                ids.sort();
+                ids[0].clone()
+            }
+        };
+
+        Ok(Self { maps, default_id })
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<GameMap>> {
+        self.maps.get(id).cloned()
+    }
+
+    pub fn default_map(&self) -> Arc<GameMap> {
+        self.maps[&self.default_id].clone()
+    }
+
+    // Sorted list of map ids
+    pub fn ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.maps.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+}
+
+// Resolve the map directory from `--maps <dir>`, then MAP_DIR, then ./maps, then ublike/maps
+fn map_dir_from_env() -> PathBuf {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(pos) = args.iter().position(|arg| arg == "--maps") {
+        if let Some(dir) = args.get(pos + 1) {
+            return PathBuf::from(dir);
+        }
+    }
+
+    if let Ok(dir) = std::env::var("MAP_DIR") {
+        return PathBuf::from(dir);
+    }
+    let local = PathBuf::from("maps");
+    if local.is_dir() {
+        return local;
+    }
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/maps"))
+}
+
+// Global map registry, loaded from disk the first time it is accessed
+pub static MAP_REGISTRY: Lazy<MapRegistry> = Lazy::new(|| {
+    let dir = map_dir_from_env();
+    let default_id = std::env::var("DEFAULT_MAP").ok();
+    MapRegistry::load_dir(&dir, default_id.as_deref())
+        .unwrap_or_else(|e| panic!("Failed to load maps: {}", e))
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOALS: &str = r#"{ "type": "goal_red", "x": 0, "y": 0, "width": 40, "height": 120 },
+        { "type": "goal_blue", "x": 100, "y": 0, "width": 40, "height": 120 }"#;
+
+    // A fresh directory holding the given map files
+    fn map_dir(files: &[(&str, String)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ublike-maps-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn loads_every_json_map_and_defaults_to_soccer() {
+        let dir = map_dir(&[
+            ("arena.json", format!("[{}]", GOALS)),
+            ("soccer.json", format!("[{}]", GOALS)),
+            ("notes.txt", "not a map".to_string()),
+        ]);
+        let registry = MapRegistry::load_dir(&dir, None).unwrap();
+        assert_eq!(registry.ids(), ["arena", "soccer"]);
+        assert_eq!(registry.default_map().id, "soccer");
+        assert_eq!(MapRegistry::load_dir(&dir, Some("arena")).unwrap().default_map().id, "arena");
+        assert!(MapRegistry::load_dir(&dir, Some("missing")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn one_bad_map_fails_the_load() {
+        let dir = map_dir(&[
+            ("soccer.json", format!("[{}]", GOALS)),
+            ("broken.json", format!(r#"[{}, {{ "type": "spike", "x": 0, "y": 0, "width": 1, "height": 1 }}]"#, GOALS)),
+        ]);
+        let error = MapRegistry::load_dir(&dir, None).err().unwrap();
+        assert!(error.contains("unknown type 'spike'"), "{}", error);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn empty_or_missing_directory_fails_the_load() {
+        let dir = map_dir(&[]);
+        assert!(MapRegistry::load_dir(&dir, None).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(MapRegistry::load_dir(&dir, None).is_err());
+    }
+}
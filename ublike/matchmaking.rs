@@ -0,0 +1,262 @@
+// This module groups players waiting in the lobby's match queue into balanced games.
+//
+// Players queue with the rating of the account they logged in with and their ping to the
+// server. The queue is scanned starting from whoever has waited longest: they're grouped with
+// the queued players closest to their rating that fall inside their rating and ping windows,
+// and once there are enough for a game the group is split into the two teams whose ratings are
+// closest. Both windows start narrow and widen the longer a player waits, and after
+// QUEUE_RELAX_SECS a player is matched with anyone, so nobody waits forever.
+
+use crate::sim::player::Team;
+
+// Players per team in a queued match
+pub const MATCH_TEAM_SIZE: usize = 2;
+
+// Rating difference accepted straight away, and how much it widens per second waited
+const RATING_WINDOW: f64 = 100.0;
+const RATING_WINDOW_GROWTH: f64 = 10.0;
+
+// Ping difference (ms) accepted straight away, and how much it widens per second waited
+const PING_WINDOW: f64 = 50.0;
+const PING_WINDOW_GROWTH: f64 = 2.0;
+
+// Seconds after which a player is matched regardless of rating and ping
+const QUEUE_RELAX_SECS: f64 = 90.0;
+
+#[derive(Debug, Clone)]
+pub struct QueuedPlayer {
+    pub client_id: String, // Lobby connection to notify
+    pub account_id: String,
+    pub name: String,
+    pub rating: f64,
+    pub ping_ms: Option<u32>, // As measured by the client; unknown pings match any ping
+    pub queued_at: u64, // Server time in ms
+}
+
+impl QueuedPlayer {
+    // How far from this player's rating and ping a match may be after waiting until `now_ms`
+    fn windows(&self, now_ms: u64) -> (f64, f64) {
+        let waited = now_ms.saturating_sub(self.queued_at) as f64 / 1000.0;
+        if waited >= QUEUE_RELAX_SECS {
+            return (f64::INFINITY, f64::INFINITY);
+        }
+        (RATING_WINDOW + RATING_WINDOW_GROWTH * waited, PING_WINDOW + PING_WINDOW_GROWTH * waited)
+    }
+
+    fn accepts(&self, other: &QueuedPlayer, now_ms: u64) -> bool {
+        let (rating_window, ping_window) = self.windows(now_ms);
+        let ping_close = match (self.ping_ms, other.ping_ms) {
+            (Some(mine), Some(theirs)) => (mine as f64 - theirs as f64).abs() <= ping_window,
+            _ => true,
+        };
+        (self.rating - other.rating).abs() <= rating_window && ping_close
+    }
+}
+
+// Players taken out of the queue to play together, with the team each one goes on
+pub struct FormedMatch {
+    pub players: Vec<(QueuedPlayer, Team)>,
+}
+
+pub struct MatchQueue {
+    players: Vec<QueuedPlayer>,
+}
+
+impl MatchQueue {
+    pub fn new() -> Self {
+        Self { players: Vec::new() }
+    }
+
+    // Add a player to the queue. Queueing again (from the same lobby connection or account)
+    // updates the entry but keeps the time they started waiting. Returns the queue length.
+    pub fn join(&mut self, player: QueuedPlayer) -> usize {
+        let existing = self.players.iter_mut()
+            .find(|queued| queued.client_id == player.client_id || queued.account_id == player.account_id);
+        match existing {
+            Some(queued) => *queued = QueuedPlayer { queued_at: queued.queued_at, ..player },
+            None => self.players.push(player),
+        }
+        self.players.len()
+    }
+
+    pub fn leave(&mut self, client_id: &str) -> bool {
+        let before = self.players.len();
+        self.players.retain(|queued| queued.client_id != client_id);
+        self.players.len() != before
+    }
+
+    // Take the next group that can play together out of the queue, if there is one
+    pub fn find_match(&mut self, now_ms: u64) -> Option<FormedMatch> {
+        let needed = MATCH_TEAM_SIZE * 2;
+        if self.players.len() < needed {
+            return None;
+        }
+
+        let mut by_wait: Vec<usize> = (0..self.players.len()).collect();
+        by_wait.sort_by_key(|&i| self.players[i].queued_at);
+
+        for anchor in by_wait {
+            let oldest = &self.players[anchor];
+            let mut group: Vec<usize> = (0..self.players.len())
+                .filter(|&i| i != anchor && oldest.accepts(&self.players[i], now_ms))
+                .collect();
+            if group.len() + 1 < needed {
+                continue;
+            }
+
+            // The closest ratings make the fairest game
+            group.sort_by(|&a, &b| {
+                let distance = |i: usize| (self.players[i].rating - oldest.rating).abs();
+                distance(a).total_cmp(&distance(b))
+            });
+            group.truncate(needed - 1);
+            group.push(anchor);
+
+            // Remove from the back so the remaining indices stay valid
+            group.sort_unstable_by(|a, b| b.cmp(a));
+            let players: Vec<QueuedPlayer> = group.into_iter().map(|i| self.players.remove(i)).collect();
+            return Some(FormedMatch { players: balance_teams(players) });
+        }
+        None
+    }
+}
+
+// Split the players into Red and Blue halves whose total ratings are as close as possible
+fn balance_teams(players: Vec<QueuedPlayer>) -> Vec<(QueuedPlayer, Team)> {
+    let count = players.len();
+    let total: f64 = players.iter().map(|player| player.rating).sum();
+
+    // Try every way of picking half the players for Red (with player 0 always on Red, since
+    // swapping the teams gives the same split)
+    let mut best_red = 0u32;
+    let mut best_difference = f64::INFINITY;
+    for red in 0u32..(1 << count) {
+        if red & 1 == 0 || red.count_ones() as usize != count / 2 {
+            continue;
+        }
+        let red_total: f64 = (0..count)
+            .filter(|i| red & (1 << i) != 0)
+            .map(|i| players[i].rating)
+            .sum();
+        let difference = (total - 2.0 * red_total).abs();
+        if difference < best_difference {
+            best_difference = difference;
+            best_red = red;
+        }
+    }
+
+    players.into_iter()
+        .enumerate()
+        .map(|(i, player)| {
+            let team = if best_red & (1 << i) != 0 { Team::Red } else { Team::Blue };
+            (player, team)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW_MS: u64 = 1_000_000;
+
+    fn queued(id: &str, rating: f64, ping_ms: Option<u32>, queued_at: u64) -> QueuedPlayer {
+        QueuedPlayer {
+            client_id: id.to_string(),
+            account_id: id.to_string(),
+            name: id.to_string(),
+            rating,
+            ping_ms,
+            queued_at,
+        }
+    }
+
+    fn queue_of(players: Vec<QueuedPlayer>) -> MatchQueue {
+        let mut queue = MatchQueue::new();
+        for player in players {
+            queue.join(player);
+        }
+        queue
+    }
+
+    #[test]
+    fn rating_window_widens_with_waiting() {
+        let mut queue = queue_of(vec![
+            queued("a", 1000.0, None, NOW_MS),
+            queued("b", 1050.0, None, NOW_MS),
+            queued("c", 1090.0, None, NOW_MS),
+            queued("d", 1300.0, None, NOW_MS),
+        ]);
+        assert!(queue.find_match(NOW_MS).is_none());
+        assert!(queue.find_match(NOW_MS + 10_000).is_none());
+        assert!(queue.find_match(NOW_MS + 20_000).is_some());
+    }
+
+    #[test]
+    fn ping_window_widens_with_waiting() {
+        let mut queue = queue_of(vec![
+            queued("a", 1000.0, Some(20), NOW_MS),
+            queued("b", 1000.0, Some(30), NOW_MS),
+            queued("c", 1000.0, Some(40), NOW_MS),
+            queued("d", 1000.0, Some(150), NOW_MS),
+        ]);
+        assert!(queue.find_match(NOW_MS).is_none());
+        assert!(queue.find_match(NOW_MS + 20_000).is_none());
+        assert!(queue.find_match(NOW_MS + 30_000).is_some());
+    }
+
+    #[test]
+    fn anyone_matches_after_waiting_long_enough() {
+        let mut queue = queue_of(vec![
+            queued("a", 1000.0, Some(20), NOW_MS),
+            queued("b", 1000.0, Some(30), NOW_MS),
+            queued("c", 1000.0, None, NOW_MS),
+            queued("d", 5000.0, Some(900), NOW_MS),
+        ]);
+        let relaxed_ms = (QUEUE_RELAX_SECS * 1000.0) as u64;
+        assert!(queue.find_match(NOW_MS + relaxed_ms - 1).is_none());
+        assert!(queue.find_match(NOW_MS + relaxed_ms).is_some());
+    }
+
+    #[test]
+    fn longest_waiting_player_gets_the_closest_ratings() {
+        let mut queue = queue_of(vec![
+            queued("e", 1090.0, None, NOW_MS + 4),
+            queued("a", 1000.0, None, NOW_MS),
+            queued("b", 1010.0, None, NOW_MS + 1),
+            queued("c", 1020.0, None, NOW_MS + 2),
+            queued("d", 1030.0, None, NOW_MS + 3),
+        ]);
+        let formed = queue.find_match(NOW_MS + 5).unwrap();
+        let mut ids: Vec<&str> = formed.players.iter().map(|(player, _)| player.client_id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, ["a", "b", "c", "d"]);
+        assert!(queue.find_match(NOW_MS + 5).is_none());
+        assert!(queue.leave("e"));
+    }
+
+    #[test]
+    fn queueing_again_keeps_the_original_wait() {
+        let mut queue = MatchQueue::new();
+        assert_eq!(queue.join(queued("a", 1000.0, None, NOW_MS)), 1);
+        assert_eq!(queue.join(queued("a", 1200.0, None, NOW_MS + 60_000)), 1);
+        assert_eq!(queue.players[0].queued_at, NOW_MS);
+        assert_eq!(queue.players[0].rating, 1200.0);
+    }
+
+    #[test]
+    fn teams_are_split_by_closest_total_rating() {
+        let players = vec![
+            queued("a", 1400.0, None, NOW_MS),
+            queued("b", 1200.0, None, NOW_MS),
+            queued("c", 1200.0, None, NOW_MS),
+            queued("d", 1000.0, None, NOW_MS),
+        ];
+        let teams = balance_teams(players);
+        let total = |team: Team| -> f64 {
+            teams.iter().filter(|(_, t)| *t == team).map(|(player, _)| player.rating).sum()
+        };
+        assert_eq!(teams.iter().filter(|(_, team)| *team == Team::Red).count(), MATCH_TEAM_SIZE);
+        assert_eq!(total(Team::Red), total(Team::Blue));
+    }
+}
@@ -0,0 +1,268 @@
+// This module keeps the server's bans and chat mutes.
+//
+// A ban or mute targets a lobby session, an account or an IP address, either on the whole server
+// or in one game, and either lasts for a set time or until it's lifted. Hosts ban and mute
+// within their own game (see host.rs); admins, the accounts listed in ADMIN_ACCOUNTS
+// (comma-separated account ids), do it server-wide from the lobby and count as host in every
+// game. Bans are checked when a lobby or game WebSocket is upgraded, so a banned client never
+// gets a connection; banning someone who is connected also closes their sockets. Mutes stop
+// a client chatting (see chat.rs).
+//
+// Everything is kept in a JSON file (BANS_FILE, default `bans.json`) that is rewritten in the
+// background (see persist.rs) whenever a ban or mute is added or lifted, so they survive
+// restarts. Expired entries are dropped the next time the file is written. Lobby games' ids are
+// reused after a restart, so bans and mutes in a lobby game only last as long as the game;
+// server-wide ones and those in the default game are kept.
+
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use crate::game::{now_ms, DEFAULT_GAME_ID};
+use crate::persist::JsonFile;
+
+const DEFAULT_BANS_FILE: &str = "bans.json";
+
+static ADMIN_ACCOUNTS: Lazy<Vec<String>> = Lazy::new(|| {
+    std::env::var("ADMIN_ACCOUNTS")
+        .map(|ids| ids.split(',').map(|id| id.trim().to_string()).filter(|id| !id.is_empty()).collect())
+        .unwrap_or_default()
+});
+
+pub fn is_admin(account_id: Option<&str>) -> bool {
+    account_id.is_some_and(|id| ADMIN_ACCOUNTS.iter().any(|admin| admin == id))
+}
+
+// Who a connection belongs to, as far as the server knows
+#[derive(Debug, Clone, Default)]
+pub struct Identity {
+    pub session: Option<String>, // Lobby client id, from the join ticket
+    pub account_id: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum BanTarget {
+    Session(String),
+    Account(String),
+    Ip(IpAddr),
+}
+
+impl BanTarget {
+    pub fn matches(&self, identity: &Identity) -> bool {
+        match self {
+            BanTarget::Session(session) => identity.session.as_ref() == Some(session),
+            BanTarget::Account(account_id) => identity.account_id.as_ref() == Some(account_id),
+            BanTarget::Ip(ip) => identity.ip == Some(*ip),
+        }
+    }
+}
+
+// Which part of a player's identity a host ban targets
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BanKind {
+    Session,
+    Account,
+    Ip,
+}
+
+impl BanKind {
+    // What a ban hits when the host doesn't say: the player's account, so reconnecting with a new
+    // session doesn't get around it, or their IP address if they're a guest
+    pub fn default_target(identity: &Identity) -> Option<BanTarget> {
+        [BanKind::Account, BanKind::Ip].into_iter().find_map(|kind| kind.target(identity))
+    }
+
+    pub fn target(self, identity: &Identity) -> Option<BanTarget> {
+        match self {
+            BanKind::Session => identity.session.clone().map(BanTarget::Session),
+            BanKind::Account => identity.account_id.clone().map(BanTarget::Account),
+            BanKind::Ip => identity.ip.map(BanTarget::Ip),
+        }
+    }
+}
+
+// A ban or a mute
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sanction {
+    pub target: BanTarget,
+    pub game_id: Option<String>, // Only in this game; None for the whole server
+    pub reason: String,
+    pub issued_by: String,
+    pub expires_at: Option<u64>, // ms since the epoch; None lasts until lifted
+}
+
+impl Sanction {
+    pub fn new(target: BanTarget, game_id: Option<String>, reason: Option<String>, issued_by: String, duration_secs: Option<u64>) -> Self {
+        Self {
+            target,
+            game_id,
+            reason: reason.unwrap_or_default(),
+            issued_by,
+            expires_at: duration_secs.map(|secs| now_ms() + secs * 1000),
+        }
+    }
+
+    fn active(&self, now_ms: u64) -> bool {
+        self.expires_at.is_none_or(|expires_at| now_ms < expires_at)
+    }
+
+    // Whether this applies to `identity` in `game_id` (None for the lobby)
+    fn applies(&self, identity: &Identity, game_id: Option<&str>, now_ms: u64) -> bool {
+        let in_scope = match &self.game_id {
+            Some(scope) => game_id == Some(scope.as_str()),
+            None => true,
+        };
+        in_scope && self.active(now_ms) && self.target.matches(identity)
+    }
+
+    // How the sanction is explained to the client it applies to
+    pub fn describe(&self, what: &str) -> String {
+        let mut text = what.to_string();
+        if let Some(until) = self.expires_at.and_then(|ms| chrono::DateTime::from_timestamp_millis(ms as i64)) {
+            text.push_str(&format!(" until {}", until.format("%Y-%m-%d %H:%M UTC")));
+        }
+        if !self.reason.is_empty() {
+            text.push_str(&format!(": {}", self.reason));
+        }
+        text
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ModerationStore {
+    #[serde(skip)]
+    file: JsonFile,
+    bans: Vec<Sanction>,
+    mutes: Vec<Sanction>,
+}
+
+impl ModerationStore {
+    // Load the bans and mutes saved at `path`; a missing file starts with none
+    pub fn load(path: PathBuf) -> Self {
+        let mut store: Self = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                println!("Failed to parse {} ({}), starting with no bans", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        let outlives_restart = |sanction: &Sanction| sanction.game_id.as_deref().is_none_or(|id| id == DEFAULT_GAME_ID);
+        store.bans.retain(outlives_restart);
+        store.mutes.retain(outlives_restart);
+        println!("Loaded {} bans and {} mutes from {}", store.bans.len(), store.mutes.len(), path.display());
+        Self { file: JsonFile::new(path), ..store }
+    }
+
+    // Write the active bans and mutes back to the file, the same way accounts are saved
+    fn save(&mut self) {
+        let now = now_ms();
+        self.bans.retain(|ban| ban.active(now));
+        self.mutes.retain(|mute| mute.active(now));
+        let json = match serde_json::to_string_pretty(self) {
+            Ok(json) => json,
+            Err(e) => {
+                println!("Failed to serialize bans: {}", e);
+                return;
+            }
+        };
+        self.file.save(json);
+    }
+
+    // Add a ban, replacing any ban on the same target in the same scope
+    pub fn ban(&mut self, ban: Sanction) {
+        println!("Banned {:?} (game: {:?}, by {})", ban.target, ban.game_id, ban.issued_by);
+        self.bans.retain(|existing| existing.target != ban.target || existing.game_id != ban.game_id);
+        self.bans.push(ban);
+        self.save();
+    }
+
+    pub fn mute(&mut self, mute: Sanction) {
+        println!("Muted {:?} (game: {:?}, by {})", mute.target, mute.game_id, mute.issued_by);
+        self.mutes.retain(|existing| existing.target != mute.target || existing.game_id != mute.game_id);
+        self.mutes.push(mute);
+        self.save();
+    }
+
+    // Lift the ban on `target` in `game_id` (None for a server-wide ban). Returns whether there was one.
+    pub fn unban(&mut self, target: &BanTarget, game_id: Option<&str>) -> bool {
+        let before = self.bans.len();
+        self.bans.retain(|ban| &ban.target != target || ban.game_id.as_deref() != game_id);
+        let lifted = self.bans.len() != before;
+        if lifted {
+            self.save();
+        }
+        lifted
+    }
+
+    pub fn unmute(&mut self, target: &BanTarget, game_id: Option<&str>) -> bool {
+        let before = self.mutes.len();
+        self.mutes.retain(|mute| &mute.target != target || mute.game_id.as_deref() != game_id);
+        let lifted = self.mutes.len() != before;
+        if lifted {
+            self.save();
+        }
+        lifted
+    }
+
+    // Drop the bans and mutes of a lobby game that has closed
+    pub fn forget_game(&mut self, game_id: &str) {
+        let before = self.bans.len() + self.mutes.len();
+        self.bans.retain(|ban| ban.game_id.as_deref() != Some(game_id));
+        self.mutes.retain(|mute| mute.game_id.as_deref() != Some(game_id));
+        if self.bans.len() + self.mutes.len() != before {
+            self.save();
+        }
+    }
+
+    // The ban keeping `identity` out of `game_id` (None for the lobby), if any
+    pub fn ban_for(&self, identity: &Identity, game_id: Option<&str>) -> Option<&Sanction> {
+        let now = now_ms();
+        self.bans.iter().find(|ban| ban.applies(identity, game_id, now))
+    }
+
+    // The mute keeping `identity` from chatting in `game_id` (None for the lobby), if any
+    pub fn mute_for(&self, identity: &Identity, game_id: Option<&str>) -> Option<&Sanction> {
+        let now = now_ms();
+        self.mutes.iter().find(|mute| mute.applies(identity, game_id, now))
+    }
+
+    // Active bans and mutes, for admins
+    pub fn active(&self) -> (Vec<Sanction>, Vec<Sanction>) {
+        let now = now_ms();
+        (
+            self.bans.iter().filter(|ban| ban.active(now)).cloned().collect(),
+            self.mutes.iter().filter(|mute| mute.active(now)).cloned().collect(),
+        )
+    }
+}
+
+// Global ban list, loaded from BANS_FILE on first use
+pub static MODERATION: Lazy<Arc<Mutex<ModerationStore>>> = Lazy::new(|| {
+    let path = std::env::var("BANS_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_BANS_FILE));
+    Arc::new(Mutex::new(ModerationStore::load(path)))
+});
+
+// Drop the bans and mutes of a lobby game that has closed. Callers hold the lobby lock, so the
+// ban list is waited for in the background.
+pub fn forget_closed_game(game_id: &str) {
+    let game_id = game_id.to_string();
+    tokio::spawn(async move {
+        MODERATION.lock().await.forget_game(&game_id);
+    });
+}
+
+// Refuse a connection from a banned client. `game_id` is None for the lobby.
+pub async fn check_not_banned(identity: &Identity, game_id: Option<&str>) -> Result<(), String> {
+    match MODERATION.lock().await.ban_for(identity, game_id) {
+        Some(ban) if ban.game_id.is_some() => Err(ban.describe("You are banned from this game")),
+        Some(ban) => Err(ban.describe("You are banned from this server")),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,44 @@
+// This module writes the server's JSON files (accounts and bans) without blocking the runtime.
+//
+// The owner serializes its state while it holds its own lock and hands the text to `JsonFile`,
+// which writes it on a blocking thread the way replays are saved: to a temporary file that is
+// then renamed over the old one, so a crash never leaves half a file behind. Writes are
+// numbered, and one that finishes after a newer write has landed is dropped, so the file always
+// ends up with the latest state.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+pub struct JsonFile {
+    path: PathBuf,
+    queued: u64,              // Number of the newest write handed off
+    written: Arc<Mutex<u64>>, // Number of the newest write on disk; held while writing
+}
+
+impl JsonFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, ..Self::default() }
+    }
+
+    // Write `json` to the file in the background, replacing anything written before it
+    pub fn save(&mut self, json: String) {
+        self.queued += 1;
+        let number = self.queued;
+        let path = self.path.clone();
+        let written = self.written.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut written = written.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if *written > number {
+                return;
+            }
+            let tmp_path = path.with_extension("tmp");
+            let result = std::fs::write(&tmp_path, json)
+                .and_then(|_| std::fs::rename(&tmp_path, &path));
+            match result {
+                Ok(()) => *written = number,
+                Err(e) => println!("Failed to save {}: {}", path.display(), e),
+            }
+        });
+    }
+}
@@ -0,0 +1,773 @@
+// This module contains the compact binary wire protocol.
+//
+// JSON stays the default (and the debugging fallback). Clients opt into binary after the
+// reliable `init` message by sending `{"type": "set_wire_format", "format": "binary", "version": N}`.
+// Binary frames are little-endian and start with a message tag and the protocol version.
+// Positions are quantized to 1/16 px in a u16, velocities to 1/8 px/s in an i16, and timers to
+// tenths of a second in a u8. Display names are not part of binary snapshots; they are sent
+// once through a `name_table` message whenever a client sees a new or renamed player.
+// Snapshots are full keyframes or deltas against a snapshot the client acked (see delta.rs).
+// Version 2 added the snapshot id after the tick in keyframes, so clients can ack them.
+// Version 3 added the match phase, half and clock after the scores.
+//
+// Player ids (including ball and rocket owners) are sent as u16; `Game::allocate_player_id`
+// keeps them in that range. The tick is sent as its low 32 bits, which wrap after more than two
+// years of continuous play at 60 Hz; the snapshot id and time are what identify a snapshot.
+
+use crate::sim::ball::Ball;
+use crate::sim::game::{GameStateSnapshot, Projectile};
+use crate::sim::match_state::{MatchPhase, MatchState};
+use crate::sim::player::{ShipState, Team};
+use crate::sim::input_buffer::InputCommand;
+use serde::Deserialize;
+
+pub const PROTOCOL_VERSION: u8 = 3;
+
+// Message tags (first byte of every binary frame)
+pub const TAG_SNAPSHOT: u8 = 0x01;
+pub const TAG_INPUT: u8 = 0x02;
+pub const TAG_SNAPSHOT_DELTA: u8 = 0x03;
+pub const TAG_SNAPSHOT_ACK: u8 = 0x04;
+
+const POSITION_SCALE: f32 = 16.0;
+const VELOCITY_SCALE: f32 = 8.0;
+const TIMER_SCALE: f32 = 10.0;
+
+// Input flag bits
+const INPUT_LEFT: u8 = 1 << 0;
+const INPUT_RIGHT: u8 = 1 << 1;
+const INPUT_UP: u8 = 1 << 2;
+const INPUT_DOWN: u8 = 1 << 3;
+const INPUT_SHOOT: u8 = 1 << 4;
+const INPUT_BOOST: u8 = 1 << 5;
+const INPUT_HAS_TARGET: u8 = 1 << 6;
+const INPUT_HAS_VIEW_TIME: u8 = 1 << 7;
+
+// Ball flag bits
+const BALL_ACTIVE: u8 = 1 << 0;
+const BALL_GRABBED: u8 = 1 << 1;
+const BALL_HAS_OWNER: u8 = 1 << 2;
+const BALL_HAS_LAST_SHOOTER: u8 = 1 << 3;
+const BALL_HAS_EXCLUSIVE_TEAM: u8 = 1 << 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireFormat {
+    Json,
+    Binary,
+}
+
+impl WireFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(WireFormat::Json),
+            "binary" => Some(WireFormat::Binary),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WireFormat::Json => "json",
+            WireFormat::Binary => "binary",
+        }
+    }
+}
+
+pub fn team_to_u8(team: Team) -> u8 {
+    match team {
+        Team::Red => 0,
+        Team::Blue => 1,
+        Team::Yellow => 2,
+        Team::Green => 3,
+    }
+}
+
+// Player ids on the wire; see the note at the top of this file
+fn wire_player_id(id: u32) -> u16 {
+    debug_assert!(id <= u16::MAX as u32, "player id {} doesn't fit in a u16", id);
+    id as u16
+}
+
+fn quantize_position(value: f32) -> u16 {
+    (value * POSITION_SCALE).round().clamp(0.0, u16::MAX as f32) as u16
+}
+
+fn dequantize_position(value: u16) -> f32 {
+    value as f32 / POSITION_SCALE
+}
+
+fn quantize_velocity(value: f32) -> i16 {
+    (value * VELOCITY_SCALE).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+fn quantize_timer(value: f32) -> u8 {
+    (value * TIMER_SCALE).round().clamp(0.0, u8::MAX as f32) as u8
+}
+
+// Minimal little-endian writer for building frames
+struct ByteWriter {
+    buf: Vec<u8>,
+}
+
+impl ByteWriter {
+    fn with_capacity(capacity: usize) -> Self {
+        Self { buf: Vec::with_capacity(capacity) }
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Low `width` bytes of a quantized field
+    fn field(&mut self, value: u32, width: usize) {
+        self.buf.extend_from_slice(&value.to_le_bytes()[..width]);
+    }
+}
+
+// Minimal little-endian reader that reports truncated frames as errors
+struct ByteReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos + len > self.buf.len() {
+            return Err(format!("Binary frame truncated at byte {}", self.pos));
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+// Quantized record fields in wire order, with their byte widths. Full snapshots write every
+// field; delta snapshots write a change mask per record followed by only the changed fields.
+const PLAYER_FIELD_WIDTHS: [usize; 6] = [2, 2, 4, 1, 1, 1];
+const BALL_FIELD_WIDTHS: [usize; 10] = [1, 2, 2, 2, 2, 2, 2, 1, 1, 1];
+const PROJECTILE_FIELD_WIDTHS: [usize; 6] = [2, 2, 2, 2, 2, 1];
+const MATCH_FIELD_WIDTHS: [usize; 3] = [1, 1, 2];
+
+fn player_fields(state: &ShipState) -> [u32; 6] {
+    [
+        quantize_position(state.x) as u32,
+        quantize_position(state.y) as u32,
+        state.seq,
+        state.fuel.round().clamp(0.0, 255.0) as u32,
+        team_to_u8(state.team) as u32,
+        quantize_timer(state.rocket_cooldown) as u32,
+    ]
+}
+
+fn ball_fields(ball: &Ball) -> [u32; 10] {
+    let exclusive_team = ball.exclusive_team.as_deref().and_then(|team| match team {
+        "Red" => Some(Team::Red),
+        "Blue" => Some(Team::Blue),
+        "Yellow" => Some(Team::Yellow),
+        "Green" => Some(Team::Green),
+        _ => None,
+    });
+
+    let mut flags = 0u8;
+    if ball.active { flags |= BALL_ACTIVE; }
+    if ball.grabbed { flags |= BALL_GRABBED; }
+    if ball.owner.is_some() { flags |= BALL_HAS_OWNER; }
+    if ball.last_shooter.is_some() { flags |= BALL_HAS_LAST_SHOOTER; }
+    if exclusive_team.is_some() { flags |= BALL_HAS_EXCLUSIVE_TEAM; }
+
+    [
+        flags as u32,
+        quantize_position(ball.x) as u32,
+        quantize_position(ball.y) as u32,
+        quantize_velocity(ball.vx) as u16 as u32,
+        quantize_velocity(ball.vy) as u16 as u32,
+        wire_player_id(ball.owner.unwrap_or(0)) as u32,
+        wire_player_id(ball.last_shooter.unwrap_or(0)) as u32,
+        quantize_timer(ball.shot_clock) as u32,
+        quantize_timer(ball.pickup_cooldown) as u32,
+        exclusive_team.map(team_to_u8).unwrap_or(0) as u32,
+    ]
+}
+
+fn projectile_fields(projectile: &Projectile) -> [u32; 6] {
+    [
+        quantize_position(projectile.x) as u32,
+        quantize_position(projectile.y) as u32,
+        quantize_velocity(projectile.vx) as u16 as u32,
+        quantize_velocity(projectile.vy) as u16 as u32,
+        wire_player_id(projectile.owner_id) as u32,
+        quantize_timer(projectile.lifetime) as u32,
+    ]
+}
+
+fn score_fields(snapshot: &GameStateSnapshot) -> [u32; 4] {
+    [
+        snapshot.team1_score.min(u16::MAX as u32),
+        snapshot.team2_score.min(u16::MAX as u32),
+        snapshot.team3_score.min(u16::MAX as u32),
+        snapshot.team4_score.min(u16::MAX as u32),
+    ]
+}
+
+fn match_phase_to_u8(phase: MatchPhase) -> u8 {
+    match phase {
+        MatchPhase::Warmup => 0,
+        MatchPhase::Countdown => 1,
+        MatchPhase::Live => 2,
+        MatchPhase::Halftime => 3,
+        MatchPhase::Overtime => 4,
+        MatchPhase::Finished => 5,
+    }
+}
+
+// Phase, half and the clock in tenths of a second
+fn match_fields(state: &MatchState) -> [u32; 3] {
+    [
+        match_phase_to_u8(state.phase) as u32,
+        state.half.min(u8::MAX as u32),
+        (state.clock * TIMER_SCALE).round().clamp(0.0, u16::MAX as f32) as u32,
+    ]
+}
+
+fn write_fields(w: &mut ByteWriter, fields: &[u32], widths: &[usize]) {
+    for (value, width) in fields.iter().zip(widths) {
+        w.field(*value, *width);
+    }
+}
+
+// Bit i of the mask is set when field i differs from the baseline (all bits for new records)
+fn change_mask(baseline: Option<&[u32]>, fields: &[u32]) -> u16 {
+    fields.iter().enumerate().fold(0u16, |mask, (i, value)| match baseline {
+        Some(base) if base[i] == *value => mask,
+        _ => mask | (1 << i),
+    })
+}
+
+// Write a change mask (one byte for records of up to 8 fields, two otherwise) and the changed fields
+fn write_changed_fields(w: &mut ByteWriter, mask: u16, fields: &[u32], widths: &[usize]) {
+    if widths.len() <= 8 {
+        w.u8(mask as u8);
+    } else {
+        w.u16(mask);
+    }
+    for (i, (value, width)) in fields.iter().zip(widths).enumerate() {
+        if mask & (1 << i) != 0 {
+            w.field(*value, *width);
+        }
+    }
+}
+
+// Encode a snapshot as a full (keyframe) binary frame
+pub fn encode_snapshot(snapshot: &GameStateSnapshot) -> Vec<u8> {
+    let mut w = ByteWriter::with_capacity(40 + snapshot.players.len() * 13 + snapshot.projectiles.len() * 15);
+    w.u8(TAG_SNAPSHOT);
+    w.u8(PROTOCOL_VERSION);
+    w.u32(snapshot.tick as u32); // Low 32 bits, see the note at the top of this file
+    w.u32(snapshot.snapshot_id);
+    w.u64(snapshot.time);
+
+    w.u8(snapshot.players.len().min(u8::MAX as usize) as u8);
+    for (id, state) in snapshot.players.iter().take(u8::MAX as usize) {
+        w.u16(wire_player_id(*id));
+        write_fields(&mut w, &player_fields(state), &PLAYER_FIELD_WIDTHS);
+    }
+
+    write_fields(&mut w, &ball_fields(&snapshot.ball), &BALL_FIELD_WIDTHS);
+
+    w.u16(snapshot.projectiles.len().min(u16::MAX as usize) as u16);
+    for projectile in snapshot.projectiles.iter().take(u16::MAX as usize) {
+        w.u32(projectile.id);
+        write_fields(&mut w, &projectile_fields(projectile), &PROJECTILE_FIELD_WIDTHS);
+    }
+
+    for score in score_fields(snapshot) {
+        w.u16(score as u16);
+    }
+
+    write_fields(&mut w, &match_fields(&snapshot.match_state), &MATCH_FIELD_WIDTHS);
+
+    w.buf
+}
+
+// Encode a snapshot as a delta against a baseline the client has acked. Layout after the header
+// (tag, version, tick, snapshot id, baseline id, time): changed players (u8 count of id + mask + fields),
+// removed player ids (u8 count), ball mask + fields, changed projectiles (u16 count of id + mask +
+// fields), removed projectile ids (u16 count), a score mask + changed scores, then a match mask +
+// changed match fields.
+pub fn encode_delta(baseline: &GameStateSnapshot, snapshot: &GameStateSnapshot) -> Vec<u8> {
+    let mut w = ByteWriter::with_capacity(64);
+    w.u8(TAG_SNAPSHOT_DELTA);
+    w.u8(PROTOCOL_VERSION);
+    w.u32(snapshot.tick as u32); // Low 32 bits, as in keyframes
+    w.u32(snapshot.snapshot_id);
+    w.u32(baseline.snapshot_id);
+    w.u64(snapshot.time);
+
+    // Players
+    let mut changed = ByteWriter::with_capacity(snapshot.players.len() * 8);
+    let mut changed_count = 0usize;
+    for (id, state) in snapshot.players.iter() {
+        let fields = player_fields(state);
+        let base_fields = baseline.players.get(id).map(player_fields);
+        let mask = change_mask(base_fields.as_ref().map(|f| &f[..]), &fields);
+        if mask != 0 && changed_count < u8::MAX as usize {
+            changed.u16(wire_player_id(*id));
+            write_changed_fields(&mut changed, mask, &fields, &PLAYER_FIELD_WIDTHS);
+            changed_count += 1;
+        }
+    }
+    w.u8(changed_count as u8);
+    w.buf.extend_from_slice(&changed.buf);
+
+    let removed: Vec<u32> = baseline.players.keys()
+        .filter(|id| !snapshot.players.contains_key(id))
+        .copied()
+        .take(u8::MAX as usize)
+        .collect();
+    w.u8(removed.len() as u8);
+    for id in removed {
+        w.u16(wire_player_id(id));
+    }
+
+    // Ball
+    let ball = ball_fields(&snapshot.ball);
+    let ball_mask = change_mask(Some(&ball_fields(&baseline.ball)), &ball);
+    write_changed_fields(&mut w, ball_mask, &ball, &BALL_FIELD_WIDTHS);
+
+    // Projectiles
+    let mut changed = ByteWriter::with_capacity(snapshot.projectiles.len() * 8);
+    let mut changed_count = 0usize;
+    for projectile in snapshot.projectiles.iter() {
+        let fields = projectile_fields(projectile);
+        let base_fields = baseline.projectiles.iter()
+            .find(|p| p.id == projectile.id)
+            .map(projectile_fields);
+        let mask = change_mask(base_fields.as_ref().map(|f| &f[..]), &fields);
+        if mask != 0 && changed_count < u16::MAX as usize {
+            changed.u32(projectile.id);
+            write_changed_fields(&mut changed, mask, &fields, &PROJECTILE_FIELD_WIDTHS);
+            changed_count += 1;
+        }
+    }
+    w.u16(changed_count as u16);
+    w.buf.extend_from_slice(&changed.buf);
+
+    let removed: Vec<u32> = baseline.projectiles.iter()
+        .filter(|base| !snapshot.projectiles.iter().any(|p| p.id == base.id))
+        .map(|base| base.id)
+        .take(u16::MAX as usize)
+        .collect();
+    w.u16(removed.len() as u16);
+    for id in removed {
+        w.u32(id);
+    }
+
+    // Scores
+    let scores = score_fields(snapshot);
+    let score_mask = change_mask(Some(&score_fields(baseline)), &scores);
+    write_changed_fields(&mut w, score_mask, &scores, &[2, 2, 2, 2]);
+
+    // Match phase and clock
+    let match_state = match_fields(&snapshot.match_state);
+    let match_mask = change_mask(Some(&match_fields(&baseline.match_state)), &match_state);
+    write_changed_fields(&mut w, match_mask, &match_state, &MATCH_FIELD_WIDTHS);
+
+    w.buf
+}
+
+// A client's input, from a JSON input message or a binary input frame
+#[derive(Deserialize, Debug)]
+pub struct InputMessage {
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    pub seq: u32,
+    pub shoot: Option<bool>,
+    pub boost: Option<bool>,
+    pub target_x: Option<f32>,
+    pub target_y: Option<f32>,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub view_time: Option<u64>, // Server time (ms) of the state the client is rendering
+    #[serde(default)]
+    pub previous: Vec<InputCommand>, // Redundant copies of the client's last few commands
+}
+
+impl InputMessage {
+    // This message's command followed by the redundant earlier ones
+    pub fn commands(&self) -> Vec<InputCommand> {
+        let mut commands = self.previous.clone();
+        commands.push(InputCommand {
+            seq: self.seq,
+            left: self.left,
+            right: self.right,
+            up: self.up,
+            down: self.down,
+            shoot: self.shoot.unwrap_or(false),
+            boost: self.boost.unwrap_or(false),
+            target_x: self.target_x,
+            target_y: self.target_y,
+        });
+        commands
+    }
+}
+
+// Binary frames a client can send
+pub enum ClientFrame {
+    Input(InputMessage),
+    SnapshotAck(u32),
+}
+
+// Decode a binary client frame. Every frame starts with its tag and the protocol version;
+// a snapshot ack is followed by the acked snapshot id (u32).
+pub fn decode_client_frame(frame: &[u8]) -> Result<ClientFrame, String> {
+    let mut r = ByteReader::new(frame);
+    let tag = r.u8()?;
+    let version = r.u8()?;
+    if version != PROTOCOL_VERSION {
+        return Err(format!("Unsupported binary protocol version {}", version));
+    }
+
+    match tag {
+        TAG_INPUT => Ok(ClientFrame::Input(decode_input(&mut r)?)),
+        TAG_SNAPSHOT_ACK => Ok(ClientFrame::SnapshotAck(r.u32()?)),
+        _ => Err(format!("Unexpected binary message tag {:#04x}", tag)),
+    }
+}
+
+// Decode the body of a binary input frame: seq (u32), flags (u8), then target x/y (u16 each)
+// only when the has-target flag is set and the view time (u64) only when its flag is set.
+// Optionally followed by a u8 count of redundant earlier commands, each encoded as seq, flags
+// and target the same way.
+fn decode_input(r: &mut ByteReader) -> Result<InputMessage, String> {
+    let (command, flags) = decode_command(r)?;
+    let view_time = if flags & INPUT_HAS_VIEW_TIME != 0 { Some(r.u64()?) } else { None };
+
+    let mut previous = Vec::new();
+    if !r.is_empty() {
+        let count = r.u8()?;
+        for _ in 0..count {
+            previous.push(decode_command(r)?.0);
+        }
+    }
+
+    Ok(InputMessage {
+        left: command.left,
+        right: command.right,
+        up: command.up,
+        down: command.down,
+        seq: command.seq,
+        shoot: Some(command.shoot),
+        boost: Some(command.boost),
+        target_x: command.target_x,
+        target_y: command.target_y,
+        display_name: String::new(),
+        view_time,
+        previous,
+    })
+}
+
+// Decode seq, flags and the optional target of one input command
+fn decode_command(r: &mut ByteReader) -> Result<(InputCommand, u8), String> {
+    let seq = r.u32()?;
+    let flags = r.u8()?;
+    let (target_x, target_y) = if flags & INPUT_HAS_TARGET != 0 {
+        (Some(dequantize_position(r.u16()?)), Some(dequantize_position(r.u16()?)))
+    } else {
+        (None, None)
+    };
+
+    let command = InputCommand {
+        seq,
+        left: flags & INPUT_LEFT != 0,
+        right: flags & INPUT_RIGHT != 0,
+        up: flags & INPUT_UP != 0,
+        down: flags & INPUT_DOWN != 0,
+        shoot: flags & INPUT_SHOOT != 0,
+        boost: flags & INPUT_BOOST != 0,
+        target_x,
+        target_y,
+    };
+    Ok((command, flags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn ship(x: f32, y: f32, team: Team) -> ShipState {
+        ShipState { x, y, seq: 41, fuel: 80.4, team, display_name: String::new(), rocket_cooldown: 0.25 }
+    }
+
+    fn ball(x: f32, y: f32, owner: Option<u32>) -> Ball {
+        Ball {
+            x,
+            y,
+            vx: 12.3,
+            vy: -4.0,
+            active: true,
+            grabbed: owner.is_some(),
+            grab_cooldown: 0.0,
+            owner,
+            last_shooter: None,
+            shot_clock: 3.0,
+            pickup_cooldown: 0.0,
+            exclusive_team: Some("Blue".to_string()),
+        }
+    }
+
+    fn snapshot(snapshot_id: u32, players: Vec<(u32, ShipState)>, projectiles: Vec<Projectile>) -> GameStateSnapshot {
+        GameStateSnapshot {
+            tick: 600,
+            snapshot_id,
+            time: 1_700_000_000_000,
+            players: players.into_iter().collect::<HashMap<_, _>>(),
+            ball: ball(1000.0, 600.0, None),
+            projectiles,
+            team1_score: 2,
+            team2_score: 1,
+            team3_score: 0,
+            team4_score: 0,
+            match_state: MatchState { phase: MatchPhase::Live, half: 2, clock: 95.25, forced: false },
+        }
+    }
+
+    fn read_fields<const N: usize>(r: &mut ByteReader, widths: &[usize; N]) -> [u32; N] {
+        let mut fields = [0u32; N];
+        for (field, width) in fields.iter_mut().zip(widths) {
+            let mut bytes = [0u8; 4];
+            bytes[..*width].copy_from_slice(r.take(*width).unwrap());
+            *field = u32::from_le_bytes(bytes);
+        }
+        fields
+    }
+
+    // A keyframe read back field by field
+    struct Keyframe {
+        tick: u32,
+        snapshot_id: u32,
+        time: u64,
+        players: HashMap<u16, [u32; 6]>,
+        ball: [u32; 10],
+        projectiles: Vec<(u32, [u32; 6])>,
+        scores: [u16; 4],
+        match_state: [u32; 3],
+    }
+
+    fn decode_keyframe(frame: &[u8]) -> Keyframe {
+        let mut r = ByteReader::new(frame);
+        assert_eq!(r.u8().unwrap(), TAG_SNAPSHOT);
+        assert_eq!(r.u8().unwrap(), PROTOCOL_VERSION);
+        let (tick, snapshot_id, time) = (r.u32().unwrap(), r.u32().unwrap(), r.u64().unwrap());
+        let players = (0..r.u8().unwrap())
+            .map(|_| (r.u16().unwrap(), read_fields(&mut r, &PLAYER_FIELD_WIDTHS)))
+            .collect();
+        let ball = read_fields(&mut r, &BALL_FIELD_WIDTHS);
+        let projectiles = (0..r.u16().unwrap())
+            .map(|_| (r.u32().unwrap(), read_fields(&mut r, &PROJECTILE_FIELD_WIDTHS)))
+            .collect();
+        let scores = [r.u16().unwrap(), r.u16().unwrap(), r.u16().unwrap(), r.u16().unwrap()];
+        let match_state = read_fields(&mut r, &MATCH_FIELD_WIDTHS);
+        assert!(r.is_empty(), "trailing bytes after the keyframe");
+        Keyframe { tick, snapshot_id, time, players, ball, projectiles, scores, match_state }
+    }
+
+    #[test]
+    fn keyframe_round_trips() {
+        let mut rocket = Projectile::new(9, 500.0, 250.5, -300.0, 0.0, 3);
+        rocket.lifetime = 1.5;
+        let mut snapshot = snapshot(77, vec![(3, ship(123.4, 56.78, Team::Blue)), (65_535, ship(0.0, 0.0, Team::Red))], vec![rocket]);
+        snapshot.tick = (1 << 32) + 5;
+        snapshot.ball = ball(1000.0, 600.0, Some(3));
+        let keyframe = decode_keyframe(&encode_snapshot(&snapshot));
+
+        // The tick is sent as its low 32 bits
+        assert_eq!((keyframe.tick, keyframe.snapshot_id, keyframe.time), (5, 77, 1_700_000_000_000));
+        let player = keyframe.players[&3];
+        assert!((dequantize_position(player[0] as u16) - 123.4).abs() <= 0.5 / POSITION_SCALE);
+        assert!((dequantize_position(player[1] as u16) - 56.78).abs() <= 0.5 / POSITION_SCALE);
+        assert_eq!(&player[2..], &[41, 80, team_to_u8(Team::Blue) as u32, 3]);
+        assert!(keyframe.players.contains_key(&65_535));
+
+        assert_eq!(keyframe.ball[0] as u8, BALL_ACTIVE | BALL_GRABBED | BALL_HAS_OWNER | BALL_HAS_EXCLUSIVE_TEAM);
+        assert_eq!(keyframe.ball[3] as u16 as i16, 98); // 12.3 px/s in eighths
+        assert_eq!(keyframe.ball[4] as u16 as i16, -32);
+        assert_eq!(keyframe.ball[5], 3);
+        assert_eq!(keyframe.ball[9], team_to_u8(Team::Blue) as u32);
+
+        assert_eq!(keyframe.projectiles.len(), 1);
+        let (id, fields) = keyframe.projectiles[0];
+        assert_eq!(id, 9);
+        assert_eq!(dequantize_position(fields[1] as u16), 250.5);
+        assert_eq!(fields[2] as u16 as i16, -2400);
+        assert_eq!(&fields[4..], &[3, 15]);
+
+        assert_eq!(keyframe.scores, [2, 1, 0, 0]);
+        assert_eq!(keyframe.match_state, [match_phase_to_u8(MatchPhase::Live) as u32, 2, 953]);
+    }
+
+    #[test]
+    fn out_of_range_values_are_clamped() {
+        let mut far = ship(-20.0, 5000.0, Team::Red);
+        far.fuel = 900.0;
+        far.rocket_cooldown = 60.0;
+        let mut snapshot = snapshot(1, vec![(1, far)], Vec::new());
+        snapshot.ball.vx = 1e9;
+        snapshot.ball.vy = -1e9;
+        snapshot.team1_score = 70_000;
+        snapshot.match_state.clock = 1e9;
+        let keyframe = decode_keyframe(&encode_snapshot(&snapshot));
+
+        let player = keyframe.players[&1];
+        assert_eq!(dequantize_position(player[0] as u16), 0.0);
+        assert_eq!(dequantize_position(player[1] as u16), u16::MAX as f32 / POSITION_SCALE);
+        assert_eq!((player[3], player[5]), (255, 255));
+        assert_eq!(keyframe.ball[3] as u16 as i16, i16::MAX);
+        assert_eq!(keyframe.ball[4] as u16 as i16, i16::MIN);
+        assert_eq!(keyframe.scores[0], u16::MAX);
+        assert_eq!(keyframe.match_state[2], u16::MAX as u32);
+    }
+
+    // The fields a delta record carries, by index, after its change mask
+    fn read_changed_fields(r: &mut ByteReader, widths: &[usize]) -> Vec<(usize, u32)> {
+        let mask = if widths.len() <= 8 { r.u8().unwrap() as u16 } else { r.u16().unwrap() };
+        (0..widths.len())
+            .filter(|i| mask & (1 << i) != 0)
+            .map(|i| {
+                let mut bytes = [0u8; 4];
+                bytes[..widths[i]].copy_from_slice(r.take(widths[i]).unwrap());
+                (i, u32::from_le_bytes(bytes))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn delta_frame_carries_changes_and_removals() {
+        let baseline = snapshot(10, vec![(1, ship(10.0, 10.0, Team::Red)), (2, ship(20.0, 20.0, Team::Blue))], vec![
+            Projectile::new(7, 0.0, 0.0, 1.0, 0.0, 1),
+            Projectile::new(8, 5.0, 5.0, 1.0, 0.0, 2),
+        ]);
+        let mut current = snapshot(12, vec![(1, ship(10.0, 12.0, Team::Red)), (3, ship(30.0, 30.0, Team::Red))], vec![
+            Projectile::new(8, 5.0, 5.0, 1.0, 0.0, 2),
+        ]);
+        current.team2_score = 2;
+        current.match_state.clock = 94.0;
+        let frame = encode_delta(&baseline, &current);
+
+        let mut r = ByteReader::new(&frame);
+        assert_eq!((r.u8().unwrap(), r.u8().unwrap()), (TAG_SNAPSHOT_DELTA, PROTOCOL_VERSION));
+        assert_eq!((r.u32().unwrap(), r.u32().unwrap(), r.u32().unwrap()), (600, 12, 10));
+        r.u64().unwrap();
+
+        // Player 1 only moved down, player 3 is new and player 2 is gone
+        let changed: HashMap<u16, Vec<(usize, u32)>> = (0..r.u8().unwrap())
+            .map(|_| (r.u16().unwrap(), read_changed_fields(&mut r, &PLAYER_FIELD_WIDTHS)))
+            .collect();
+        assert_eq!(changed[&1], [(1, quantize_position(12.0) as u32)]);
+        assert_eq!(changed[&3].len(), PLAYER_FIELD_WIDTHS.len());
+        assert_eq!(changed.len(), 2);
+        assert_eq!(r.u8().unwrap(), 1);
+        assert_eq!(r.u16().unwrap(), 2);
+
+        assert!(read_changed_fields(&mut r, &BALL_FIELD_WIDTHS).is_empty());
+
+        // Rocket 8 didn't change and rocket 7 is gone
+        assert_eq!(r.u16().unwrap(), 0);
+        assert_eq!(r.u16().unwrap(), 1);
+        assert_eq!(r.u32().unwrap(), 7);
+
+        assert_eq!(read_changed_fields(&mut r, &[2, 2, 2, 2]), [(1, 2)]);
+        assert_eq!(read_changed_fields(&mut r, &MATCH_FIELD_WIDTHS), [(2, 940)]);
+        assert!(r.is_empty());
+    }
+
+    // Seq, flags and an optional quantized target, the way clients write one command
+    fn write_command(w: &mut ByteWriter, seq: u32, flags: u8, target: Option<(u16, u16)>) {
+        w.u32(seq);
+        w.u8(flags | if target.is_some() { INPUT_HAS_TARGET } else { 0 });
+        if let Some((x, y)) = target {
+            w.u16(x);
+            w.u16(y);
+        }
+    }
+
+    #[test]
+    fn input_frame_round_trips() {
+        let mut w = ByteWriter::with_capacity(64);
+        w.u8(TAG_INPUT);
+        w.u8(PROTOCOL_VERSION);
+        write_command(&mut w, 12, INPUT_LEFT | INPUT_SHOOT | INPUT_HAS_VIEW_TIME, Some((1600, u16::MAX)));
+        w.u64(123_456);
+        w.u8(2);
+        write_command(&mut w, 10, INPUT_UP, None);
+        write_command(&mut w, 11, INPUT_RIGHT | INPUT_BOOST, Some((0, 8)));
+
+        let Ok(ClientFrame::Input(input)) = decode_client_frame(&w.buf) else { panic!("not an input frame") };
+        assert_eq!(input.seq, 12);
+        assert!(input.left && !input.right && !input.up && !input.down);
+        assert_eq!((input.shoot, input.boost), (Some(true), Some(false)));
+        assert_eq!((input.target_x, input.target_y), (Some(100.0), Some(u16::MAX as f32 / POSITION_SCALE)));
+        assert_eq!(input.view_time, Some(123_456));
+
+        let commands = input.commands();
+        assert_eq!(commands.iter().map(|c| c.seq).collect::<Vec<_>>(), [10, 11, 12]);
+        assert!(commands[0].up && commands[0].target_x.is_none());
+        assert!(commands[1].right && commands[1].boost);
+        assert_eq!((commands[1].target_x, commands[1].target_y), (Some(0.0), Some(0.5)));
+    }
+
+    #[test]
+    fn ack_frame_round_trips_and_bad_frames_are_rejected() {
+        let mut ack = vec![TAG_SNAPSHOT_ACK, PROTOCOL_VERSION];
+        ack.extend_from_slice(&0xDEAD_BEEFu32.to_le_bytes());
+        assert!(matches!(decode_client_frame(&ack), Ok(ClientFrame::SnapshotAck(0xDEAD_BEEF))));
+
+        assert!(decode_client_frame(&ack[..4]).is_err());
+        assert!(decode_client_frame(&[TAG_SNAPSHOT_ACK, PROTOCOL_VERSION - 1, 1, 0, 0, 0]).is_err());
+        assert!(decode_client_frame(&[TAG_SNAPSHOT, PROTOCOL_VERSION]).is_err());
+        assert!(decode_client_frame(&[TAG_INPUT, PROTOCOL_VERSION, 1, 0, 0, 0, INPUT_HAS_TARGET, 0]).is_err());
+    }
+}
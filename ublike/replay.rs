@@ -0,0 +1,237 @@
+// This module saves recorded matches to disk and plays them back to viewers.
+//
+// Recording is turned on by setting REPLAY_DIR. A game records while it has players; the replay
+// ends when the game is reset or empties and is written to `{REPLAY_DIR}/{game}_{start}.replay`.
+// `GET /replays` lists the saved replays and a WebSocket on `/replays/{name}/ws` re-simulates one
+// and streams it like a live game (game_state snapshots plus events). Viewers control playback with
+// `{"type": "replay_control", "speed": 0.5 | 1 | 4, "seek": TICK, "paused": true | false}`.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use futures::{SinkExt, StreamExt};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::time::{Duration, Instant};
+use warp::ws::{Message, WebSocket};
+use crate::game::Game;
+use crate::sim::replay::{Replay, ReplayPlayer};
+
+const REPLAY_EXTENSION: &str = "replay";
+
+// Playback speeds viewers can pick
+const REPLAY_SPEEDS: [f32; 3] = [0.5, 1.0, 4.0];
+
+// Directory replays are saved to; recording is off when REPLAY_DIR isn't set
+pub static REPLAY_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    let dir = std::env::var("REPLAY_DIR").ok().map(PathBuf::from);
+    match &dir {
+        Some(dir) => println!("Recording replays to {}", dir.display()),
+        None => println!("Replay recording disabled (set REPLAY_DIR to enable)"),
+    }
+    dir
+});
+
+// Start or stop recording as players come and go, and save replays that have finished.
+// Called by the game loops before each step.
+pub fn update_recording(game: &mut Game, label: &str, fixed_dt: f32, game_width: f32, game_height: f32) {
+    let dir = match REPLAY_DIR.as_ref() {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    if game.players.is_empty() {
+        game.stop_recording();
+    } else if !game.is_recording() {
+        game.start_recording(fixed_dt, game_width, game_height);
+    }
+
+    for replay in game.take_finished_replays() {
+        save_replay(dir.clone(), label, replay);
+    }
+}
+
+fn save_replay(dir: PathBuf, label: &str, replay: Replay) {
+    if replay.ticks.is_empty() {
+        return;
+    }
+
+    let path = dir.join(format!("{}_{}.{}", label, replay.started_at_ms(), REPLAY_EXTENSION));
+    tokio::task::spawn_blocking(move || {
+        let result = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(&path, replay.to_jsonl()));
+        match result {
+            Ok(()) => println!("Saved replay ({} ticks) to {}", replay.ticks.len(), path.display()),
+            Err(e) => println!("Failed to save replay to {}: {}", path.display(), e),
+        }
+    });
+}
+
+// Names of the saved replays, oldest first
+pub fn list_replays() -> Vec<String> {
+    let dir = match REPLAY_DIR.as_ref() {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .map(|entries| entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| name.ends_with(&format!(".{}", REPLAY_EXTENSION)))
+            .collect())
+        .unwrap_or_default();
+    names.sort_by_key(|name| replay_start(name));
+    names
+}
+
+// Start time encoded in a replay file name, for sorting
+fn replay_start(name: &str) -> u64 {
+    name.trim_end_matches(&format!(".{}", REPLAY_EXTENSION))
+        .rsplit('_')
+        .next()
+        .and_then(|start| start.parse().ok())
+        .unwrap_or(0)
+}
+
+fn load_replay(name: &str) -> Result<Replay, String> {
+    let dir = REPLAY_DIR.as_ref().ok_or("Replays are not enabled on this server")?;
+
+    // Only plain file names from list_replays(), never paths
+    let valid_name = name.ends_with(&format!(".{}", REPLAY_EXTENSION))
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+        && !name.starts_with('.');
+    if !valid_name {
+        return Err(format!("Invalid replay name: {}", name));
+    }
+
+    let text = std::fs::read_to_string(dir.join(name))
+        .map_err(|e| format!("Failed to read replay {}: {}", name, e))?;
+    Replay::from_jsonl(&text)
+}
+
+#[derive(Deserialize, Debug)]
+struct ReplayControlMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    speed: Option<f32>,
+    seek: Option<usize>, // Tick to jump to
+    paused: Option<bool>,
+}
+
+fn snapshot_message(game: &Game) -> Message {
+    let snapshot = serde_json::to_value(game.create_snapshot()).unwrap_or(serde_json::Value::Null);
+    Message::text(snapshot.to_string())
+}
+
+fn position_message(player: &ReplayPlayer, speed: f32, paused: bool) -> Message {
+    Message::text(json!({
+        "type": "replay_position",
+        "tick": player.position(),
+        "total_ticks": player.len(),
+        "speed": speed,
+        "paused": paused
+    }).to_string())
+}
+
+// Stream a saved replay to one viewer
+pub async fn handle_replay_viewer(ws: WebSocket, name: String) {
+    let (mut tx, mut rx) = ws.split();
+
+    let player = load_replay(&name).and_then(|replay| ReplayPlayer::new(Arc::new(replay)));
+    let mut player = match player {
+        Ok(player) => player,
+        Err(e) => {
+            println!("Replay viewer: {}", e);
+            let _ = tx.send(Message::text(json!({ "type": "error", "message": e }).to_string())).await;
+            return;
+        }
+    };
+
+    let header = &player.replay().header;
+    let tick_duration = Duration::from_secs_f32(header.fixed_dt);
+    let info = json!({
+        "type": "replay_info",
+        "name": name,
+        "map_id": header.map_id,
+        "started_at": player.replay().started_at_ms(),
+        "total_ticks": player.len(),
+        "tick_rate": (1.0 / header.fixed_dt).round(),
+        "speeds": REPLAY_SPEEDS
+    });
+    if tx.send(Message::text(info.to_string())).await.is_err() {
+        return;
+    }
+    println!("Replay viewer started for {} ({} ticks)", name, player.len());
+
+    let mut speed = 1.0;
+    let mut paused = false;
+    let mut next_tick = Instant::now();
+
+    loop {
+        let playing = !paused && !player.is_finished();
+        tokio::select! {
+            message = rx.next() => {
+                let message = match message {
+                    Some(Ok(message)) => message,
+                    _ => break,
+                };
+                if message.is_close() {
+                    break;
+                }
+                let control = match message.to_str().ok().and_then(|txt| serde_json::from_str::<ReplayControlMessage>(txt).ok()) {
+                    Some(control) if control.message_type == "replay_control" => control,
+                    _ => continue,
+                };
+
+                if let Some(new_speed) = control.speed {
+                    if REPLAY_SPEEDS.contains(&new_speed) {
+                        speed = new_speed;
+                    } else {
+                        let error = json!({ "type": "error", "message": format!("Unsupported replay speed {}", new_speed) });
+                        let _ = tx.send(Message::text(error.to_string())).await;
+                    }
+                }
+                if let Some(new_paused) = control.paused {
+                    paused = new_paused;
+                }
+                if let Some(tick) = control.seek {
+                    player.seek(tick);
+                    if tx.send(snapshot_message(player.game())).await.is_err() {
+                        break;
+                    }
+                }
+
+                next_tick = Instant::now() + tick_duration.div_f32(speed);
+                if tx.send(position_message(&player, speed, paused)).await.is_err() {
+                    break;
+                }
+            }
+            _ = tokio::time::sleep_until(next_tick), if playing => {
+                next_tick += tick_duration.div_f32(speed);
+
+                let events = player.step().unwrap_or_default();
+                let mut messages: Vec<Message> = events.into_iter()
+                    .map(|event| Message::text(event.data.to_string()))
+                    .collect();
+                messages.push(snapshot_message(player.game()));
+                if player.is_finished() {
+                    messages.push(Message::text(json!({ "type": "replay_end" }).to_string()));
+                }
+
+                let mut sent = true;
+                for message in messages {
+                    if tx.send(message).await.is_err() {
+                        sent = false;
+                        break;
+                    }
+                }
+                if !sent {
+                    break;
+                }
+            }
+        }
+    }
+
+    println!("Replay viewer for {} disconnected", name);
+}
@@ -1,8 +1,8 @@
 // This module will contain ball-related structures and logic.
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Ball {
     pub x: f32,
     pub y: f32,
@@ -41,8 +41,8 @@ impl Ball {
             self.x += self.vx * sub_dt;
             self.y += self.vy * sub_dt;
             
-            // Apply friction
-            let friction = 0.998;
+            // Apply friction (0.998 per 10 ms sub-step)
+            let friction = 0.998f32.powf(sub_dt / 0.01);
             self.vx *= friction;
             self.vy *= friction;
 
@@ -0,0 +1,281 @@
+// Server-driven bot players that fill empty team slots.
+//
+// A bot is an ordinary `Player` whose `InputState` is written by `Bot::think` during each step
+// instead of arriving from a client, so bots show up in snapshots and replays like anyone else
+// (a replay only records their inputs and never runs this code). Each bot keeps its own RNG,
+// seeded without drawing from the game's, so adding bots doesn't change the game's sequence.
+//
+// Every decision, the team's bot nearest to the ball chases it (or the ball carrier),
+// while the rest hold a defensive spot between their goal and the ball or run ahead of a
+// teammate who has it. A bot with the ball carries it toward the nearest opponent goal and
+// shoots once in range; Normal and Hard bots also fire rockets at opposing ball carriers.
+
+use serde::{Deserialize, Serialize};
+use super::game::{Game, InputState};
+use super::map::MapObject;
+use super::player::{Player, Team};
+use super::rng::SimRng;
+
+// Bots fill each team up to this many players unless a room asks for something else
+pub const DEFAULT_BOT_TEAM_SIZE: u32 = 2;
+
+// Don't steer toward a spot closer than this (px), so bots don't jitter around it
+const ARRIVE_RADIUS: f32 = 12.0;
+
+// Press a direction key when the target is within about 67 degrees of it, giving 8 directions
+const STEER_THRESHOLD: f32 = 0.38;
+
+// How far from their own goal toward the ball (as a fraction) defenders hold position
+const DEFEND_DEPTH: f32 = 0.35;
+
+// How far ahead of a teammate carrying the ball a bot runs to support them (px)
+const SUPPORT_DISTANCE: f32 = 150.0;
+
+// Shoot anyway when the shot clock gets this low (s)
+const SHOT_CLOCK_PANIC: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BotDifficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl BotDifficulty {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(BotDifficulty::Easy),
+            "normal" => Some(BotDifficulty::Normal),
+            "hard" => Some(BotDifficulty::Hard),
+            _ => None,
+        }
+    }
+
+    // Seconds between decisions
+    fn reaction_time(self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 0.35,
+            BotDifficulty::Normal => 0.2,
+            BotDifficulty::Hard => 0.08,
+        }
+    }
+
+    // Largest random offset (px) added to shot and rocket targets
+    fn aim_error(self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 120.0,
+            BotDifficulty::Normal => 60.0,
+            BotDifficulty::Hard => 20.0,
+        }
+    }
+
+    // Distance (px) from the opponent goal at which a bot holding the ball shoots
+    fn shoot_range(self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 350.0,
+            BotDifficulty::Normal => 450.0,
+            BotDifficulty::Hard => 550.0,
+        }
+    }
+
+    // Distance (px) at which a bot fires rockets at an opposing ball carrier, if it uses them
+    fn rocket_range(self) -> Option<f32> {
+        match self {
+            BotDifficulty::Easy => None,
+            BotDifficulty::Normal => Some(300.0),
+            BotDifficulty::Hard => Some(420.0),
+        }
+    }
+
+    // Seconds of ball movement a bot anticipates when chasing it
+    fn ball_lead(self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 0.0,
+            BotDifficulty::Normal => 0.15,
+            BotDifficulty::Hard => 0.3,
+        }
+    }
+}
+
+// Whether a game tops its teams up with bots, and how
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BotFill {
+    pub difficulty: BotDifficulty,
+    pub team_size: u32, // Players (humans and bots) per team to fill up to
+}
+
+impl BotFill {
+    pub fn new(difficulty: BotDifficulty) -> Self {
+        Self { difficulty, team_size: DEFAULT_BOT_TEAM_SIZE }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bot {
+    pub difficulty: BotDifficulty,
+    rng: SimRng,
+    think_timer: f32, // Seconds until the next decision
+}
+
+impl Bot {
+    pub fn new(difficulty: BotDifficulty, seed: u64) -> Self {
+        Self {
+            difficulty,
+            rng: SimRng::new(seed),
+            think_timer: 0.0,
+        }
+    }
+
+    // Decide what the bot's player should do. Returns None between decisions, when the
+    // previous input stays in effect the same way a client's does between input messages.
+    pub fn think(&mut self, game: &Game, player_id: u32, fixed_dt: f32) -> Option<InputState> {
+        self.think_timer -= fixed_dt;
+        if self.think_timer > 0.0 {
+            return None;
+        }
+        self.think_timer = self.difficulty.reaction_time();
+
+        let me = game.players.get(&player_id)?;
+        let pos = (me.ship.x, me.ship.y);
+        let ball = (game.ball.x, game.ball.y);
+        let own_goal = own_goal_center(game, me.team);
+        let target_goal = nearest_opponent_goal(game, me.team, pos);
+        let mut input = InputState::default();
+
+        let carrier = if game.ball.grabbed { game.ball.owner.and_then(|id| game.players.get(&id)) } else { None };
+
+        let destination = match carrier {
+            // Carry the ball toward the opponent goal and shoot once in range
+            Some(carrier) if carrier.id == player_id => {
+                let goal = target_goal.unwrap_or(ball);
+                let aim = self.aim_at(goal);
+                input.target_x = Some(aim.0);
+                input.target_y = Some(aim.1);
+                if distance(pos, goal) < self.difficulty.shoot_range() || game.ball.shot_clock < SHOT_CLOCK_PANIC {
+                    input.shoot = true;
+                }
+                goal
+            }
+            // Run ahead of a teammate who has the ball
+            Some(carrier) if carrier.team == me.team => {
+                let from = (carrier.ship.x, carrier.ship.y);
+                let ahead = toward(from, target_goal.unwrap_or(from), SUPPORT_DISTANCE);
+                // Spread supporting bots to either side of the carrier
+                let side = if player_id.is_multiple_of(2) { 1.0 } else { -1.0 };
+                (ahead.0, ahead.1 + side * SUPPORT_DISTANCE / 2.0)
+            }
+            // Go after an opponent with the ball, or fall back to defend
+            Some(carrier) => {
+                let carrier_pos = (carrier.ship.x, carrier.ship.y);
+                if let Some(range) = self.difficulty.rocket_range() {
+                    if me.rocket_cooldown <= 0.0 && distance(pos, carrier_pos) < range {
+                        let aim = self.aim_at(carrier_pos);
+                        input.boost = true;
+                        input.target_x = Some(aim.0);
+                        input.target_y = Some(aim.1);
+                    }
+                }
+                if is_chaser(game, me, carrier_pos) {
+                    carrier_pos
+                } else {
+                    defend_position(own_goal, carrier_pos)
+                }
+            }
+            // Loose ball: the nearest bot on the team chases it unless only the other team may pick it up
+            None => {
+                let lead = self.difficulty.ball_lead();
+                let ball_ahead = (ball.0 + game.ball.vx * lead, ball.1 + game.ball.vy * lead);
+                let can_pick_up = game.ball.exclusive_team.as_ref()
+                    .is_none_or(|team| *team == format!("{:?}", me.team));
+                if can_pick_up && is_chaser(game, me, ball) {
+                    ball_ahead
+                } else {
+                    defend_position(own_goal, ball_ahead)
+                }
+            }
+        };
+
+        steer(&mut input, pos, destination);
+        Some(input)
+    }
+
+    fn aim_at(&mut self, target: (f32, f32)) -> (f32, f32) {
+        let error = self.difficulty.aim_error();
+        (
+            target.0 + (self.rng.next_f32() * 2.0 - 1.0) * error,
+            target.1 + (self.rng.next_f32() * 2.0 - 1.0) * error,
+        )
+    }
+}
+
+fn goal_type(team: Team) -> &'static str {
+    match team {
+        Team::Red => "goal_red",
+        Team::Blue => "goal_blue",
+        Team::Yellow => "goal_yellow",
+        Team::Green => "goal_green",
+    }
+}
+
+fn center(obj: &MapObject) -> (f32, f32) {
+    (obj.x + obj.width / 2.0, obj.y + obj.height / 2.0)
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+// The point `length` px from `from` in the direction of `to`
+fn toward(from: (f32, f32), to: (f32, f32), length: f32) -> (f32, f32) {
+    let dist = distance(from, to);
+    if dist < f32::EPSILON {
+        return from;
+    }
+    (from.0 + (to.0 - from.0) / dist * length, from.1 + (to.1 - from.1) / dist * length)
+}
+
+fn own_goal_center(game: &Game, team: Team) -> Option<(f32, f32)> {
+    game.map.goals().find(|goal| goal.obj_type == goal_type(team)).map(center)
+}
+
+fn nearest_opponent_goal(game: &Game, team: Team, pos: (f32, f32)) -> Option<(f32, f32)> {
+    game.map.goals()
+        .filter(|goal| goal.obj_type != goal_type(team))
+        .map(center)
+        .min_by(|a, b| distance(pos, *a).total_cmp(&distance(pos, *b)))
+}
+
+// Hold the line between our goal and the threat
+fn defend_position(own_goal: Option<(f32, f32)>, threat: (f32, f32)) -> (f32, f32) {
+    match own_goal {
+        Some(goal) => toward(goal, threat, distance(goal, threat) * DEFEND_DEPTH),
+        None => threat,
+    }
+}
+
+// Whether this bot is the one nearest to the target among its team's bots. Humans aren't
+// counted, so an idle teammate can't leave the ball sitting untouched.
+fn is_chaser(game: &Game, me: &Player, target: (f32, f32)) -> bool {
+    let my_distance = distance((me.ship.x, me.ship.y), target);
+    !game.players.values().any(|other| {
+        other.id != me.id
+            && other.team == me.team
+            && game.is_bot(other.id)
+            && distance((other.ship.x, other.ship.y), target) < my_distance
+    })
+}
+
+// Press the direction keys that move from `pos` toward `destination`
+fn steer(input: &mut InputState, pos: (f32, f32), destination: (f32, f32)) {
+    let dx = destination.0 - pos.0;
+    let dy = destination.1 - pos.1;
+    let dist = (dx * dx + dy * dy).sqrt();
+    if dist < ARRIVE_RADIUS {
+        return;
+    }
+    input.left = dx < -STEER_THRESHOLD * dist;
+    input.right = dx > STEER_THRESHOLD * dist;
+    input.up = dy < -STEER_THRESHOLD * dist;
+    input.down = dy > STEER_THRESHOLD * dist;
+}
@@ -1,8 +1,8 @@
 // This module will contain collision detection and resolution functions.
 
-use crate::ball::Ball;
-use crate::player::Ship;
-use crate::game::MapObject;
+use super::ball::Ball;
+use super::player::Ship;
+use super::map::MapObject;
 
 pub const SHIP_WIDTH: f32 = 40.0;
 pub const SHIP_HEIGHT: f32 = 40.0;
@@ -10,11 +10,6 @@ pub const BALL_WIDTH: f32 = 20.0;
 pub const BALL_HEIGHT: f32 = 20.0;
 pub const WALL_COLLISION_INSET: f32 = 0.0;
 
-pub fn aabb_collision(x1: f32, y1: f32, w1: f32, h1: f32, x2: f32, y2: f32, w2: f32, h2: f32) -> bool {
-    x1 < x2 + w2 && x1 + w1 > x2 &&
-    y1 < y2 + h2 && y1 + h1 > y2
-}
-
 pub fn resolve_rect_collision(ball: &mut Ball, wall: &MapObject) {
     let ball_left = ball.x - BALL_WIDTH / 2.0;
     let ball_right = ball.x + BALL_WIDTH / 2.0;
@@ -128,6 +123,7 @@ pub fn resolve_ship_collision(ship: &mut Ship, velocity: &mut (f32, f32), wall:
 // Placeholder for collision logic 
 
 // Function to check if a line intersects with a rectangle
+#[allow(clippy::too_many_arguments)]
 pub fn line_intersects_rect(
     line_x1: f32, line_y1: f32, 
     line_x2: f32, line_y2: f32,
@@ -190,6 +186,7 @@ fn point_in_rect(
 }
 
 // Helper function to check if two line segments intersect
+#[allow(clippy::too_many_arguments)]
 fn line_intersects_segment(
     line1_x1: f32, line1_y1: f32, 
     line1_x2: f32, line1_y2: f32,
@@ -197,16 +194,16 @@ fn line_intersects_segment(
     line2_x2: f32, line2_y2: f32
 ) -> bool {
     // Calculate the direction of the lines
-    let uA = ((line2_x2 - line2_x1) * (line1_y1 - line2_y1) - 
+    let u_a = ((line2_x2 - line2_x1) * (line1_y1 - line2_y1) - 
               (line2_y2 - line2_y1) * (line1_x1 - line2_x1)) /
              ((line2_y2 - line2_y1) * (line1_x2 - line1_x1) - 
               (line2_x2 - line2_x1) * (line1_y2 - line1_y1));
               
-    let uB = ((line1_x2 - line1_x1) * (line1_y1 - line2_y1) - 
+    let u_b = ((line1_x2 - line1_x1) * (line1_y1 - line2_y1) - 
               (line1_y2 - line1_y1) * (line1_x1 - line2_x1)) /
              ((line2_y2 - line2_y1) * (line1_x2 - line1_x1) - 
               (line2_x2 - line2_x1) * (line1_y2 - line1_y1));
     
-    // If uA and uB are between 0-1, lines are colliding
-    (uA >= 0.0 && uA <= 1.0) && (uB >= 0.0 && uB <= 1.0)
+    // If u_a and u_b are between 0-1, lines are colliding
+    (0.0..=1.0).contains(&u_a) && (0.0..=1.0).contains(&u_b)
 } 
\ No newline at end of file
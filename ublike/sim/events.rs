@@ -0,0 +1,26 @@
+// Events the simulation reports from each step. The data is the JSON payload clients already
+// understand (it carries its own "type" field); the kind tells the server how to route it.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum EventKind {
+    Goal,
+    GameReset,
+    Countdown,
+    AutoShoot,      // Shot clock ran out and the ball was released
+    BallShot,
+    ShotProcessed,  // Confirmation of a reliable shot for the shooter
+    BallKnocked,
+    ProjectileFired,
+    Explosion,
+    MatchPhase,     // The match moved to a new phase (warmup, live, halftime...)
+    MatchEnd,       // Final results
+    Scoreboard,     // Every player's match stats
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameEvent {
+    pub kind: EventKind,
+    pub data: serde_json::Value,
+}
@@ -0,0 +1,2781 @@
+// The game simulation: players, ball, projectiles and goals on one map.
+//
+// `Game::step` advances the world by one fixed tick and returns the events (goals, shots,
+// explosions, countdown...) that happened during it. All randomness comes from the game's
+// seeded `SimRng` and all time from the `now_ms` passed to `step`, so two games created with the
+// same map and seed and fed the same inputs stay identical.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use super::ball::{Ball, BALL_WIDTH, BALL_HEIGHT};
+use super::bot::{Bot, BotFill};
+use super::player::{Player, ShipState, Team};
+use super::collision::{resolve_ship_collision};
+use super::input_buffer::InputBuffer;
+use super::events::{EventKind, GameEvent};
+use super::map::GameMap;
+use super::match_state::{MatchEndReason, MatchPhase, MatchSettings, MatchState};
+use super::lag_compensation::{DEFAULT_MAX_REWIND_MS, HistoryFrame, PositionHistory};
+use super::replay::{Replay, ReplayAction, ReplayRecorder};
+use super::rng::SimRng;
+use super::stats::{sort_scoreboard, PlayerStats, ScoreboardEntry, TouchHistory};
+
+// Tick length the movement and shot constants were tuned for; friction and shot impulses are
+// scaled from it so the game feels the same at any tick rate
+pub const TUNING_DT: f32 = 0.1;
+
+// Most players (humans and bots) allowed on one team
+pub const MAX_PLAYERS_PER_TEAM: u32 = 3;
+
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    pub shoot: bool,
+    pub boost: bool,
+    pub target_x: Option<f32>,
+    pub target_y: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Projectile {
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub vx: f32,
+    pub vy: f32,
+    pub owner_id: u32,
+    pub lifetime: f32,  // Time in seconds before explosion
+    pub active: bool,
+}
+
+impl Projectile {
+    pub fn new(id: u32, x: f32, y: f32, vx: f32, vy: f32, owner_id: u32) -> Self {
+        Self {
+            id,
+            x,
+            y,
+            vx,
+            vy,
+            owner_id,
+            lifetime: 3.0,  // 3 seconds before auto-explosion (increased by 1 second)
+            active: true,
+        }
+    }
+    
+    pub fn update_position(&mut self, dt: f32) {
+        self.x += self.vx * dt;
+        self.y += self.vy * dt;
+        self.lifetime -= dt;
+        
+        // Deactivate if lifetime is up
+        if self.lifetime <= 0.0 {
+            self.active = false;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GameStateSnapshot {
+    pub tick: u64, // simulation tick this snapshot was taken after
+    pub snapshot_id: u32, // increases by one per broadcast, referenced by client acks
+    pub time: u64, // server timestamp in ms
+    pub players: HashMap<u32, ShipState>,
+    pub ball: Ball,
+    pub projectiles: Vec<Projectile>, // Add projectiles to the game state
+    pub team1_score: u32, // Red team score
+    pub team2_score: u32, // Blue team score
+    pub team3_score: u32, // Yellow team score
+    pub team4_score: u32, // Green team score
+    pub match_state: MatchState, // Phase, half and clock
+}
+
+pub struct Game {
+    pub ball: Ball,
+    pub players: BTreeMap<u32, Player>, // Ordered so every step iterates players the same way
+    pub next_id: u32,
+    pub team1_score: u32, // Red team score
+    pub team2_score: u32, // Blue team score
+    pub team3_score: u32, // Yellow team score  
+    pub team4_score: u32, // Green team score
+    pub goal_cooldown: f32, // Add cooldown after a goal is scored
+    pub red_team_count: u32, // Count of players in red team
+    pub blue_team_count: u32, // Count of players in blue team
+    pub yellow_team_count: u32, // Count of players in yellow team
+    pub green_team_count: u32, // Count of players in green team
+    pub projectiles: Vec<Projectile>, // Add projectiles list
+    pub next_projectile_id: u32, // Track projectile IDs
+    pub map: Arc<GameMap>, // Arena this game is played on
+    pub map_rotation: Vec<Arc<GameMap>>, // Maps to cycle through after each match (empty = no rotation)
+    pub rotation_index: usize, // Position of the current map in map_rotation
+    pub snapshot_seq: u32, // Id of the last broadcast snapshot
+    pub tick: u64, // Number of simulation ticks run so far
+    pub position_history: PositionHistory, // Recent positions for lag-compensated hit checks
+    pub rng: SimRng, // Source of all randomness in the simulation
+    pub time_ms: u64, // Time of the last step in ms
+    pub countdown_remaining: f32, // Seconds left in the kickoff countdown after a reset
+    pub bots: BTreeMap<u32, Bot>, // Players driven by the server, keyed by player id
+    pub bot_fill: Option<BotFill>, // Top teams up with bots (see balance_bots); None = no bots
+    pub match_settings: MatchSettings, // Match length, score limit, overtime...
+    pub match_state: MatchState, // Where the current match is in its lifecycle
+    pub teams_locked: bool, // Set by the host to stop players switching teams
+    pub touches: TouchHistory, // Who had the ball last, for crediting goals and assists
+    events: Vec<GameEvent>, // Events emitted since the last step returned
+    recorder: Option<ReplayRecorder>, // Replay of the current match, while recording
+    finished_replays: Vec<Replay>, // Recordings that ended and haven't been collected yet
+}
+
+impl Game {
+    // Helper function to detect if we're using the soccer map (only Red and Blue goals)
+    fn is_soccer_map(&self) -> bool {
+        self.map.is_soccer()
+    }
+
+    pub fn new(map: Arc<GameMap>, seed: u64) -> Self {
+        // Calculate the middle of the game area
+        let game_width = 1600.0;
+        let game_height = 900.0;
+        let middle_x = game_width / 2.0;
+        let middle_y = game_height / 2.0;
+        
+        Self {
+            ball: Ball {
+                x: middle_x,
+                y: middle_y,
+                vx: 0.0,
+                vy: 0.0,
+                active: true,
+                grabbed: false,
+                grab_cooldown: 0.0,
+                owner: None,
+                last_shooter: None,
+                shot_clock: 10.0, // Initialize shot clock
+                pickup_cooldown: 0.0, // Initialize pickup cooldown
+                exclusive_team: None, // No team restriction initially
+            },
+            players: BTreeMap::new(),
+            next_id: 1,
+            team1_score: 0,
+            team2_score: 0,
+            team3_score: 0,
+            team4_score: 0,
+            goal_cooldown: 0.0,
+            red_team_count: 0,
+            blue_team_count: 0,
+            yellow_team_count: 0,
+            green_team_count: 0,
+            projectiles: Vec::new(),
+            next_projectile_id: 1,
+            map,
+            map_rotation: Vec::new(),
+            rotation_index: 0,
+            snapshot_seq: 0,
+            tick: 0,
+            position_history: PositionHistory::new(DEFAULT_MAX_REWIND_MS),
+            rng: SimRng::new(seed),
+            time_ms: 0,
+            countdown_remaining: 0.0,
+            bots: BTreeMap::new(),
+            bot_fill: None,
+            match_settings: MatchSettings::default(),
+            match_state: MatchState::default(),
+            teams_locked: false,
+            touches: TouchHistory::default(),
+            events: Vec::new(),
+            recorder: None,
+            finished_replays: Vec::new(),
+        }
+    }
+    
+    // Move to the next map in the rotation. Returns false if there is nothing to rotate to.
+    pub fn advance_map_rotation(&mut self) -> bool {
+        if self.map_rotation.len() < 2 {
+            return false;
+        }
+        
+        self.rotation_index = (self.rotation_index + 1) % self.map_rotation.len();
+        let next_map = self.map_rotation[self.rotation_index].clone();
+        println!("Map rotation: switching from '{}' to '{}'", self.map.id, next_map.id);
+        self.change_map(next_map);
+        true
+    }
+    
+    // Swap the arena, moving players off teams the new map doesn't have
+    pub fn change_map(&mut self, map: Arc<GameMap>) {
+        self.map = map;
+        
+        if self.is_soccer_map() {
+            let player_ids: Vec<u32> = self.players.keys().cloned().collect();
+            for player_id in player_ids {
+                let team = self.players[&player_id].team;
+                if team == Team::Yellow || team == Team::Green {
+                    self.recalculate_team_counts();
+                    let new_team = if self.red_team_count <= self.blue_team_count { Team::Red } else { Team::Blue };
+                    if let Some(player) = self.players.get_mut(&player_id) {
+                        println!("Map change: moving player {} from {:?} to {:?}", player_id, team, new_team);
+                        player.team = new_team;
+                    }
+                }
+            }
+        }
+        
+        self.recalculate_team_counts();
+        self.projectiles.clear();
+        self.position_history.clear();
+    }
+    
+    // Server time the given player is looking at, if lag compensation applies to them
+    fn view_time(&self, player_id: u32, now_ms: u64) -> Option<u64> {
+        let player = self.players.get(&player_id)?;
+        self.position_history.rewind_time(now_ms, player.view_delay_ms)
+    }
+    
+    // Whether the player was holding the ball in the state they were looking at when they shot
+    pub fn held_ball_at_view_time(&self, player_id: u32) -> bool {
+        match self.view_time(player_id, self.time_ms) {
+            Some(view_time) => self.position_history.ball_owner_at(view_time) == Some(player_id),
+            None => false,
+        }
+    }
+    
+    fn record_position_history(&mut self, now_ms: u64) {
+        let players = self.players.iter()
+            .map(|(id, player)| (*id, (player.ship.x, player.ship.y)))
+            .collect();
+        self.position_history.record(HistoryFrame {
+            time: now_ms,
+            players,
+            ball: (self.ball.x, self.ball.y),
+            ball_owner: if self.ball.grabbed { self.ball.owner } else { None },
+        });
+    }
+    
+    // Add a method to determine which team a new player should join
+    pub fn assign_team(&mut self) -> Team {
+        // For corner defense mode: limit to 3 players per team
+        let max_players_per_team = MAX_PLAYERS_PER_TEAM;
+        
+        let team_counts = if self.is_soccer_map() {
+            // Soccer map: only Red and Blue teams
+            println!("Soccer map detected - limiting teams to Red and Blue only");
+            vec![
+                (Team::Red, self.red_team_count),
+                (Team::Blue, self.blue_team_count),
+            ]
+        } else {
+            // Corner defense map: all four teams
+            vec![
+                (Team::Red, self.red_team_count),
+                (Team::Blue, self.blue_team_count),
+                (Team::Yellow, self.yellow_team_count),
+                (Team::Green, self.green_team_count),
+            ]
+        };
+        
+        // Find team with lowest count that hasn't reached the limit
+        let available_teams: Vec<_> = team_counts.iter()
+            .filter(|(_, count)| *count < max_players_per_team)
+            .collect();
+        
+        if available_teams.is_empty() {
+            // All teams are full, assign to Red as fallback (should not happen in corner defense)
+            println!("Warning: All teams are full, assigning to Red team");
+            return Team::Red;
+        }
+        
+        // Find team with lowest count among available teams
+        let (team, _) = available_teams.iter()
+            .min_by_key(|(_, count)| *count)
+            .map(|(team, _)| (*team, 0u32))
+            .unwrap_or((Team::Red, 0u32));
+        
+        // Increment the appropriate counter
+        match team {
+            Team::Red => self.red_team_count += 1,
+            Team::Blue => self.blue_team_count += 1,
+            Team::Yellow => self.yellow_team_count += 1,
+            Team::Green => self.green_team_count += 1,
+        }
+        
+        team
+    }
+    
+    // Put a new player on the team they were matched to, or pick one the usual way if that
+    // team isn't on this map or is full of humans
+    pub fn assign_preferred_team(&mut self, team: Team) -> Team {
+        if !self.playable_teams().contains(&team) || !self.make_room_on_team(team) {
+            return self.assign_team();
+        }
+        match team {
+            Team::Red => self.red_team_count += 1,
+            Team::Blue => self.blue_team_count += 1,
+            Team::Yellow => self.yellow_team_count += 1,
+            Team::Green => self.green_team_count += 1,
+        }
+        team
+    }
+    
+    // Add method to check if a team can accept new players
+    pub fn can_join_team(&self, team: Team) -> bool {
+        // Check if team is allowed on this map
+        if self.is_soccer_map() {
+            match team {
+                Team::Yellow | Team::Green => {
+                    println!("Team {:?} not allowed on soccer map", team);
+                    return false;
+                },
+                _ => {} // Red and Blue are allowed
+            }
+        }
+        
+        let max_players_per_team = MAX_PLAYERS_PER_TEAM; // For corner defense mode
+        
+        let current_count = match team {
+            Team::Red => self.red_team_count,
+            Team::Blue => self.blue_team_count,
+            Team::Yellow => self.yellow_team_count,
+            Team::Green => self.green_team_count,
+        };
+        
+        let can_join = current_count < max_players_per_team;
+        
+        println!("can_join_team check: {:?} team has {} players (max: {}), can join: {}", 
+                 team, current_count, max_players_per_team, can_join);
+        
+        // Also count actual players in this team for verification
+        let actual_count = self.players.values()
+            .filter(|player| player.team == team)
+            .count();
+        
+        println!("Verification: {:?} team actually has {} players in game", team, actual_count);
+        
+        if current_count as usize != actual_count {
+            println!("WARNING: Team count mismatch! Stored count: {}, Actual count: {}", 
+                     current_count, actual_count);
+        }
+        
+        can_join
+    }
+    
+    // Add method to recalculate team counts from actual players (to fix any sync issues)
+    pub fn recalculate_team_counts(&mut self) {
+        // Reset all counts
+        self.red_team_count = 0;
+        self.blue_team_count = 0;
+        self.yellow_team_count = 0;
+        self.green_team_count = 0;
+        
+        // Count players by team
+        for player in self.players.values() {
+            match player.team {
+                Team::Red => self.red_team_count += 1,
+                Team::Blue => self.blue_team_count += 1,
+                Team::Yellow => self.yellow_team_count += 1,
+                Team::Green => self.green_team_count += 1,
+            }
+        }
+        
+        println!("Recalculated team counts - Red: {}, Blue: {}, Yellow: {}, Green: {}", 
+                 self.red_team_count, self.blue_team_count, self.yellow_team_count, self.green_team_count);
+    }
+    
+    // Create a player for a new connection and add them to the game
+    pub fn spawn_player(&mut self, id: u32, team: Team, display_name: String) -> &mut Player {
+        let player = Player::new(id, team, display_name.clone(), &self.map, &mut self.rng);
+        self.players.insert(id, player);
+        self.record(ReplayAction::Join { id, team, name: display_name });
+        self.players.get_mut(&id).expect("player was just inserted")
+    }
+    
+    // Update player removal to account for team counts
+    pub fn remove_player(&mut self, player_id: u32) {
+        if let Some(player) = self.players.get(&player_id) {
+            match player.team {
+                Team::Red => self.red_team_count = self.red_team_count.saturating_sub(1),
+                Team::Blue => self.blue_team_count = self.blue_team_count.saturating_sub(1),
+                Team::Yellow => self.yellow_team_count = self.yellow_team_count.saturating_sub(1),
+                Team::Green => self.green_team_count = self.green_team_count.saturating_sub(1),
+            }
+        } else {
+            return;
+        }
+        self.players.remove(&player_id);
+        self.bots.remove(&player_id);
+        
+        // A leaving player drops the ball
+        self.release_held_ball(player_id);
+        
+        self.record(ReplayAction::Leave { id: player_id });
+        
+        // An empty game waits in warmup for the next players
+        if self.players.is_empty() && self.match_state.phase != MatchPhase::Warmup {
+            self.match_state = MatchState::default();
+            self.team1_score = 0;
+            self.team2_score = 0;
+            self.team3_score = 0;
+            self.team4_score = 0;
+        }
+    }
+    
+    // Leave a player whose connection dropped in the game, idle, so they keep their id, team and
+    // stats if they reconnect. They let go of the ball.
+    pub fn disconnect_player(&mut self, player_id: u32) {
+        let player = match self.players.get_mut(&player_id) {
+            Some(player) => player,
+            None => return,
+        };
+        player.input = InputState::default();
+        player.input_buffer = InputBuffer::new(); // A reconnecting client restarts its input sequence
+        if self.release_held_ball(player_id) {
+            self.record(ReplayAction::DropBall { id: player_id });
+        }
+    }
+    
+    // Make a player let go of the ball if they have it. Returns whether they did.
+    pub(super) fn release_held_ball(&mut self, player_id: u32) -> bool {
+        if !(self.ball.grabbed && self.ball.owner == Some(player_id)) {
+            return false;
+        }
+        self.ball.grabbed = false;
+        self.ball.owner = None;
+        self.ball.grab_cooldown = 0.5;
+        true
+    }
+    
+    pub fn is_bot(&self, player_id: u32) -> bool {
+        self.bots.contains_key(&player_id)
+    }
+    
+    // Players connected from a client, i.e. everyone but the bots
+    pub fn human_count(&self) -> usize {
+        self.players.len() - self.bots.len()
+    }
+    
+    // Teams that play on the current map
+    fn playable_teams(&self) -> Vec<Team> {
+        if self.is_soccer_map() {
+            vec![Team::Red, Team::Blue]
+        } else {
+            vec![Team::Red, Team::Blue, Team::Yellow, Team::Green]
+        }
+    }
+    
+    // A fresh id for a player, bot or spectator. Ids are sent as u16 (see protocol.rs), so after
+    // u16::MAX they start again from 1, skipping players who are still in the game.
+    pub fn allocate_player_id(&mut self) -> u32 {
+        loop {
+            let id = self.next_id;
+            self.next_id = if id >= u16::MAX as u32 { 1 } else { id + 1 };
+            if !self.players.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+    
+    // Add a bot to a team with the difficulty from bot_fill. Returns its player id.
+    pub fn add_bot(&mut self, team: Team) -> Option<u32> {
+        let difficulty = self.bot_fill?.difficulty;
+        let id = self.allocate_player_id();
+        
+        // Seed from the game's RNG state without drawing from it, so replays (which don't run
+        // bots) see the same sequence
+        let seed = self.rng.state() ^ (id as u64).rotate_left(32);
+        self.bots.insert(id, Bot::new(difficulty, seed));
+        self.spawn_player(id, team, format!("Bot_{}", id));
+        match team {
+            Team::Red => self.red_team_count += 1,
+            Team::Blue => self.blue_team_count += 1,
+            Team::Yellow => self.yellow_team_count += 1,
+            Team::Green => self.green_team_count += 1,
+        }
+        println!("Added {:?} bot {} to {:?} team", difficulty, id, team);
+        Some(id)
+    }
+    
+    // Remove the most recently added bot from a team. Returns false if the team has no bots.
+    fn remove_bot_from_team(&mut self, team: Team) -> bool {
+        let bot_id = self.bots.keys().rev()
+            .find(|id| self.players.get(id).map(|player| player.team) == Some(team))
+            .copied();
+        match bot_id {
+            Some(bot_id) => {
+                self.remove_player(bot_id);
+                println!("Removed bot {} from {:?} team", bot_id, team);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    // Free up a slot on a full team by removing one of its bots, so a human can take it.
+    // Returns whether the team can now take another player.
+    pub fn make_room_on_team(&mut self, team: Team) -> bool {
+        let count = self.players.values().filter(|player| player.team == team).count() as u32;
+        count < MAX_PLAYERS_PER_TEAM || self.remove_bot_from_team(team)
+    }
+    
+    // Make sure a joining human gets a slot: when every team is full, one bot gives up its place
+    pub fn make_room_for_human(&mut self) {
+        let teams = self.playable_teams();
+        let all_full = teams.iter()
+            .all(|team| self.players.values().filter(|player| player.team == *team).count() as u32 >= MAX_PLAYERS_PER_TEAM);
+        if !all_full {
+            return;
+        }
+        let team_with_most_bots = teams.into_iter()
+            .max_by_key(|team| self.bots.keys().filter(|id| self.players.get(id).map(|player| player.team) == Some(*team)).count());
+        if let Some(team) = team_with_most_bots {
+            self.remove_bot_from_team(team);
+        }
+    }
+    
+    // Add and remove bots so every team has the same number of players: bot_fill's team size,
+    // or more if humans have already filled a team past it. A game without humans has no bots.
+    // Called by the server before each step.
+    pub fn balance_bots(&mut self) {
+        let fill = match self.bot_fill {
+            Some(fill) if self.human_count() > 0 => fill,
+            _ => {
+                let bot_ids: Vec<u32> = self.bots.keys().copied().collect();
+                for bot_id in bot_ids {
+                    self.remove_player(bot_id);
+                }
+                return;
+            }
+        };
+        
+        let teams = self.playable_teams();
+        let team_count = |game: &Game, team: Team| game.players.values().filter(|player| player.team == team).count() as u32;
+        let humans_on = |game: &Game, team: Team| game.players.values()
+            .filter(|player| player.team == team && !game.is_bot(player.id))
+            .count() as u32;
+        
+        let largest_human_team = teams.iter().map(|team| humans_on(self, *team)).max().unwrap_or(0);
+        let target = fill.team_size.max(largest_human_team).min(MAX_PLAYERS_PER_TEAM);
+        
+        for team in teams {
+            while team_count(self, team) > target && self.remove_bot_from_team(team) {}
+            while team_count(self, team) < target {
+                if self.add_bot(team).is_none() {
+                    break;
+                }
+            }
+        }
+    }
+    
+    // Let each bot decide its input for this tick. Only the bot that is thinking is taken out
+    // of `bots`, so it still sees its teammates as bots when picking who chases the ball.
+    fn update_bots(&mut self, fixed_dt: f32) {
+        let ids: Vec<u32> = self.bots.keys().copied().collect();
+        for id in ids {
+            let Some(mut bot) = self.bots.remove(&id) else { continue };
+            let input = bot.think(self, id, fixed_dt);
+            self.bots.insert(id, bot);
+            if let (Some(input), Some(player)) = (input, self.players.get_mut(&id)) {
+                player.input = input;
+            }
+        }
+    }
+    
+    // Move a player to another team (team counts are kept by the caller)
+    pub fn set_player_team(&mut self, player_id: u32, team: Team) {
+        if let Some(player) = self.players.get_mut(&player_id) {
+            player.team = team;
+            self.record(ReplayAction::SwitchTeam { id: player_id, team });
+        }
+    }
+    
+    // Lag compensation for reliable shots: the shooter still had the ball in the state they saw
+    // and it has only been knocked loose since, so give it back. Returns whether it was returned.
+    pub fn return_ball_for_shot(&mut self, player_id: u32) -> bool {
+        if self.ball.grabbed || self.ball.pickup_cooldown > 0.0 || !self.held_ball_at_view_time(player_id) {
+            return false;
+        }
+        self.return_ball(player_id)
+    }
+    
+    pub(super) fn return_ball(&mut self, player_id: u32) -> bool {
+        let (ship_x, ship_y) = match self.players.get(&player_id) {
+            Some(player) => (player.ship.x, player.ship.y),
+            None => return false,
+        };
+        self.ball.grab(player_id, ship_x, ship_y);
+        self.record(ReplayAction::ReturnBall { id: player_id });
+        true
+    }
+    
+    // Start recording a replay from the current state. The lag compensation history is dropped
+    // so the recorded initial state is complete.
+    pub fn start_recording(&mut self, fixed_dt: f32, game_width: f32, game_height: f32) {
+        self.position_history.clear();
+        self.recorder = Some(ReplayRecorder::start(self, fixed_dt, game_width, game_height));
+    }
+    
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+    
+    // Stop recording; the replay is handed out by the next take_finished_replays()
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            self.finished_replays.push(recorder.finish());
+        }
+    }
+    
+    // Replays that have finished since the last call (on reset or stop_recording)
+    pub fn take_finished_replays(&mut self) -> Vec<Replay> {
+        std::mem::take(&mut self.finished_replays)
+    }
+    
+    fn record(&mut self, action: ReplayAction) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.action(action);
+        }
+    }
+
+    // Queue an event to be returned from the current (or next) step
+    fn emit(&mut self, kind: EventKind, data: serde_json::Value) {
+        self.events.push(GameEvent { kind, data });
+    }
+
+    // Advance the simulation by one tick. `now_ms` is the time of this tick; it timestamps
+    // events and snapshots and is what lag compensation rewinds from.
+    pub fn step(&mut self, fixed_dt: f32, game_width: f32, game_height: f32, now_ms: u64) -> Vec<GameEvent> {
+        // Hold our own handle on the map so wall/goal lookups don't borrow self
+        let map = self.map.clone();
+        self.time_ms = now_ms;
+        self.tick += 1;
+        
+        self.update_countdown(fixed_dt);
+        
+        // Bots write their input directly; everyone else consumes exactly one buffered
+        // input command per tick
+        self.update_bots(fixed_dt);
+        for player in self.players.values_mut() {
+            player.apply_next_input();
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_step(&self.players, now_ms);
+        }
+        
+        // Stats only count while goals do, so they stand still at halftime and on the results screen
+        let counting_stats = self.match_state.phase.goals_count();
+        if counting_stats && self.ball.grabbed {
+            if let Some(carrier) = self.ball.owner.and_then(|id| self.players.get_mut(&id)) {
+                carrier.stats.possession += fixed_dt;
+            }
+        }
+        
+        // Update cooldowns (remove ball grab cooldown update)
+        // Update goal cooldown
+        if self.goal_cooldown > 0.0 {
+            self.goal_cooldown -= fixed_dt;
+            if self.goal_cooldown < 0.0 { self.goal_cooldown = 0.0; }
+        }
+        
+        // Update ball pickup cooldown
+        if self.ball.pickup_cooldown > 0.0 {
+            self.ball.pickup_cooldown -= fixed_dt;
+            if self.ball.pickup_cooldown < 0.0 { 
+                self.ball.pickup_cooldown = 0.0;
+                if let Some(ref team) = self.ball.exclusive_team {
+                    println!("Ball glow ended - now only {} team can grab it", team);
+                }
+                // Keep team restriction active even after cooldown expires
+                // It will only be cleared when the ball is grabbed by the exclusive team
+            }
+        }
+        
+        // Update player cooldowns
+        for player in self.players.values_mut() {
+            if player.shoot_cooldown > 0.0 {
+                player.shoot_cooldown -= fixed_dt;
+                if player.shoot_cooldown < 0.0 { player.shoot_cooldown = 0.0; }
+            }
+            
+            if player.grab_cooldown > 0.0 {
+                player.grab_cooldown -= fixed_dt;
+                if player.grab_cooldown < 0.0 { player.grab_cooldown = 0.0; }
+            }
+            
+            // Add rocket cooldown update
+            if player.rocket_cooldown > 0.0 {
+                player.rocket_cooldown -= fixed_dt;
+                if player.rocket_cooldown < 0.0 { player.rocket_cooldown = 0.0; }
+            }
+        }
+        
+        // Update shot clock if ball is grabbed
+        if self.ball.grabbed {
+            self.ball.shot_clock -= fixed_dt;
+            
+            // Update the shot clock display in the player's boost field
+            if let Some(owner_id) = self.ball.owner {
+                if let Some(player) = self.players.get_mut(&owner_id) {
+                    // Scale the shot clock to 0-200 range for the boost UI
+                    player.fuel = (self.ball.shot_clock / 10.0) * 200.0;
+                }
+            }
+            
+            if self.ball.shot_clock <= 0.0 {
+                // Auto-shoot when shot clock expires
+                if let Some(owner_id) = self.ball.owner {
+                    if let Some(player) = self.players.get(&owner_id) {
+                        // Use the player's velocity direction for auto-shooting
+                        let mut dx = player.velocity.0;
+                        let mut dy = player.velocity.1;
+                        let mut mag = (dx * dx + dy * dy).sqrt();
+                        
+                        // If player is not moving, use the target coordinates as fallback
+                        if mag < 1.0 {
+                            if let (Some(target_x), Some(target_y)) = (player.input.target_x, player.input.target_y) {
+                                dx = target_x - player.ship.x;
+                                dy = target_y - player.ship.y;
+                                mag = (dx * dx + dy * dy).sqrt();
+                            }
+                            
+                            // If still no direction, default to shooting upward
+                            if mag < 1.0 {
+                                dx = 0.0;
+                                dy = -1.0;
+                                mag = 1.0;
+                            }
+                        }
+
+                        let max_allowed = 500.0;
+                        if mag > max_allowed {
+                            let scale = max_allowed / mag;
+                            dx *= scale;
+                            dy *= scale;
+                            mag = max_allowed;
+                        }
+
+                        let ship_mass = 1.0;
+                        let ball_mass = 0.5;
+                        let base_shot_force = 800.0;
+                        let dt = TUNING_DT;
+
+                        let aim_norm = (dx / mag, dy / mag);
+                        let impulse_base = (aim_norm.0 * base_shot_force * dt, aim_norm.1 * base_shot_force * dt);
+                        let additional_impulse = (player.velocity.0 * dt, player.velocity.1 * dt);
+                        let total_impulse = (impulse_base.0 + additional_impulse.0, impulse_base.1 + additional_impulse.1);
+
+                        let (ship_x, ship_y) = (player.ship.x, player.ship.y);
+
+                        self.ball.vx = total_impulse.0 / ball_mass;
+                        self.ball.vy = total_impulse.1 / ball_mass;
+                        
+                        // Cap the ball's maximum velocity to prevent tunneling
+                        let max_ball_speed = 500.0;
+                        let current_speed = (self.ball.vx * self.ball.vx + self.ball.vy * self.ball.vy).sqrt();
+                        if current_speed > max_ball_speed {
+                            let scale_factor = max_ball_speed / current_speed;
+                            self.ball.vx *= scale_factor;
+                            self.ball.vy *= scale_factor;
+                            println!("Capped ball velocity after shot: speed was {:.2}, now {:.2}", current_speed, max_ball_speed);
+                        }
+                        
+                        // Calculate normalized direction vector for ball positioning
+                        let dir_mag = (self.ball.vx * self.ball.vx + self.ball.vy * self.ball.vy).sqrt();
+                        let dir_x = if dir_mag > 0.0 { self.ball.vx / dir_mag } else { 0.0 };
+                        let dir_y = if dir_mag > 0.0 { self.ball.vy / dir_mag } else { 0.0 };
+                        
+                        // Use the helper function to position the ball safely
+                        self.position_ball_after_shot(ship_x, ship_y, dir_x, dir_y);
+
+                        // Set the last shooter and apply cooldown to prevent immediate grabbing
+                        self.ball.last_shooter = Some(owner_id);
+                        self.touches.touch(owner_id);
+                        if counting_stats {
+                            if let Some(shooter) = self.players.get_mut(&owner_id) {
+                                shooter.stats.shots += 1;
+                            }
+                        }
+                        
+                        // Collect events to broadcast after mutable borrow ends
+                        let mut shot_confirmation_opt = None;
+                        
+                        // Apply grab cooldown only to the shooter
+                        if let Some(player_mut) = self.players.get_mut(&owner_id) {
+                            player_mut.grab_cooldown = 0.3; // Increased to prevent tunneling through ships
+                            let recoil_factor = 1.0;
+                            player_mut.velocity.0 -= (total_impulse.0 / ship_mass) * recoil_factor;
+                            player_mut.velocity.1 -= (total_impulse.1 / ship_mass) * recoil_factor;
+                            player_mut.shoot_cooldown = 0.25;
+                            
+                            // Prepare confirmation for reliable shots
+                            if let Some(shot_id) = player_mut.pending_shot_id.take() {
+                                shot_confirmation_opt = Some(json!({
+                                    "type": "shot_processed",
+                                    "shot_id": shot_id,
+                                    "player_id": owner_id,
+                                    "ball_velocity": {
+                                        "x": self.ball.vx,
+                                        "y": self.ball.vy
+                                    },
+                                    "timestamp": now_ms
+                                }));
+                            }
+                            
+                            // Reset the shoot flag AFTER processing to prevent double-shooting
+                            player_mut.input.shoot = false;
+                            println!("Reset shoot flag for player {} after processing", owner_id);
+                        }
+
+                        self.ball.release(owner_id);
+                        
+                        // Send confirmation after mutable borrow ends
+                        if let Some(shot_confirmation) = shot_confirmation_opt {
+                            self.emit(EventKind::ShotProcessed, shot_confirmation);
+                        }
+                        
+                        // Send a message to all clients about the auto-shoot
+                        let auto_shoot_event = json!({
+                            "type": "auto_shoot",
+                            "player_id": owner_id
+                        });
+                        
+                        self.emit(EventKind::AutoShoot, auto_shoot_event);
+                    }
+                }
+            }
+        }
+        
+        for player in self.players.values_mut() {
+            if player.shoot_cooldown > 0.0 {
+                player.shoot_cooldown -= fixed_dt;
+                if player.shoot_cooldown < 0.0 { player.shoot_cooldown = 0.0; }
+            }
+        }
+
+        // Update players' ships
+        let ball_grabbed = self.ball.grabbed;
+        let ball_owner = self.ball.owner;
+        let mut events_to_broadcast = Vec::new();
+        for player in self.players.values_mut() {
+            let slowdown = if ball_grabbed && ball_owner == Some(player.id) { 0.8 } else { 1.0 };
+            let acceleration = 200.0 * slowdown;
+            let max_speed = 100.0 * slowdown;
+            
+            // Calculate movement direction from input
+            let mut dx: f32 = 0.0;
+            let mut dy: f32 = 0.0;
+            if player.input.left { dx -= 1.0; }
+            if player.input.right { dx += 1.0; }
+            if player.input.up { dy -= 1.0; }
+            if player.input.down { dy += 1.0; }
+            
+            // Normalize direction if needed
+            let mag = (dx * dx + dy * dy).sqrt();
+            if mag > 0.0 {
+                dx /= mag;
+                dy /= mag;
+            }
+            
+            // Apply acceleration in the input direction
+            let ax = dx * acceleration;
+            let ay = dy * acceleration;
+            
+            player.velocity.0 += ax * fixed_dt;
+            player.velocity.1 += ay * fixed_dt;
+            let friction = 0.8f32.powf(fixed_dt / TUNING_DT);
+            player.velocity.0 *= friction;
+            player.velocity.1 *= friction;
+            let speed = (player.velocity.0.powi(2) + player.velocity.1.powi(2)).sqrt();
+            if speed > max_speed {
+                let scale = max_speed / speed;
+                player.velocity.0 *= scale;
+                player.velocity.1 *= scale;
+            }
+            let (old_x, old_y) = (player.ship.x, player.ship.y);
+            player.ship.x += player.velocity.0 * fixed_dt;
+            player.ship.y += player.velocity.1 * fixed_dt;
+            player.ship.x = player.ship.x.clamp(0.0, game_width);
+            player.ship.y = player.ship.y.clamp(0.0, game_height);
+            if counting_stats {
+                player.stats.distance += ((player.ship.x - old_x).powi(2) + (player.ship.y - old_y).powi(2)).sqrt();
+            }
+            
+            // Handle projectile firing with boost button (using rocket_cooldown)
+            if player.input.boost && player.rocket_cooldown <= 0.0 {
+                println!("Player {} is attempting to fire a rocket. Cooldown: {}", player.id, player.rocket_cooldown);
+                
+                // Get aim direction from target coordinates (like ball shooting)
+                let mut dx = 0.0;
+                let mut dy = -1.0; // Default upward direction
+                let mut mag = 1.0;
+                
+                // Use target coordinates for direction
+                if let (Some(target_x), Some(target_y)) = (player.input.target_x, player.input.target_y) {
+                    dx = target_x - player.ship.x;
+                    dy = target_y - player.ship.y;
+                    mag = (dx * dx + dy * dy).sqrt();
+                    println!("Using target coordinates for rocket direction: ({}, {}), magnitude: {}", dx, dy, mag);
+                } else {
+                    println!("No target coordinates, using default upward direction for rocket");
+                }
+                
+                if mag > 0.0 {
+                    // Normalize direction
+                    let dir_x = dx / mag;
+                    let dir_y = dy / mag;
+                    
+                    // Calculate projectile velocity (constant speed)
+                    let projectile_speed = 125.0;
+                    let vx = dir_x * projectile_speed;
+                    let vy = dir_y * projectile_speed;
+                    
+                    // Add a portion of player's velocity to projectile
+                    let total_vx = vx + player.velocity.0 * 0.5;
+                    let total_vy = vy + player.velocity.1 * 0.5;
+                    
+                    // Position the projectile just outside the ship's radius
+                    let ship_radius = 20.0;
+                    let projectile_radius = 5.0;
+                    let offset = ship_radius + projectile_radius + 5.0;
+                    
+                    let projectile_x = player.ship.x + dir_x * offset;
+                    let projectile_y = player.ship.y + dir_y * offset;
+                    
+                    // Create and add the projectile
+                    let projectile = Projectile::new(
+                        self.next_projectile_id,
+                        projectile_x,
+                        projectile_y,
+                        total_vx,
+                        total_vy,
+                        player.id
+                    );
+                    
+                    self.projectiles.push(projectile);
+                    self.next_projectile_id += 1;
+                    
+                    // Apply rocket cooldown
+                    player.rocket_cooldown = 8.0; // Increased from 0.5 to 8.0 seconds
+                    
+                    // Reset the boost flag to prevent continuous firing
+                    player.input.boost = false;
+                    println!("Reset boost flag for player {}", player.id);
+                    
+                    // Apply recoil to the player (doubled for more impact!)
+                    let recoil_factor = 0.6; // Increased from 0.3 to 0.6 (2x more recoil)
+                    player.velocity.0 -= dir_x * projectile_speed * recoil_factor;
+                    player.velocity.1 -= dir_y * projectile_speed * recoil_factor;
+                    
+                    // Collect event to broadcast after mutable borrow ends
+                    let projectile_event = json!({
+                        "type": "projectile_fired",
+                        "player_id": player.id,
+                        "projectile": {
+                            "id": self.next_projectile_id - 1,
+                            "x": projectile_x,
+                            "y": projectile_y,
+                            "vx": total_vx,
+                            "vy": total_vy,
+                            "owner_id": player.id,
+                            "lifetime": 4.0
+                        }
+                    });
+                    
+                    events_to_broadcast.push((EventKind::ProjectileFired, projectile_event));
+                }
+            }
+        }
+        
+        // Broadcast collected events after mutable borrow ends
+        for (kind, event_data) in events_to_broadcast {
+            self.emit(kind, event_data);
+        }
+
+        // Ship-Wall Collision Resolution
+        for player in self.players.values_mut() {
+            for wall in map.walls() {
+                resolve_ship_collision(&mut player.ship, &mut player.velocity, wall);
+            }
+        }
+
+        // CORNERDEFENSE AUTO-SHOOTING: Server-side auto-shooting for maximum responsiveness
+        let is_cornerdefense_map = false; // Using corner.json - disable auto-shooting for soccer
+        
+        if is_cornerdefense_map && self.ball.grabbed {
+            if let Some(owner_id) = self.ball.owner {
+                if let Some(player) = self.players.get(&owner_id) {
+                    println!("AUTO-SHOOT DEBUG: Player {} has ball, cooldown: {:.3}, target: ({:?}, {:?})", 
+                            owner_id, player.shoot_cooldown, player.input.target_x, player.input.target_y);
+                    
+                    // Auto-shoot if player has target coordinates (remove cooldown check for now)
+                    if let (Some(target_x), Some(target_y)) = (player.input.target_x, player.input.target_y) {
+                        let dx = target_x - player.ship.x;
+                        let dy = target_y - player.ship.y;
+                        let distance = (dx * dx + dy * dy).sqrt();
+                        
+                        println!("AUTO-SHOOT DEBUG: Distance to target: {:.1}", distance);
+                        
+                        // Auto-shoot if mouse is far enough away (minimum distance check)
+                        if distance > 30.0 && player.shoot_cooldown <= 0.0 {
+                            println!("🔥 SERVER AUTO-SHOOTING for player {} at target ({:.1}, {:.1}), distance: {:.1}", 
+                                    owner_id, target_x, target_y, distance);
+                            
+                                                            // Trigger shooting by setting the shoot flag
+                                if let Some(player_mut) = self.players.get_mut(&owner_id) {
+                                    player_mut.input.shoot = true;
+                                    // Override the shoot cooldown for ultra-fast auto-shooting
+                                    player_mut.shoot_cooldown = 0.0; // Reset cooldown immediately for next auto-shot
+                                }
+                        } else if distance <= 30.0 {
+                            println!("AUTO-SHOOT DEBUG: Target too close ({:.1} <= 30.0)", distance);
+                        } else if player.shoot_cooldown > 0.0 {
+                            println!("AUTO-SHOOT DEBUG: Still in cooldown ({:.3})", player.shoot_cooldown);
+                        }
+                    } else {
+                        println!("AUTO-SHOOT DEBUG: No target coordinates available");
+                    }
+                }
+            }
+        }
+
+        // Process shooting using impulse - now based on target coordinates (like old rocket shooting)
+        if self.ball.grabbed {
+            if let Some(owner_id) = self.ball.owner {
+                if let Some(player) = self.players.get(&owner_id) {
+                    // Log player's shoot state and cooldown
+                    println!("Player {} shoot state: {}, cooldown: {}", owner_id, player.input.shoot, player.shoot_cooldown);
+                    
+                    if player.input.shoot && player.shoot_cooldown <= 0.0 {
+                        println!("Player {} is shooting the ball!", owner_id);
+                        
+                        // Get aim direction from target coordinates (like the old rocket shooting)
+                        let mut dx = 0.0;
+                        let mut dy = -1.0; // Default upward direction
+                        let mut mag = 1.0;
+                        
+                        // Use target coordinates for direction
+                        if let (Some(target_x), Some(target_y)) = (player.input.target_x, player.input.target_y) {
+                            dx = target_x - player.ship.x;
+                            dy = target_y - player.ship.y;
+                            mag = (dx * dx + dy * dy).sqrt();
+                            println!("Using target coordinates for ball direction: ({}, {}), magnitude: {}", dx, dy, mag);
+                        } else {
+                            // If no target coordinates, default to shooting upward
+                            println!("Using default upward direction for ball");
+                        }
+
+                        let max_allowed = 500.0;
+                        if mag > max_allowed {
+                            let scale = max_allowed / mag;
+                            dx *= scale;
+                            dy *= scale;
+                            mag = max_allowed;
+                        }
+
+                        let ship_mass = 1.0;
+                        let ball_mass = 0.5;
+                        let base_shot_force = 800.0;
+                        let dt = TUNING_DT;
+
+                        let aim_norm = (dx / mag, dy / mag);
+                        let impulse_base = (aim_norm.0 * base_shot_force * dt, aim_norm.1 * base_shot_force * dt);
+                        let additional_impulse = (player.velocity.0 * dt, player.velocity.1 * dt);
+                        let total_impulse = (impulse_base.0 + additional_impulse.0, impulse_base.1 + additional_impulse.1);
+
+                        let (ship_x, ship_y) = (player.ship.x, player.ship.y);
+
+                        self.ball.vx = total_impulse.0 / ball_mass;
+                        self.ball.vy = total_impulse.1 / ball_mass;
+                        
+                        // Cap the ball's maximum velocity to prevent tunneling
+                        let max_ball_speed = 500.0;
+                        let current_speed = (self.ball.vx * self.ball.vx + self.ball.vy * self.ball.vy).sqrt();
+                        if current_speed > max_ball_speed {
+                            let scale_factor = max_ball_speed / current_speed;
+                            self.ball.vx *= scale_factor;
+                            self.ball.vy *= scale_factor;
+                            println!("Capped ball velocity after shot: speed was {:.2}, now {:.2}", current_speed, max_ball_speed);
+                        }
+                        
+                        // Calculate normalized direction vector for ball positioning
+                        let dir_mag = (self.ball.vx * self.ball.vx + self.ball.vy * self.ball.vy).sqrt();
+                        let dir_x = if dir_mag > 0.0 { self.ball.vx / dir_mag } else { 0.0 };
+                        let dir_y = if dir_mag > 0.0 { self.ball.vy / dir_mag } else { 0.0 };
+                        
+                        // Use the helper function to position the ball safely
+                        self.position_ball_after_shot(ship_x, ship_y, dir_x, dir_y);
+
+                        // Set the last shooter and apply cooldown to prevent immediate grabbing
+                        self.ball.last_shooter = Some(owner_id);
+                        self.touches.touch(owner_id);
+                        if counting_stats {
+                            if let Some(shooter) = self.players.get_mut(&owner_id) {
+                                shooter.stats.shots += 1;
+                            }
+                        }
+                        
+                        // Collect events to broadcast after mutable borrow ends
+                        let mut shot_confirmation_opt = None;
+                        
+                        // Apply grab cooldown only to the shooter
+                        if let Some(player_mut) = self.players.get_mut(&owner_id) {
+                            player_mut.grab_cooldown = 0.3; // Increased to prevent tunneling through ships
+                            let recoil_factor = 1.0;
+                            player_mut.velocity.0 -= (total_impulse.0 / ship_mass) * recoil_factor;
+                            player_mut.velocity.1 -= (total_impulse.1 / ship_mass) * recoil_factor;
+                            player_mut.shoot_cooldown = 0.25;
+                            
+                            // Prepare confirmation for reliable shots
+                            if let Some(shot_id) = player_mut.pending_shot_id.take() {
+                                shot_confirmation_opt = Some(json!({
+                                    "type": "shot_processed",
+                                    "shot_id": shot_id,
+                                    "player_id": owner_id,
+                                    "ball_velocity": {
+                                        "x": self.ball.vx,
+                                        "y": self.ball.vy
+                                    },
+                                    "timestamp": now_ms
+                                }));
+                            }
+                            
+                            // Reset the shoot flag AFTER processing to prevent double-shooting
+                            player_mut.input.shoot = false;
+                            println!("Reset shoot flag for player {} after processing", owner_id);
+                        }
+
+                        self.ball.release(owner_id);
+                        
+                        // Send confirmation after mutable borrow ends
+                        if let Some(shot_confirmation) = shot_confirmation_opt {
+                            self.emit(EventKind::ShotProcessed, shot_confirmation);
+                        }
+                        
+                        // Send a message to all clients about the shot
+                        let shoot_event = json!({
+                            "type": "shoot",
+                            "player_id": owner_id
+                        });
+                        
+                        self.emit(EventKind::BallShot, shoot_event);
+                    }
+                }
+            }
+        }
+
+        // Player-Player Collision Resolution
+        let collision_radius = 20.0;
+        let player_ids: Vec<u32> = self.players.keys().cloned().collect();
+        let mut adjustments: HashMap<u32, (f32, f32)> = HashMap::new();
+        let mut velocity_changes: HashMap<u32, (f32, f32)> = HashMap::new();
+        
+        for i in 0..player_ids.len() {
+            for j in (i + 1)..player_ids.len() {
+                let id_i = player_ids[i];
+                let id_j = player_ids[j];
+                let (xi, yi) = (self.players[&id_i].ship.x, self.players[&id_i].ship.y);
+                let (xj, yj) = (self.players[&id_j].ship.x, self.players[&id_j].ship.y);
+                let dx = xi - xj;
+                let dy = yi - yj;
+                let dist = (dx * dx + dy * dy).sqrt();
+                if dist < collision_radius * 2.0 {
+                    // Calculate collision normal
+                    let overlap = collision_radius * 2.0 - dist;
+                    let (nx, ny) = if dist > 0.0 { (dx / dist, dy / dist) } else { (1.0, 0.0) };
+                    
+                    // Increase the collision force by using a multiplier
+                    let collision_force = 1.5; // Increase this value for more bumpiness
+                    let adjustment = (nx * overlap / 2.0 * collision_force, ny * overlap / 2.0 * collision_force);
+                    
+                    // Apply position adjustments
+                    adjustments.entry(id_i).and_modify(|e| { e.0 += adjustment.0; e.1 += adjustment.1; }).or_insert(adjustment);
+                    adjustments.entry(id_j).and_modify(|e| { e.0 -= adjustment.0; e.1 -= adjustment.1; }).or_insert((-adjustment.0, -adjustment.1));
+                    
+                    // Get player velocities
+                    let (vxi, vyi) = self.players[&id_i].velocity;
+                    let (vxj, vyj) = self.players[&id_j].velocity;
+                    
+                    // Calculate relative velocity
+                    let rvx = vxi - vxj;
+                    let rvy = vyi - vyj;
+                    
+                    // Calculate velocity along the normal
+                    let velocity_along_normal = rvx * nx + rvy * ny;
+                    
+                    // Only resolve if objects are moving toward each other
+                    if velocity_along_normal < 0.0 {
+                        // Calculate restitution (bounciness)
+                        let restitution = 0.6; // Higher values make collisions more bouncy
+                        
+                        // Calculate impulse scalar
+                        let impulse_scalar = -(1.0 + restitution) * velocity_along_normal;
+                        
+                        // Apply impulse to velocities
+                        let impulse_x = impulse_scalar * nx;
+                        let impulse_y = impulse_scalar * ny;
+                        
+                        // Store velocity changes
+                        velocity_changes.entry(id_i).and_modify(|e| { e.0 += impulse_x; e.1 += impulse_y; }).or_insert((impulse_x, impulse_y));
+                        velocity_changes.entry(id_j).and_modify(|e| { e.0 -= impulse_x; e.1 -= impulse_y; }).or_insert((-impulse_x, -impulse_y));
+                    }
+
+                    if self.ball.grabbed {
+                        if let Some(owner_id) = self.ball.owner {
+                            if owner_id == id_i || owner_id == id_j {
+                                if let Some((x, y)) = self.players.get(&owner_id).map(|p| (p.ship.x, p.ship.y)) {
+                                    self.ball.x = x;
+                                    self.ball.y = y;
+                                }
+                                self.ball.grabbed = false;
+                                self.ball.owner = None;
+                                
+                                // Apply longer grab cooldown to the player who had the ball
+                                // and a shorter cooldown to the other player to make it easier to steal
+                                if let Some(player) = self.players.get_mut(&id_i) {
+                                    if owner_id == id_i {
+                                        // Longer cooldown for the player who had the ball
+                                        player.grab_cooldown = 0.8; // Increased from 0.3
+                                    } else {
+                                        // Shorter cooldown for the player who didn't have the ball
+                                        player.grab_cooldown = 0.1; // Decreased from 0.3
+                                    }
+                                }
+                                if let Some(player) = self.players.get_mut(&id_j) {
+                                    if owner_id == id_j {
+                                        // Longer cooldown for the player who had the ball
+                                        player.grab_cooldown = 0.8; // Increased from 0.3
+                                    } else {
+                                        // Shorter cooldown for the player who didn't have the ball
+                                        player.grab_cooldown = 0.1; // Decreased from 0.3
+                                    }
+                                }
+                                
+                                self.ball.last_shooter = None;
+                                
+                                // Send a message to all clients about the ball being knocked loose
+                                let knock_event = json!({
+                                    "type": "ball_knocked",
+                                    "player_id": owner_id
+                                });
+                                
+                                self.emit(EventKind::BallKnocked, knock_event);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        
+        // Apply position adjustments
+        for (id, (dx, dy)) in adjustments {
+            if let Some(player) = self.players.get_mut(&id) {
+                player.ship.x += dx;
+                player.ship.y += dy;
+            }
+        }
+        
+        // Apply velocity changes
+        for (id, (dvx, dvy)) in velocity_changes {
+            if let Some(player) = self.players.get_mut(&id) {
+                player.velocity.0 += dvx;
+                player.velocity.1 += dvy;
+            }
+        }
+
+        // Sub-Stepped Ball Physics
+        let sub_steps = 10;
+        
+        // Increase sub-steps if ball is moving very fast to prevent tunneling
+        let ball_speed = (self.ball.vx * self.ball.vx + self.ball.vy * self.ball.vy).sqrt();
+        let max_safe_speed = 300.0; // Threshold for increasing sub-steps
+        
+        // Adaptively increase sub-steps based on ball speed
+        let adaptive_sub_steps = if ball_speed > max_safe_speed {
+            // Calculate how many more sub-steps we need based on speed
+            let speed_ratio = ball_speed / max_safe_speed;
+            let additional_steps = (speed_ratio * 10.0).ceil() as i32;
+            println!("Ball moving fast ({:.2}), increasing sub-steps to {}", ball_speed, sub_steps + additional_steps);
+            sub_steps + additional_steps
+        } else {
+            sub_steps
+        };
+        
+        let sub_dt = fixed_dt / adaptive_sub_steps as f32;
+        for _ in 0..adaptive_sub_steps {
+            self.ball.update_position(sub_dt, game_width, game_height);
+            
+            // Check for ball grabbing during each sub-step
+            if !self.ball.grabbed {
+                let ship_radius = 20.0;
+                let ball_radius = 10.0;
+                let grab_radius = ship_radius + ball_radius; // Removed the +5.0 buffer to make grab radius match ship size
+                let grab_radius_squared = grab_radius * grab_radius;
+                
+                let mut closest_id: Option<u32> = None;
+                let mut closest_dist2: f32 = f32::MAX;
+                let mut new_x = 0.0;
+                let mut new_y = 0.0;
+                
+                for player in self.players.values() {
+                    // Skip players who are on cooldown
+                    if player.grab_cooldown > 0.0 {
+                        continue;
+                    }
+                    
+                    // Check if ball is in pickup cooldown
+                    if self.ball.pickup_cooldown > 0.0 {
+                        continue; // No one can pick up the ball during cooldown (reduced logging)
+                    }
+                    
+                    // Check if ball has team restriction
+                    if let Some(ref exclusive_team) = self.ball.exclusive_team {
+                        let player_team_str = format!("{:?}", player.team);
+                        if player_team_str != *exclusive_team {
+                            // Only log when someone tries to grab but can't (reduced spam)
+                            continue; // Only the exclusive team can pick up the ball
+                        } else {
+                            println!("Player {} ({} team) can grab ball (exclusive access)", 
+                                player.id, player_team_str);
+                        }
+                    }
+                    
+                    let dx = player.ship.x - self.ball.x;
+                    let dy = player.ship.y - self.ball.y;
+                    let mut dist2 = dx * dx + dy * dy;
+                    
+                    // Lag compensation: also accept the loose ball where this player saw it
+                    if let Some((ball_x, ball_y)) = self.view_time(player.id, now_ms)
+                        .and_then(|view_time| self.position_history.loose_ball_at(view_time)) {
+                        let dx = player.ship.x - ball_x;
+                        let dy = player.ship.y - ball_y;
+                        dist2 = dist2.min(dx * dx + dy * dy);
+                    }
+                    
+                    // Check for line of sight to prevent grabbing through walls
+                    let mut can_grab = true;
+                    for wall in map.walls() {
+                        if super::collision::line_intersects_rect(
+                            self.ball.x, self.ball.y, 
+                            player.ship.x, player.ship.y,
+                            wall.x, wall.y, wall.width, wall.height
+                        ) {
+                            can_grab = false;
+                            break;
+                        }
+                    }
+                    
+                    if can_grab && dist2 < grab_radius_squared && dist2 < closest_dist2 {
+                        closest_dist2 = dist2;
+                        closest_id = Some(player.id);
+                        new_x = player.ship.x;
+                        new_y = player.ship.y;
+                    }
+                }
+                
+                if let Some(player_id) = closest_id {
+                    if let Some(player) = self.players.get(&player_id) {
+                        println!("Ball grabbed by player {} ({:?} team)", player_id, player.team);
+                    }
+                    self.ball.grab(player_id, new_x, new_y);
+                    self.touches.touch(player_id);
+                    
+                    // Clear exclusive team restriction when ball is successfully grabbed (this will reset ball color on client)
+                    self.ball.exclusive_team = None;
+                    
+                    break; // Exit the sub-step loop early since the ball is now grabbed
+                }
+            }
+            
+            // Ball-Wall Collision
+            let mut iterations = 0;
+            let max_iterations = 5;
+            
+            // Store the ball's previous position for ray-casting
+            let prev_x = self.ball.x - self.ball.vx * sub_dt;
+            let prev_y = self.ball.y - self.ball.vy * sub_dt;
+            
+            loop {
+                let mut collision_occurred = false;
+                for wall in map.walls() {
+                    let ball_left = self.ball.x - BALL_WIDTH / 2.0;
+                    let ball_right = self.ball.x + BALL_WIDTH / 2.0;
+                    let ball_top = self.ball.y - BALL_HEIGHT / 2.0;
+                    let ball_bottom = self.ball.y + BALL_HEIGHT / 2.0;
+
+                    // Standard AABB collision check
+                    let aabb_collision = ball_right > wall.x && ball_left < wall.x + wall.width &&
+                                         ball_bottom > wall.y && ball_top < wall.y + wall.height;
+                    
+                    // For fast-moving balls, also check if the ball's path intersects with the wall
+                    let ray_collision = if !aabb_collision && (self.ball.vx * self.ball.vx + self.ball.vy * self.ball.vy) > 90000.0 {
+                        // Check if the line from prev position to current position intersects with the wall
+                        super::collision::line_intersects_rect(
+                            prev_x, prev_y, 
+                            self.ball.x, self.ball.y,
+                            wall.x, wall.y, wall.width, wall.height
+                        )
+                    } else {
+                        false
+                    };
+                    
+                    if aabb_collision || ray_collision {
+                        if ray_collision {
+                            println!("Ray-casting detected collision with wall that AABB missed!");
+                        }
+                        super::collision::resolve_rect_collision(&mut self.ball, wall);
+                        collision_occurred = true;
+                    }
+                }
+                iterations += 1;
+                if !collision_occurred || iterations >= max_iterations {
+                    break;
+                }
+            }
+            
+            // Check for goal collision if not in cooldown
+            if self.goal_cooldown <= 0.0 && !self.ball.grabbed {
+                self.check_goal_collision(game_width, game_height);
+            }
+        }
+
+        // Update projectiles
+        let mut projectiles_to_explode: Vec<usize> = Vec::new();
+        
+        // First update positions and check for lifetime expiration
+        for (i, projectile) in self.projectiles.iter_mut().enumerate() {
+            if projectile.active {
+                projectile.update_position(fixed_dt);
+                
+                // Check if projectile is out of bounds
+                if projectile.x < 0.0 || projectile.x > game_width || 
+                   projectile.y < 0.0 || projectile.y > game_height {
+                    projectile.active = false;
+                }
+                
+                // Check if lifetime expired
+                if projectile.lifetime <= 0.0 {
+                    projectiles_to_explode.push(i);
+                }
+            }
+        }
+        
+        // Check for projectile collisions with walls
+        for (i, projectile) in self.projectiles.iter_mut().enumerate() {
+            if !projectile.active || projectiles_to_explode.contains(&i) {
+                continue;
+            }
+            
+            for wall in map.walls() {
+                let projectile_radius = 5.0;
+                let projectile_left = projectile.x - projectile_radius;
+                let projectile_right = projectile.x + projectile_radius;
+                let projectile_top = projectile.y - projectile_radius;
+                let projectile_bottom = projectile.y + projectile_radius;
+                
+                if projectile_right > wall.x && projectile_left < wall.x + wall.width &&
+                   projectile_bottom > wall.y && projectile_top < wall.y + wall.height {
+                    projectiles_to_explode.push(i);
+                    break;
+                }
+            }
+        }
+        
+        // Check for projectile collisions with players
+        for (i, projectile) in self.projectiles.iter().enumerate() {
+            if !projectile.active || projectiles_to_explode.contains(&i) {
+                continue;
+            }
+            
+            // Lag compensation: the rocket's owner aimed at where targets were at their view time
+            let owner_view_time = self.view_time(projectile.owner_id, now_ms);
+            
+            for (player_id, player) in self.players.iter() {
+                // Skip collision with the projectile owner
+                if *player_id == projectile.owner_id {
+                    continue;
+                }
+                
+                // Judge the hit where the owner saw the target, or where it is now if there is
+                // no history to rewind to
+                let (target_x, target_y) = owner_view_time
+                    .and_then(|view_time| self.position_history.player_at(*player_id, view_time))
+                    .unwrap_or((player.ship.x, player.ship.y));
+                let dx = target_x - projectile.x;
+                let dy = target_y - projectile.y;
+                let dist_squared = dx * dx + dy * dy;
+                
+                let collision_radius = 20.0 + 5.0; // Ship radius + projectile radius
+                if dist_squared < collision_radius * collision_radius {
+                    projectiles_to_explode.push(i);
+                    break;
+                }
+            }
+        }
+        
+        // Check for projectile-to-projectile collisions (rockets can hit each other!)
+        let mut rocket_collision_pairs: Vec<(usize, usize)> = Vec::new();
+        for i in 0..self.projectiles.len() {
+            if !self.projectiles[i].active || projectiles_to_explode.contains(&i) {
+                continue;
+            }
+            
+            for j in (i + 1)..self.projectiles.len() {
+                if !self.projectiles[j].active || projectiles_to_explode.contains(&j) {
+                    continue;
+                }
+                
+                let dx = self.projectiles[i].x - self.projectiles[j].x;
+                let dy = self.projectiles[i].y - self.projectiles[j].y;
+                let dist_squared = dx * dx + dy * dy;
+                
+                let collision_radius = 25.0; // Increased for easier rocket-to-rocket collisions!
+                if dist_squared < collision_radius * collision_radius {
+                    // Mark both rockets for explosion
+                    rocket_collision_pairs.push((i, j));
+                    if !projectiles_to_explode.contains(&i) {
+                        projectiles_to_explode.push(i);
+                    }
+                    if !projectiles_to_explode.contains(&j) {
+                        projectiles_to_explode.push(j);
+                    }
+                    println!("🚀💥 ROCKET COLLISION! Rockets {} and {} collided!", self.projectiles[i].id, self.projectiles[j].id);
+                    break;
+                }
+            }
+        }
+
+        // Check for projectile collisions with the ball
+        for (i, projectile) in self.projectiles.iter().enumerate() {
+            if !projectile.active || projectiles_to_explode.contains(&i) {
+                continue;
+            }
+            
+            // Ball is immune to rockets while in respawn state (pickup cooldown or colored ball)
+            if self.ball.pickup_cooldown > 0.0 || self.ball.exclusive_team.is_some() {
+                continue;
+            }
+            
+            let dx = self.ball.x - projectile.x;
+            let dy = self.ball.y - projectile.y;
+            let dist_squared = dx * dx + dy * dy;
+            
+            let collision_radius = 10.0 + 5.0; // Ball radius + projectile radius
+            if dist_squared < collision_radius * collision_radius {
+                projectiles_to_explode.push(i);
+            }
+        }
+        
+        // Process explosions with enhanced effects for rocket collisions
+        for &index in projectiles_to_explode.iter() {
+            if index < self.projectiles.len() {
+                let projectile = &self.projectiles[index];
+                
+                // Check if this projectile was involved in a rocket-to-rocket collision
+                let is_rocket_collision = rocket_collision_pairs.iter().any(|(i, j)| *i == index || *j == index);
+                
+                if is_rocket_collision {
+                    // Create a bigger explosion for rocket-to-rocket collisions!
+                    println!("💥💥 Creating ENHANCED rocket collision explosion at ({}, {})", projectile.x, projectile.y);
+                    self.create_enhanced_explosion(projectile.x, projectile.y, projectile.owner_id);
+                } else {
+                    // Regular explosion
+                    self.create_explosion(projectile.x, projectile.y, projectile.owner_id);
+                }
+                
+                self.projectiles[index].active = false;
+            }
+        }
+        
+        // Remove inactive projectiles
+        self.projectiles.retain(|p| p.active);
+        
+        self.record_position_history(now_ms);
+        
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.end_step(&self.players);
+        }
+        
+        // Phase changes come last: a new match finishes the replay, which needs this tick in it
+        self.update_match(fixed_dt);
+
+        std::mem::take(&mut self.events)
+    }
+    
+    // Add a method to check for goal collisions
+    fn check_goal_collision(&mut self, _game_width: f32, _game_height: f32) {
+        let map = self.map.clone();
+        let ball_left = self.ball.x - BALL_WIDTH / 2.0;
+        let ball_right = self.ball.x + BALL_WIDTH / 2.0;
+        let ball_top = self.ball.y - BALL_HEIGHT / 2.0;
+        let ball_bottom = self.ball.y + BALL_HEIGHT / 2.0;
+        
+        // Debug: Log ball bounds (commented out to reduce spam)
+        // println!("Ball bounds: left={:.1}, right={:.1}, top={:.1}, bottom={:.1}", ball_left, ball_right, ball_top, ball_bottom);
+        
+        // Determine the middle Y position to distinguish north from south goals
+        let mut min_y = f32::INFINITY;
+        let mut max_y = f32::NEG_INFINITY;
+        let mut min_x = f32::INFINITY;
+        let mut max_x = f32::NEG_INFINITY;
+        
+        // Find the min and max coordinates of all goals
+        for goal in map.goals() {
+            min_y = min_y.min(goal.y);
+            max_y = max_y.max(goal.y + goal.height);
+            min_x = min_x.min(goal.x);
+            max_x = max_x.max(goal.x + goal.width);
+        }
+        
+        // Calculate the middle position
+        let middle_y = (min_y + max_y) / 2.0;
+        let middle_x = (min_x + max_x) / 2.0;
+        
+        // println!("Ball position: ({}, {})", self.ball.x, self.ball.y);
+        // println!("Goal Y range: {} to {}, middle: {}", min_y, max_y, middle_y);
+        
+        // Check if ball is in a goal area (the goals are shut at halftime and after the match)
+        if self.goal_cooldown <= 0.0 && self.match_state.phase.goals_open() {
+            for goal in map.goals() {
+                // Add debug print for each goal (commented out to reduce spam)
+                // println!("Checking goal: type={}, x={}, y={}, w={}, h={}", goal.obj_type, goal.x, goal.y, goal.width, goal.height);
+                // Add a small margin to the collision check
+                let margin = 1.0;
+                if ball_right >= goal.x - margin && ball_left <= goal.x + goal.width + margin &&
+                   ball_bottom >= goal.y - margin && ball_top <= goal.y + goal.height + margin {
+                    println!("GOAL SCORED! Ball hit {} goal", goal.obj_type);
+                    
+                    // Determine which team was scored on based on the goal type
+                    let scored_on_team = match goal.obj_type.as_str() {
+                        "goal_red" => Team::Red,      // Red goal hit = Red team scored on
+                        "goal_blue" => Team::Blue,    // Blue goal hit = Blue team scored on  
+                        "goal_yellow" => Team::Yellow, // Yellow goal hit = Yellow team scored on
+                        "goal_green" => Team::Green,  // Green goal hit = Green team scored on
+                        _ => continue, // Skip if not a valid goal type
+                    };
+                    
+                    // Get the player who scored (last shooter, or whoever had the ball last if it was knocked loose)
+                    let scorer_id = self.ball.last_shooter.or(self.touches.last);
+                    let scorer_name = match scorer_id.and_then(|id| self.players.get(&id)) {
+                        Some(player) => player.display_name.clone(),
+                        None => "Unknown Player".to_string(),
+                    };
+                    let own_goal = scorer_id.and_then(|id| self.players.get(&id))
+                        .is_some_and(|player| player.team == scored_on_team);
+                    
+                    // Warmup goals reset the ball like any other, but don't score
+                    if self.match_state.phase.goals_count() {
+                        self.credit_goal(scorer_id, own_goal);
+                        
+                        // Update scores based on which team was scored on
+                        // In corner defense, when your goal is hit, all OTHER teams get points
+                        if self.is_soccer_map() {
+                            // Soccer map: only Red vs Blue
+                            match scored_on_team {
+                                Team::Red => {
+                                    // Red goal was hit, so Blue team gets point
+                                    self.team2_score += 1; // Blue gets point
+                                },
+                                Team::Blue => {
+                                    // Blue goal was hit, so Red team gets point
+                                    self.team1_score += 1; // Red gets point
+                                },
+                                _ => {} // Yellow and Green goals shouldn't exist on soccer map
+                            }
+                        } else {
+                            // Corner defense map: all four teams
+                            match scored_on_team {
+                                Team::Red => {
+                                    // Red goal was hit, so Blue, Yellow, and Green teams get points
+                                    self.team2_score += 1; // Blue gets point
+                                    self.team3_score += 1; // Yellow gets point
+                                    self.team4_score += 1; // Green gets point
+                                },
+                                Team::Blue => {
+                                    // Blue goal was hit, so Red, Yellow, and Green teams get points
+                                    self.team1_score += 1; // Red gets point
+                                    self.team3_score += 1; // Yellow gets point
+                                    self.team4_score += 1; // Green gets point
+                                },
+                                Team::Yellow => {
+                                    // Yellow goal was hit, so Red, Blue, and Green teams get points
+                                    self.team1_score += 1; // Red gets point
+                                    self.team2_score += 1; // Blue gets point
+                                    self.team4_score += 1; // Green gets point
+                                },
+                                Team::Green => {
+                                    // Green goal was hit, so Red, Blue, and Yellow teams get points
+                                    self.team1_score += 1; // Red gets point
+                                    self.team2_score += 1; // Blue gets point
+                                    self.team3_score += 1; // Yellow gets point
+                                },
+                            }
+                        }
+                    }
+                    
+                    // Reset ball position and state
+                    self.ball.x = middle_x;
+                    self.ball.y = middle_y;
+                    self.ball.vx = 0.0;
+                    self.ball.vy = 0.0;
+                    self.ball.grabbed = false;
+                    self.ball.owner = None;
+                    self.ball.grab_cooldown = 0.5;
+                    self.ball.last_shooter = None;
+                    self.touches.clear();
+                    self.goal_cooldown = 2.0;
+                    
+                    // Set pickup cooldown and team restriction
+                    self.ball.pickup_cooldown = 3.0; // 3 second cooldown
+                    self.ball.exclusive_team = Some(format!("{:?}", scored_on_team)); // Team that was scored on gets exclusive pickup
+                    
+                    println!("Ball glowing for 3s, then exclusive to {:?} team", scored_on_team);
+                    
+                    // Send goal event to all clients
+                    let goal_event = json!({
+                        "type": "goal",
+                        "scored_on_team": format!("{:?}", scored_on_team),
+                        "scorer_name": scorer_name,
+                        "scorer_id": scorer_id,
+                        "own_goal": own_goal,
+                        "team1_score": self.team1_score,
+                        "team2_score": self.team2_score,
+                        "team3_score": self.team3_score,
+                        "team4_score": self.team4_score,
+                        "ball_exclusive_team": format!("{:?}", scored_on_team)
+                    });
+                    
+                    self.emit(EventKind::Goal, goal_event);
+                    self.after_goal();
+                    
+                    break;
+                }
+            }
+        }
+    }
+
+    // Full world state after the last step, as sent to clients
+    pub fn create_snapshot(&self) -> GameStateSnapshot {
+        let mut players = HashMap::new();
+        for (id, player) in &self.players {
+            players.insert(*id, ShipState {
+                x: player.ship.x,
+                y: player.ship.y,
+                seq: player.last_seq,
+                fuel: player.fuel,
+                team: player.team,
+                display_name: player.display_name.clone(),
+                rocket_cooldown: player.rocket_cooldown,
+            });
+        }
+        
+        GameStateSnapshot {
+            tick: self.tick,
+            snapshot_id: self.snapshot_seq,
+            time: self.time_ms,
+            players,
+            ball: self.ball.clone(),
+            projectiles: self.projectiles.clone(),
+            team1_score: self.team1_score,
+            team2_score: self.team2_score,
+            team3_score: self.team3_score,
+            team4_score: self.team4_score,
+            match_state: self.match_state,
+        }
+    }
+    
+    // Add a method to reset the game: restart the match on the current map
+    pub fn reset_game(&mut self) {
+        self.start_new_match(false, false);
+    }
+    
+    // Restart the match on another map, staying in the rotation if the map is part of it
+    pub fn switch_map(&mut self, map: Arc<GameMap>) {
+        if let Some(index) = self.map_rotation.iter().position(|m| m.id == map.id) {
+            self.rotation_index = index;
+        }
+        println!("Switching map from '{}' to '{}'", self.map.id, map.id);
+        self.change_map(map);
+        self.start_new_match(false, false);
+    }
+    
+    // Kick off now instead of waiting in warmup for min_players. Returns false if a match is
+    // already being played.
+    pub fn start_match_now(&mut self) -> bool {
+        if self.match_state.phase != MatchPhase::Warmup {
+            return false;
+        }
+        self.start_new_match(false, true);
+        true
+    }
+    
+    // Start a new match from kickoff, moving on to the next map in the rotation if asked. Without
+    // `force_kickoff` the match waits in warmup until there are enough players.
+    fn start_new_match(&mut self, next_map: bool, force_kickoff: bool) {
+        // A new match ends the previous one: finish its replay, and record the next match from the reset state
+        let finished_replay = self.recorder.take();
+        
+        if next_map {
+            self.advance_map_rotation();
+        }
+        
+        // Reset scores and player stats
+        self.team1_score = 0;
+        self.team2_score = 0;
+        self.team3_score = 0;
+        self.team4_score = 0;
+        for player in self.players.values_mut() {
+            player.stats = PlayerStats::default();
+        }
+        
+        // Notify all clients about the reset
+        let reset_event = serde_json::json!({
+            "type": "game_reset",
+            "map_id": self.map.id,
+            "team1_score": self.team1_score,
+            "team2_score": self.team2_score,
+            "team3_score": self.team3_score,
+            "team4_score": self.team4_score
+        });
+        
+        self.emit(EventKind::GameReset, reset_event);
+        
+        // Kick off the first half, or wait in warmup if there aren't enough players yet
+        self.match_state.forced = force_kickoff;
+        if force_kickoff || self.players.len() >= self.match_settings.min_players {
+            self.kickoff();
+            self.set_match_phase(MatchPhase::Countdown, 1, 0.0);
+        } else {
+            self.kickoff_positions();
+            self.set_match_phase(MatchPhase::Warmup, 0, 0.0);
+        }
+        
+        if let Some(recorder) = finished_replay {
+            let (fixed_dt, game_width, game_height) = recorder.settings();
+            self.finished_replays.push(recorder.finish());
+            self.start_recording(fixed_dt, game_width, game_height);
+        }
+    }
+    
+    // Put everyone back in kickoff position and start the countdown
+    fn kickoff(&mut self) {
+        self.kickoff_positions();
+        
+        // Set goal cooldown to prevent immediate scoring
+        self.goal_cooldown = 5.0;
+        
+        // Start countdown
+        self.start_countdown();
+    }
+    
+    // Ball to the middle between the goals, players next to it on their side, no projectiles
+    fn kickoff_positions(&mut self) {
+        // Players are about to be repositioned, so old positions can't be rewound to
+        self.position_history.clear();
+        // Find the min and max coordinates of all goals to determine the middle point
+        // This reuses the same logic as in check_goal_collision
+        let mut min_y = f32::INFINITY;
+        let mut max_y = f32::NEG_INFINITY;
+        let mut min_x = f32::INFINITY;
+        let mut max_x = f32::NEG_INFINITY;
+        
+        // Find the min and max coordinates of all goals
+        for goal in self.map.goals() {
+            min_y = min_y.min(goal.y);
+            max_y = max_y.max(goal.y + goal.height);
+            min_x = min_x.min(goal.x);
+            max_x = max_x.max(goal.x + goal.width);
+        }
+        
+        // Calculate the middle position between goals
+        let middle_x = (min_x + max_x) / 2.0;
+        let middle_y = (min_y + max_y) / 2.0;
+        
+        println!("RESET: Goal bounds - min_x: {}, max_x: {}, min_y: {}, max_y: {}", min_x, max_x, min_y, max_y);
+        println!("RESET: Calculated middle position: ({}, {})", middle_x, middle_y);
+        
+        // Reset ball position to the middle between goals
+        self.ball.x = middle_x;
+        self.ball.y = middle_y;
+        self.ball.vx = 0.0;
+        self.ball.vy = 0.0;
+        self.ball.grabbed = false;
+        self.ball.owner = None;
+        self.ball.last_shooter = None;
+        self.touches.clear();
+        self.ball.shot_clock = 10.0;
+        self.ball.pickup_cooldown = 0.0;
+        self.ball.exclusive_team = None;
+        
+        // Reset player positions based on team - closer to the ball
+        for (_, player) in self.players.iter_mut() {
+            match player.team {
+                Team::Red => {
+                    // Position red team players near the ball, slightly to the left
+                    player.ship.x = middle_x - 150.0;
+                    player.ship.y = middle_y + (self.rng.next_f32() - 0.5) * 100.0;
+                },
+                Team::Blue => {
+                    // Position blue team players near the ball, slightly to the right
+                    player.ship.x = middle_x + 150.0;
+                    player.ship.y = middle_y + (self.rng.next_f32() - 0.5) * 100.0;
+                },
+                Team::Yellow => {
+                    // Position yellow team players near the ball, slightly to the right
+                    player.ship.x = middle_x + 150.0;
+                    player.ship.y = middle_y + (self.rng.next_f32() - 0.5) * 100.0;
+                },
+                Team::Green => {
+                    // Position green team players near the ball, slightly to the left
+                    player.ship.x = middle_x - 150.0;
+                    player.ship.y = middle_y + (self.rng.next_f32() - 0.5) * 100.0;
+                },
+            }
+            // Reset player velocity
+            player.velocity = (0.0, 0.0);
+            
+            // BUGFIX: Reset sequence number to allow input after reset
+            player.last_seq = 0;
+        }
+        
+        // Clear all projectiles
+        self.projectiles.clear();
+    }
+    
+    fn set_match_phase(&mut self, phase: MatchPhase, half: u32, clock: f32) {
+        self.match_state = MatchState { phase, half, clock, ..self.match_state };
+        println!("Match phase: {} (half {}, clock {:.0}s)", phase.name(), half, clock);
+        self.emit(EventKind::MatchPhase, self.match_state.to_json());
+    }
+    
+    // Advance the match clock and move between phases. Called at the end of each step.
+    fn update_match(&mut self, fixed_dt: f32) {
+        let settings = self.match_settings;
+        let state = self.match_state;
+        match state.phase {
+            MatchPhase::Warmup => {
+                if self.players.len() >= settings.min_players {
+                    self.start_new_match(false, false);
+                }
+            }
+            MatchPhase::Countdown => {
+                // A match the host started goes ahead however many players there are
+                if !state.forced && self.players.len() < settings.min_players {
+                    self.set_match_phase(MatchPhase::Warmup, 0, 0.0);
+                } else if self.countdown_remaining <= 0.0 {
+                    self.set_match_phase(MatchPhase::Live, state.half, settings.half_duration);
+                }
+            }
+            MatchPhase::Live => {
+                self.match_state.clock = (state.clock - fixed_dt).max(0.0);
+                if self.match_state.clock <= 0.0 {
+                    self.end_of_half();
+                }
+            }
+            MatchPhase::Halftime => {
+                self.match_state.clock = (state.clock - fixed_dt).max(0.0);
+                if self.match_state.clock <= 0.0 {
+                    self.kickoff();
+                    self.set_match_phase(MatchPhase::Countdown, state.half + 1, 0.0);
+                }
+            }
+            MatchPhase::Overtime => {
+                self.match_state.clock = state.clock + fixed_dt;
+            }
+            MatchPhase::Finished => {
+                self.match_state.clock = (state.clock - fixed_dt).max(0.0);
+                if self.match_state.clock <= 0.0 {
+                    self.start_new_match(true, false);
+                }
+            }
+        }
+    }
+    
+    fn end_of_half(&mut self) {
+        let settings = self.match_settings;
+        if self.match_state.half < settings.halves {
+            self.set_match_phase(MatchPhase::Halftime, self.match_state.half, settings.halftime_duration);
+        } else if settings.overtime && self.leading_teams().len() > 1 {
+            self.set_match_phase(MatchPhase::Overtime, self.match_state.half, 0.0);
+        } else {
+            self.finish_match(MatchEndReason::FullTime);
+        }
+    }
+    
+    // A goal in overtime wins the match, and so does reaching the score limit
+    fn after_goal(&mut self) {
+        match self.match_state.phase {
+            MatchPhase::Overtime => self.finish_match(MatchEndReason::GoldenGoal),
+            MatchPhase::Live => {
+                let limit_reached = self.match_settings.score_limit
+                    .is_some_and(|limit| self.team_scores().iter().any(|(_, score)| *score >= limit));
+                if limit_reached {
+                    self.finish_match(MatchEndReason::ScoreLimit);
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    // Scores of the teams playing on this map
+    fn team_scores(&self) -> Vec<(Team, u32)> {
+        let scores = vec![
+            (Team::Red, self.team1_score),
+            (Team::Blue, self.team2_score),
+            (Team::Yellow, self.team3_score),
+            (Team::Green, self.team4_score),
+        ];
+        let teams = self.playable_teams();
+        scores.into_iter().filter(|(team, _)| teams.contains(team)).collect()
+    }
+    
+    // Teams sharing the highest score
+    fn leading_teams(&self) -> Vec<Team> {
+        let scores = self.team_scores();
+        let best = scores.iter().map(|(_, score)| *score).max().unwrap_or(0);
+        scores.into_iter().filter(|(_, score)| *score == best).map(|(team, _)| team).collect()
+    }
+    
+    fn finish_match(&mut self, reason: MatchEndReason) {
+        let winners = self.leading_teams();
+        let draw = winners.len() > 1;
+        println!("Match finished ({:?}): winners {:?}, draw: {}", reason, winners, draw);
+        
+        self.emit(EventKind::MatchEnd, json!({
+            "type": "match_end",
+            "reason": reason,
+            "map_id": self.map.id,
+            "winners": winners,
+            "draw": draw,
+            "team1_score": self.team1_score,
+            "team2_score": self.team2_score,
+            "team3_score": self.team3_score,
+            "team4_score": self.team4_score,
+            "halves_played": self.match_state.half,
+            "overtime": self.match_state.phase == MatchPhase::Overtime
+        }));
+        self.set_match_phase(MatchPhase::Finished, self.match_state.half, self.match_settings.results_duration);
+        self.emit(EventKind::Scoreboard, self.scoreboard());
+    }
+    
+    // Credit a goal to its scorer, and the assist to the teammate who had the ball before them
+    fn credit_goal(&mut self, scorer_id: Option<u32>, own_goal: bool) {
+        let scorer_id = match scorer_id {
+            Some(id) => id,
+            None => return,
+        };
+        let scorer_team = match self.players.get(&scorer_id) {
+            Some(player) => player.team,
+            None => return,
+        };
+        let assist_id = self.touches.previous
+            .filter(|id| !own_goal && *id != scorer_id)
+            .filter(|id| self.players.get(id).is_some_and(|player| player.team == scorer_team));
+        
+        if let Some(scorer) = self.players.get_mut(&scorer_id) {
+            if own_goal {
+                scorer.stats.own_goals += 1;
+            } else {
+                scorer.stats.goals += 1;
+            }
+        }
+        if let Some(assister) = assist_id.and_then(|id| self.players.get_mut(&id)) {
+            assister.stats.assists += 1;
+        }
+    }
+    
+    // Credit the owner of an exploding rocket with the opponents it hit and the balls it knocked loose
+    fn credit_rocket_hits(&mut self, owner_id: u32, opponents_hit: u32, steals: u32) {
+        if !self.match_state.phase.goals_count() {
+            return;
+        }
+        if let Some(owner) = self.players.get_mut(&owner_id) {
+            owner.stats.rocket_hits += opponents_hit;
+            owner.stats.steals += steals;
+        }
+    }
+    
+    // Every player's stats for the current match, sent at the end of a match and on request
+    pub fn scoreboard(&self) -> serde_json::Value {
+        let mut entries: Vec<ScoreboardEntry> = self.players.values()
+            .map(|player| ScoreboardEntry::new(player, self.is_bot(player.id)))
+            .collect();
+        sort_scoreboard(&mut entries);
+        json!({
+            "type": "scoreboard",
+            "map_id": self.map.id,
+            "match_state": self.match_state,
+            "team1_score": self.team1_score,
+            "team2_score": self.team2_score,
+            "team3_score": self.team3_score,
+            "team4_score": self.team4_score,
+            "players": entries
+        })
+    }
+    
+    // Start the 5 second kickoff countdown; step() counts it down to 0 one event per second
+    pub fn start_countdown(&mut self) {
+        self.countdown_remaining = 5.0;
+        self.emit(EventKind::Countdown, json!({
+            "type": "countdown",
+            "count": 5
+        }));
+    }
+    
+    fn update_countdown(&mut self, fixed_dt: f32) {
+        if self.countdown_remaining <= 0.0 {
+            return;
+        }
+        
+        let before = self.countdown_remaining.ceil() as u32;
+        self.countdown_remaining = (self.countdown_remaining - fixed_dt).max(0.0);
+        let after = self.countdown_remaining.ceil() as u32;
+        
+        // Count 0 indicates game start
+        if after < before {
+            self.emit(EventKind::Countdown, json!({
+                "type": "countdown",
+                "count": after
+            }));
+        }
+    }
+
+    // Add a method to create an explosion effect
+    fn create_explosion(&mut self, x: f32, y: f32, owner_id: u32) {
+        // Define explosion parameters
+        let explosion_radius = 100.0;
+        let explosion_force = 300.0;
+        let direct_hit_radius = 25.0; // Players within this radius get 3x knockback (direct hit)
+        
+        // Direct hits are judged against where the rocket's owner saw each target (lag compensation)
+        let owner_view_time = self.view_time(owner_id, self.time_ms);
+        let rewound_positions: HashMap<u32, (f32, f32)> = match owner_view_time {
+            Some(view_time) => self.players.keys()
+                .filter_map(|id| self.position_history.player_at(*id, view_time).map(|pos| (*id, pos)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        
+        // Opponents caught in the blast count as hits for the rocket's owner
+        let owner_team = self.players.get(&owner_id).map(|player| player.team);
+        let mut opponents_hit = 0;
+        let mut steals = 0;
+        
+        // Apply knockback to players in range
+        let mut events_to_broadcast = Vec::new();
+        for (player_id, player) in self.players.iter_mut() {
+            let dx = player.ship.x - x;
+            let dy = player.ship.y - y;
+            let dist = (dx * dx + dy * dy).sqrt();
+            // Direct hits use the rewound position when there is one; the blast itself pushes
+            // players from where they are now
+            let direct_hit_dist = match rewound_positions.get(player_id) {
+                Some((past_x, past_y)) => ((past_x - x).powi(2) + (past_y - y).powi(2)).sqrt(),
+                None => dist,
+            };
+            let direct_hit = direct_hit_dist < direct_hit_radius;
+            
+            if dist < explosion_radius || direct_hit {
+                // Calculate normalized direction away from explosion
+                let dir_x = if dist > 0.0 { dx / dist } else { 1.0 };
+                let dir_y = if dist > 0.0 { dy / dist } else { 0.0 };
+                
+                // Calculate force based on distance (stronger closer to center)
+                let force_dist = if direct_hit { direct_hit_dist } else { dist };
+                let force_multiplier = 1.0 - (force_dist / explosion_radius);
+                
+                // DIRECT HIT BONUS: 3x knockback for close hits!
+                let direct_hit_bonus = if direct_hit { 
+                    println!("💥 DIRECT HIT! Player {} took 3x rocket damage at distance {:.1}", player_id, direct_hit_dist);
+                    3.0 
+                } else { 
+                    1.0 
+                };
+                
+                let force_x = dir_x * explosion_force * force_multiplier * direct_hit_bonus;
+                let force_y = dir_y * explosion_force * force_multiplier * direct_hit_bonus;
+                
+                // Apply impulse to player
+                player.velocity.0 += force_x;
+                player.velocity.1 += force_y;
+                
+                let is_opponent = owner_team.is_some_and(|team| team != player.team);
+                if is_opponent {
+                    opponents_hit += 1;
+                }
+                
+                // If this player has the ball, they lose it
+                if self.ball.grabbed && self.ball.owner == Some(*player_id) {
+                    if is_opponent {
+                        steals += 1;
+                    }
+                    self.ball.grabbed = false;
+                    self.ball.owner = None;
+                    self.ball.x = player.ship.x;
+                    self.ball.y = player.ship.y;
+                    
+                    // Apply impulse to the ball in the same direction
+                    self.ball.vx = force_x * 1.5;
+                    self.ball.vy = force_y * 1.5;
+                    
+                    // Apply grab cooldown
+                    player.grab_cooldown = 0.5;
+                    
+                    // Collect event to broadcast after mutable borrow ends  
+                    let knock_event = json!({
+                        "type": "ball_knocked",
+                        "player_id": *player_id
+                    });
+                    
+                    events_to_broadcast.push((EventKind::BallKnocked, knock_event));
+                }
+            }
+        }
+        
+        // Broadcast collected events after mutable borrow ends
+        for (kind, event_data) in events_to_broadcast {
+            self.emit(kind, event_data);
+        }
+        self.credit_rocket_hits(owner_id, opponents_hit, steals);
+        
+        // Apply knockback to the ball if it's not grabbed
+        if !self.ball.grabbed {
+            // Ball is immune to explosion knockback while in respawn state (pickup cooldown or colored ball)
+            if self.ball.pickup_cooldown > 0.0 || self.ball.exclusive_team.is_some() {
+                // Skip explosion knockback for respawn ball
+                println!("Ball immune to explosion knockback (respawn state)");
+            } else {
+                let dx = self.ball.x - x;
+                let dy = self.ball.y - y;
+                let dist = (dx * dx + dy * dy).sqrt();
+                
+                if dist < explosion_radius {
+                // Calculate normalized direction away from explosion
+                let dir_x = if dist > 0.0 { dx / dist } else { 1.0 };
+                let dir_y = if dist > 0.0 { dy / dist } else { 0.0 };
+                
+                // Calculate force based on distance (stronger closer to center)
+                let force_multiplier = 1.0 - (dist / explosion_radius);
+                let force_x = dir_x * explosion_force * force_multiplier * 2.0; // Stronger effect on ball
+                let force_y = dir_y * explosion_force * force_multiplier * 2.0;
+                
+                // Apply impulse to ball
+                self.ball.vx += force_x;
+                self.ball.vy += force_y;
+                
+                // Cap the ball's maximum velocity to prevent tunneling
+                let max_ball_speed = 500.0;
+                let current_speed = (self.ball.vx * self.ball.vx + self.ball.vy * self.ball.vy).sqrt();
+                if current_speed > max_ball_speed {
+                    let scale_factor = max_ball_speed / current_speed;
+                    self.ball.vx *= scale_factor;
+                    self.ball.vy *= scale_factor;
+                    println!("Capped ball velocity after explosion: speed was {:.2}, now {:.2}", current_speed, max_ball_speed);
+                }
+            }
+            }
+        }
+        
+        // Send explosion event to all clients
+        let explosion_event = json!({
+            "type": "explosion",
+            "x": x,
+            "y": y,
+            "radius": explosion_radius,
+            "player_id": owner_id
+        });
+        
+        self.emit(EventKind::Explosion, explosion_event);
+    }
+
+    // Add a method to create an ENHANCED explosion effect for rocket-to-rocket collisions
+    fn create_enhanced_explosion(&mut self, x: f32, y: f32, owner_id: u32) {
+        // Enhanced explosion parameters - BIGGER AND STRONGER!
+        let explosion_radius = 150.0; // 50% larger radius
+        let explosion_force = 450.0; // 50% more force
+        let direct_hit_radius = 35.0; // Larger direct hit radius
+        
+        println!("💥💥💥 ENHANCED ROCKET COLLISION EXPLOSION! Radius: {}, Force: {}", explosion_radius, explosion_force);
+        
+        // Opponents caught in the blast count as hits for the rocket's owner
+        let owner_team = self.players.get(&owner_id).map(|player| player.team);
+        let mut opponents_hit = 0;
+        let mut steals = 0;
+        
+        // Apply knockback to players in range
+        let mut events_to_broadcast = Vec::new();
+        for (player_id, player) in self.players.iter_mut() {
+            let dx = player.ship.x - x;
+            let dy = player.ship.y - y;
+            let dist = (dx * dx + dy * dy).sqrt();
+            
+            if dist < explosion_radius {
+                // Calculate normalized direction away from explosion
+                let dir_x = if dist > 0.0 { dx / dist } else { 1.0 };
+                let dir_y = if dist > 0.0 { dy / dist } else { 0.0 };
+                
+                // Calculate force based on distance (stronger closer to center)
+                let force_multiplier = 1.0 - (dist / explosion_radius);
+                
+                // DIRECT HIT BONUS: 3x knockback for close hits!
+                let direct_hit_bonus = if dist < direct_hit_radius { 
+                    println!("💥💥 ENHANCED DIRECT HIT! Player {} took 3x enhanced rocket damage at distance {:.1}", player_id, dist);
+                    3.0 
+                } else { 
+                    1.0 
+                };
+                
+                let force_x = dir_x * explosion_force * force_multiplier * direct_hit_bonus;
+                let force_y = dir_y * explosion_force * force_multiplier * direct_hit_bonus;
+                
+                // Apply impulse to player
+                player.velocity.0 += force_x;
+                player.velocity.1 += force_y;
+                
+                let is_opponent = owner_team.is_some_and(|team| team != player.team);
+                if is_opponent {
+                    opponents_hit += 1;
+                }
+                
+                // If this player has the ball, they lose it
+                if self.ball.grabbed && self.ball.owner == Some(*player_id) {
+                    if is_opponent {
+                        steals += 1;
+                    }
+                    self.ball.grabbed = false;
+                    self.ball.owner = None;
+                    self.ball.x = player.ship.x;
+                    self.ball.y = player.ship.y;
+                    
+                    // Apply impulse to the ball in the same direction (enhanced)
+                    self.ball.vx = force_x * 1.5;
+                    self.ball.vy = force_y * 1.5;
+                    
+                    // Apply grab cooldown
+                    player.grab_cooldown = 0.5;
+                    
+                    // Collect event to broadcast after mutable borrow ends  
+                    let knock_event = json!({
+                        "type": "ball_knocked",
+                        "player_id": *player_id
+                    });
+                    
+                    events_to_broadcast.push((EventKind::BallKnocked, knock_event));
+                }
+            }
+        }
+        
+        // Broadcast collected events after mutable borrow ends
+        for (kind, event_data) in events_to_broadcast {
+            self.emit(kind, event_data);
+        }
+        self.credit_rocket_hits(owner_id, opponents_hit, steals);
+        
+        // Apply enhanced knockback to the ball if it's not grabbed
+        if !self.ball.grabbed {
+            // Ball is immune to explosion knockback while in respawn state (pickup cooldown or colored ball)
+            if self.ball.pickup_cooldown > 0.0 || self.ball.exclusive_team.is_some() {
+                // Skip explosion knockback for respawn ball
+                println!("Ball immune to enhanced explosion knockback (respawn state)");
+            } else {
+                let dx = self.ball.x - x;
+                let dy = self.ball.y - y;
+                let dist = (dx * dx + dy * dy).sqrt();
+                
+                if dist < explosion_radius {
+                // Calculate normalized direction away from explosion
+                let dir_x = if dist > 0.0 { dx / dist } else { 1.0 };
+                let dir_y = if dist > 0.0 { dy / dist } else { 0.0 };
+                
+                // Calculate force based on distance (stronger closer to center)
+                let force_multiplier = 1.0 - (dist / explosion_radius);
+                let force_x = dir_x * explosion_force * force_multiplier * 2.5; // Even stronger effect on ball
+                let force_y = dir_y * explosion_force * force_multiplier * 2.5;
+                
+                // Apply impulse to ball
+                self.ball.vx += force_x;
+                self.ball.vy += force_y;
+                
+                // Cap the ball's maximum velocity to prevent tunneling
+                let max_ball_speed = 500.0;
+                let current_speed = (self.ball.vx * self.ball.vx + self.ball.vy * self.ball.vy).sqrt();
+                if current_speed > max_ball_speed {
+                    let scale_factor = max_ball_speed / current_speed;
+                    self.ball.vx *= scale_factor;
+                    self.ball.vy *= scale_factor;
+                    println!("Capped ball velocity after enhanced explosion: speed was {:.2}, now {:.2}", current_speed, max_ball_speed);
+                }
+            }
+            }
+        }
+        
+        // Send enhanced explosion event to all clients (as regular explosion with enhanced flag)
+        let explosion_event = json!({
+            "type": "explosion",
+            "x": x,
+            "y": y,
+            "radius": explosion_radius,
+            "player_id": owner_id,
+            "enhanced": true
+        });
+        
+        self.emit(EventKind::Explosion, explosion_event);
+    }
+
+    // Helper function to safely position the ball after shooting
+    fn position_ball_after_shot(&mut self, ship_x: f32, ship_y: f32, dir_x: f32, dir_y: f32) {
+        let map = self.map.clone();
+        
+        // Position the ball just outside the ship's radius to prevent immediate recapture
+        // But use a smaller offset to reduce the chance of tunneling through walls
+        let ship_radius = 20.0;
+        let ball_radius = 10.0;
+        let offset = ship_radius + ball_radius + 5.0; // Reduced from 15.0 to 5.0
+        
+        // Calculate new ball position
+        let new_ball_x = ship_x + dir_x * offset;
+        let new_ball_y = ship_y + dir_y * offset;
+        
+        // Check if the new position would put the ball inside a wall
+        let mut inside_wall = false;
+        for wall in map.walls() {
+            let ball_left = new_ball_x - BALL_WIDTH / 2.0;
+            let ball_right = new_ball_x + BALL_WIDTH / 2.0;
+            let ball_top = new_ball_y - BALL_HEIGHT / 2.0;
+            let ball_bottom = new_ball_y + BALL_HEIGHT / 2.0;
+            
+            if ball_right > wall.x && ball_left < wall.x + wall.width &&
+               ball_bottom > wall.y && ball_top < wall.y + wall.height {
+                inside_wall = true;
+                println!("WARNING: Ball would be positioned inside a wall after shooting. Adjusting position.");
+                break;
+            }
+            
+            // Also check if the path from ship to new ball position intersects with a wall
+            if super::collision::line_intersects_rect(
+                ship_x, ship_y,
+                new_ball_x, new_ball_y,
+                wall.x, wall.y, wall.width, wall.height
+            ) {
+                inside_wall = true;
+                println!("WARNING: Ball path would intersect a wall after shooting. Adjusting position.");
+                break;
+            }
+        }
+        
+        if inside_wall {
+            // If the new position would put the ball inside a wall, keep it at the ship's position
+            // and let the physics system handle the collision in the next update
+            self.ball.x = ship_x;
+            self.ball.y = ship_y;
+            println!("Ball positioned at ship center due to wall proximity");
+        } else {
+            // Otherwise, use the calculated position
+            self.ball.x = new_ball_x;
+            self.ball.y = new_ball_y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::bot::BotDifficulty;
+    use crate::sim::input_buffer::InputCommand;
+
+    const FIXED_DT: f32 = 1.0 / 60.0;
+    const GAME_WIDTH: f32 = 2000.0;
+    const GAME_HEIGHT: f32 = 1200.0;
+
+    fn soccer_game(seed: u64) -> Game {
+        let map = GameMap::from_json("soccer", include_str!("../maps/soccer.json")).unwrap();
+        Game::new(Arc::new(map), seed)
+    }
+
+    #[test]
+    fn only_the_nearest_bot_on_a_team_chases_the_ball() {
+        let mut game = soccer_game(1);
+        game.bot_fill = Some(BotFill::new(BotDifficulty::Normal));
+        let near = game.add_bot(Team::Red).unwrap();
+        let far = game.add_bot(Team::Red).unwrap();
+        game.ball.x = 1000.0;
+        game.ball.y = 600.0;
+        for (id, x) in [(near, 900.0), (far, 700.0)] {
+            let ship = &mut game.players.get_mut(&id).unwrap().ship;
+            ship.x = x;
+            ship.y = 600.0;
+        }
+
+        game.update_bots(FIXED_DT);
+        assert_eq!(game.bots.len(), 2);
+        // The nearer bot heads right for the ball; the other drops back toward the red goal
+        let near_input = &game.players[&near].input;
+        assert!(near_input.right && !near_input.left);
+        let far_input = &game.players[&far].input;
+        assert!(far_input.left && !far_input.right);
+    }
+
+    #[test]
+    fn player_ids_wrap_within_u16_and_skip_players_still_in_the_game() {
+        let mut game = soccer_game(1);
+        game.bot_fill = Some(BotFill::new(BotDifficulty::Normal));
+        game.next_id = 1;
+        let bot = game.add_bot(Team::Red).unwrap();
+        game.next_id = u16::MAX as u32;
+        assert_eq!(game.allocate_player_id(), u16::MAX as u32);
+        assert_eq!(game.allocate_player_id(), bot + 1);
+    }
+
+    // A soccer match between players 1 (red) and 2 (blue), still in warmup
+    fn match_game(settings: MatchSettings) -> Game {
+        let mut game = soccer_game(1);
+        game.match_settings = settings;
+        game.spawn_player(1, Team::Red, "Ann".to_string());
+        game.spawn_player(2, Team::Blue, "Bob".to_string());
+        game.next_id = 3;
+        game
+    }
+
+    fn short_halves() -> MatchSettings {
+        MatchSettings {
+            half_duration: 2.0,
+            halftime_duration: 1.0,
+            score_limit: None,
+            overtime: false,
+            ..MatchSettings::default()
+        }
+    }
+
+    fn tick(game: &mut Game) -> Vec<GameEvent> {
+        let now_ms = game.time_ms + 16;
+        game.step(FIXED_DT, GAME_WIDTH, GAME_HEIGHT, now_ms)
+    }
+
+    // Step until the match reaches `phase`, returning every event on the way
+    fn run_until(game: &mut Game, phase: MatchPhase) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        for _ in 0..(60.0 / FIXED_DT) as u32 {
+            events.extend(tick(game));
+            if game.match_state.phase == phase {
+                return events;
+            }
+        }
+        panic!("match never reached {:?}, stuck in {:?}", phase, game.match_state.phase);
+    }
+
+    // Put the ball in `goal` after `touches` had it in that order, the last one shooting it in
+    fn score(game: &mut Game, goal: &str, touches: &[u32]) -> Vec<GameEvent> {
+        let goal = game.map.goals().find(|object| object.obj_type == goal).unwrap().clone();
+        for id in touches {
+            game.touches.touch(*id);
+        }
+        game.ball.last_shooter = touches.last().copied();
+        game.ball.grabbed = false;
+        game.ball.owner = None;
+        game.ball.x = goal.x + goal.width / 2.0;
+        game.ball.y = goal.y + goal.height / 2.0;
+        game.ball.vx = 0.0;
+        game.ball.vy = 0.0;
+        game.goal_cooldown = 0.0;
+        tick(game)
+    }
+
+    fn phases(events: &[GameEvent]) -> Vec<(String, u64)> {
+        events.iter()
+            .filter(|event| event.kind == EventKind::MatchPhase)
+            .map(|event| (event.data["phase"].as_str().unwrap().to_string(), event.data["half"].as_u64().unwrap()))
+            .collect()
+    }
+
+    fn match_end(events: &[GameEvent]) -> &serde_json::Value {
+        &events.iter().find(|event| event.kind == EventKind::MatchEnd).expect("match ended").data
+    }
+
+    #[test]
+    fn match_plays_both_halves_with_a_halftime_break() {
+        let mut game = match_game(short_halves());
+        let events = run_until(&mut game, MatchPhase::Finished);
+
+        let expected = [("countdown", 1), ("live", 1), ("halftime", 1), ("countdown", 2), ("live", 2), ("finished", 2)];
+        assert_eq!(phases(&events), expected.map(|(phase, half)| (phase.to_string(), half)));
+        let end = match_end(&events);
+        assert_eq!(end["reason"], "full_time");
+        assert_eq!(end["draw"], true);
+        assert_eq!(end["winners"], json!(["Red", "Blue"]));
+    }
+
+    #[test]
+    fn level_scores_at_full_time_go_to_golden_goal_overtime() {
+        let mut game = match_game(MatchSettings { overtime: true, ..short_halves() });
+        run_until(&mut game, MatchPhase::Overtime);
+        assert_eq!(game.match_state.half, 2);
+
+        let events = score(&mut game, "goal_blue", &[1]);
+        assert_eq!(game.match_state.phase, MatchPhase::Finished);
+        let end = match_end(&events);
+        assert_eq!(end["reason"], "golden_goal");
+        assert_eq!(end["winners"], json!(["Red"]));
+        assert_eq!(end["overtime"], true);
+    }
+
+    #[test]
+    fn a_lead_at_full_time_skips_overtime() {
+        let mut game = match_game(MatchSettings { overtime: true, ..short_halves() });
+        run_until(&mut game, MatchPhase::Live);
+        score(&mut game, "goal_red", &[2]);
+
+        let events = run_until(&mut game, MatchPhase::Finished);
+        assert!(!phases(&events).iter().any(|(phase, _)| phase == "overtime"));
+        assert_eq!(match_end(&events)["winners"], json!(["Blue"]));
+    }
+
+    #[test]
+    fn reaching_the_score_limit_ends_the_match() {
+        let mut game = match_game(MatchSettings { half_duration: 60.0, score_limit: Some(2), ..short_halves() });
+        run_until(&mut game, MatchPhase::Live);
+
+        score(&mut game, "goal_blue", &[1]);
+        assert_eq!(game.match_state.phase, MatchPhase::Live);
+        let events = score(&mut game, "goal_blue", &[1]);
+        assert_eq!(game.match_state.phase, MatchPhase::Finished);
+        let end = match_end(&events);
+        assert_eq!(end["reason"], "score_limit");
+        assert_eq!((end["team1_score"].as_u64(), end["draw"].as_bool()), (Some(2), Some(false)));
+    }
+
+    #[test]
+    fn warmup_goals_do_not_count() {
+        let mut game = match_game(MatchSettings { min_players: 3, ..short_halves() });
+        tick(&mut game);
+        assert_eq!(game.match_state.phase, MatchPhase::Warmup);
+
+        let events = score(&mut game, "goal_blue", &[2, 1]);
+        assert!(events.iter().any(|event| event.kind == EventKind::Goal));
+        assert_eq!(game.team1_score, 0);
+        assert_eq!(game.players[&1].stats, PlayerStats::default());
+        assert_eq!(game.players[&2].stats, PlayerStats::default());
+    }
+
+    #[test]
+    fn goals_credit_the_scorer_and_a_teammate_assist() {
+        let mut game = match_game(MatchSettings { half_duration: 60.0, ..short_halves() });
+        game.spawn_player(3, Team::Red, "Cat".to_string());
+        run_until(&mut game, MatchPhase::Live);
+
+        // Cat passes to Ann, who scores
+        score(&mut game, "goal_blue", &[3, 1]);
+        // Bob loses the ball to Ann, who scores: an opponent's touch isn't an assist
+        score(&mut game, "goal_blue", &[2, 1]);
+        // Bob puts it in his own goal after Ann had it
+        let events = score(&mut game, "goal_blue", &[1, 2]);
+
+        let goal = &events.iter().find(|event| event.kind == EventKind::Goal).unwrap().data;
+        assert_eq!((goal["scorer_id"].as_u64(), goal["own_goal"].as_bool()), (Some(2), Some(true)));
+        assert_eq!(game.team1_score, 3);
+        assert_eq!((game.players[&1].stats.goals, game.players[&1].stats.assists), (2, 0));
+        assert_eq!(game.players[&3].stats.assists, 1);
+        assert_eq!((game.players[&2].stats.goals, game.players[&2].stats.own_goals), (0, 1));
+    }
+
+    #[test]
+    fn knocking_the_ball_off_an_opponent_is_a_steal() {
+        let mut game = match_game(MatchSettings { half_duration: 60.0, ..short_halves() });
+        game.spawn_player(3, Team::Red, "Cat".to_string());
+        run_until(&mut game, MatchPhase::Live);
+
+        let (x, y) = (game.players[&2].ship.x, game.players[&2].ship.y);
+        game.players.get_mut(&3).unwrap().ship.x = x + 60.0;
+        game.ball.grabbed = true;
+        game.ball.owner = Some(2);
+        game.create_explosion(x, y, 1);
+
+        assert!(!game.ball.grabbed);
+        let stats = &game.players[&1].stats;
+        // The blast caught Cat too, but teammates aren't counted as hits
+        assert_eq!((stats.rocket_hits, stats.steals), (1, 1));
+
+        // Knocking the ball off a teammate hits them but steals nothing
+        game.ball.grabbed = true;
+        game.ball.owner = Some(3);
+        game.create_explosion(x + 60.0, y, 1);
+        assert_eq!(game.players[&1].stats.steals, 1);
+    }
+
+    #[test]
+    fn scoreboard_lists_every_players_stats_by_team() {
+        let mut game = match_game(MatchSettings { half_duration: 60.0, ..short_halves() });
+        game.spawn_player(3, Team::Red, "Cat".to_string());
+        run_until(&mut game, MatchPhase::Live);
+        score(&mut game, "goal_blue", &[1, 3]);
+        score(&mut game, "goal_red", &[2]);
+
+        let scoreboard = game.scoreboard();
+        assert_eq!(scoreboard["type"], "scoreboard");
+        assert_eq!((scoreboard["team1_score"].as_u64(), scoreboard["team2_score"].as_u64()), (Some(1), Some(1)));
+        assert_eq!(scoreboard["match_state"]["phase"], "live");
+        let players = scoreboard["players"].as_array().unwrap();
+        // Red before blue, then by goals
+        let order: Vec<u64> = players.iter().map(|entry| entry["id"].as_u64().unwrap()).collect();
+        assert_eq!(order, [3, 1, 2]);
+        assert_eq!(players[0]["display_name"], "Cat");
+        assert_eq!(players[0]["team"], "Red");
+        assert_eq!(players[0]["bot"], false);
+        assert_eq!((players[0]["goals"].as_u64(), players[1]["assists"].as_u64()), (Some(1), Some(1)));
+        assert_eq!(players[2]["goals"], 1);
+    }
+
+    #[test]
+    fn host_started_match_goes_live_with_one_player() {
+        let mut game = soccer_game(1);
+        let team = game.assign_team();
+        game.spawn_player(1, team, "Host".to_string());
+        game.next_id = 2;
+        assert!(game.start_match_now());
+        assert_eq!(game.match_state.phase, MatchPhase::Countdown);
+
+        let mut now_ms = 1_000_000;
+        for _ in 0..(10.0 / FIXED_DT) as u32 {
+            now_ms += 16;
+            game.step(FIXED_DT, GAME_WIDTH, GAME_HEIGHT, now_ms);
+            assert_ne!(game.match_state.phase, MatchPhase::Warmup);
+        }
+        assert_eq!(game.match_state.phase, MatchPhase::Live);
+        assert!(!game.start_match_now());
+    }
+
+    // Two games with the same map, seed and inputs must end up in exactly the same state
+    fn run_scripted_game(seed: u64) -> serde_json::Value {
+        let mut game = soccer_game(seed);
+        for (id, name) in [(1, "Ann"), (2, "Bob"), (3, "Cat"), (4, "Dan")] {
+            let team = game.assign_team();
+            let player = Player::new(id, team, name.to_string(), &game.map, &mut game.rng);
+            game.players.insert(id, player);
+        }
+        game.next_id = 5;
+
+        let mut now_ms = 1_000_000;
+        for tick in 1..=(30.0 / FIXED_DT) as u32 {
+            for (id, player) in game.players.iter_mut() {
+                let phase = tick / 40 + id;
+                player.input_buffer.push_all(vec![InputCommand {
+                    seq: tick,
+                    left: phase % 4 == 0,
+                    right: phase % 4 == 2,
+                    up: phase % 3 == 0,
+                    down: phase % 5 == 1,
+                    shoot: tick % 90 == 0,
+                    boost: phase % 7 == 3,
+                    target_x: Some(1000.0),
+                    target_y: Some(600.0),
+                }]);
+            }
+            now_ms += 16;
+            game.step(FIXED_DT, GAME_WIDTH, GAME_HEIGHT, now_ms);
+        }
+        serde_json::to_value(game.create_snapshot()).unwrap()
+    }
+
+    #[test]
+    fn same_seed_and_inputs_give_the_same_state() {
+        assert_eq!(run_scripted_game(42), run_scripted_game(42));
+    }
+}
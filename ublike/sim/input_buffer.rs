@@ -0,0 +1,157 @@
+// This module buffers player input so the simulation consumes exactly one command per tick.
+//
+// Every input message carries the current command plus (optionally) the last few commands the
+// client sent before it in `previous`, so a lost packet doesn't lose input. Commands are queued
+// by seq, duplicates are dropped, and each tick pops the next one. A small jitter buffer holds
+// commands back for a couple of ticks after the queue runs dry so uneven packet arrival doesn't
+// turn into stuttering movement. The snapshot `seq` of a player is the seq of the last command
+// actually applied, so client-side reconciliation can replay exactly the unapplied inputs.
+
+use std::collections::VecDeque;
+use serde::Deserialize;
+
+// Commands to collect (or ticks to wait) before resuming after the queue ran dry
+const JITTER_BUFFER_DEPTH: usize = 2;
+
+// When a client gets this far ahead the oldest commands are merged away to catch up
+const MAX_QUEUE_DEPTH: usize = 16;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct InputCommand {
+    pub seq: u32,
+    #[serde(default)]
+    pub left: bool,
+    #[serde(default)]
+    pub right: bool,
+    #[serde(default)]
+    pub up: bool,
+    #[serde(default)]
+    pub down: bool,
+    #[serde(default)]
+    pub shoot: bool,
+    #[serde(default)]
+    pub boost: bool,
+    #[serde(default)]
+    pub target_x: Option<f32>,
+    #[serde(default)]
+    pub target_y: Option<f32>,
+}
+
+#[derive(Debug)]
+pub struct InputBuffer {
+    queue: VecDeque<InputCommand>,
+    last_queued_seq: u32,
+    buffering: bool,
+    buffering_ticks: usize,
+}
+
+impl InputBuffer {
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            last_queued_seq: 0,
+            buffering: true,
+            buffering_ticks: 0,
+        }
+    }
+
+    // Queue commands in any order. Returns how many were new (redundant copies are ignored).
+    pub fn push_all(&mut self, mut commands: Vec<InputCommand>) -> usize {
+        commands.sort_by_key(|command| command.seq);
+
+        let mut accepted = 0;
+        for command in commands {
+            if command.seq <= self.last_queued_seq {
+                continue;
+            }
+            self.last_queued_seq = command.seq;
+            self.queue.push_back(command);
+            accepted += 1;
+        }
+        accepted
+    }
+
+    // Next command for this tick, or None to keep the current input
+    pub fn next_command(&mut self) -> Option<InputCommand> {
+        if self.buffering {
+            self.buffering_ticks += 1;
+            if self.queue.is_empty() || (self.queue.len() < JITTER_BUFFER_DEPTH && self.buffering_ticks <= JITTER_BUFFER_DEPTH) {
+                return None;
+            }
+            self.buffering = false;
+        }
+
+        // Catch up after a burst, carrying over taps so a quick shot isn't lost
+        while self.queue.len() > MAX_QUEUE_DEPTH {
+            let dropped = self.queue.pop_front()?;
+            if let Some(next) = self.queue.front_mut() {
+                next.shoot |= dropped.shoot;
+                next.boost |= dropped.boost;
+            }
+        }
+
+        let command = self.queue.pop_front();
+        if self.queue.is_empty() {
+            self.buffering = true;
+            self.buffering_ticks = 0;
+        }
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(seq: u32) -> InputCommand {
+        InputCommand { seq, ..InputCommand::default() }
+    }
+
+    fn commands(seqs: &[u32]) -> Vec<InputCommand> {
+        seqs.iter().map(|seq| command(*seq)).collect()
+    }
+
+    fn seqs(buffer: &mut InputBuffer, ticks: usize) -> Vec<Option<u32>> {
+        (0..ticks).map(|_| buffer.next_command().map(|command| command.seq)).collect()
+    }
+
+    #[test]
+    fn redundant_and_old_commands_are_dropped() {
+        let mut buffer = InputBuffer::new();
+        assert_eq!(buffer.push_all(commands(&[1, 2, 3])), 3);
+        assert_eq!(buffer.push_all(commands(&[2, 3, 4])), 1);
+        assert_eq!(buffer.push_all(commands(&[4])), 0);
+        assert_eq!(buffer.push_all(commands(&[6, 5])), 2);
+        assert_eq!(seqs(&mut buffer, 7), [Some(1), Some(2), Some(3), Some(4), Some(5), Some(6), None]);
+    }
+
+    #[test]
+    fn a_lone_command_is_held_back_for_the_jitter_buffer() {
+        let mut buffer = InputBuffer::new();
+        buffer.push_all(commands(&[1]));
+        let mut expected = vec![None; JITTER_BUFFER_DEPTH];
+        expected.push(Some(1));
+        assert_eq!(seqs(&mut buffer, JITTER_BUFFER_DEPTH + 1), expected);
+
+        // Once enough commands are queued they flow straight away, until the queue runs dry again
+        buffer.push_all(commands(&[2, 3]));
+        assert_eq!(seqs(&mut buffer, 3), [Some(2), Some(3), None]);
+        buffer.push_all(commands(&[4]));
+        assert_eq!(buffer.next_command().map(|command| command.seq), None);
+    }
+
+    #[test]
+    fn overflow_merges_the_oldest_commands_keeping_their_taps() {
+        let mut buffer = InputBuffer::new();
+        let mut burst = commands(&(1..=MAX_QUEUE_DEPTH as u32 + 4).collect::<Vec<_>>());
+        burst[1].shoot = true;
+        burst[3].boost = true;
+        buffer.push_all(burst);
+
+        let next = buffer.next_command().unwrap();
+        assert_eq!(next.seq, 5);
+        assert!(next.shoot && next.boost);
+        assert_eq!(seqs(&mut buffer, MAX_QUEUE_DEPTH - 1).last(), Some(&Some(MAX_QUEUE_DEPTH as u32 + 4)));
+        assert!(buffer.next_command().is_none());
+    }
+}
@@ -0,0 +1,172 @@
+// This module keeps a short history of player and ball positions for lag compensation.
+//
+// Clients see the ball and other ships slightly in the past (latency plus interpolation
+// delay). Each client reports the server time of the state it is looking at (`view_time` on
+// input messages, `timestamp` on reliable shots), and the server rewinds to that time when
+// validating grabs, shots and direct rocket hits. How far back it is willing to rewind is
+// capped by `max_rewind_ms` (default 200 ms, the server reads MAX_REWIND_MS).
+
+use std::collections::{HashMap, VecDeque};
+
+pub const DEFAULT_MAX_REWIND_MS: u64 = 200;
+
+// Player and ball positions at the end of one tick
+pub struct HistoryFrame {
+    pub time: u64, // server timestamp in ms
+    pub players: HashMap<u32, (f32, f32)>,
+    pub ball: (f32, f32),
+    pub ball_owner: Option<u32>,
+}
+
+pub struct PositionHistory {
+    frames: VecDeque<HistoryFrame>,
+    max_rewind_ms: u64,
+}
+
+impl PositionHistory {
+    pub fn new(max_rewind_ms: u64) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            max_rewind_ms,
+        }
+    }
+
+    pub fn record(&mut self, frame: HistoryFrame) {
+        let oldest_needed = frame.time.saturating_sub(self.max_rewind_ms);
+        self.frames.push_back(frame);
+
+        // Keep one frame older than the window so the oldest rewind can still interpolate
+        while self.frames.len() > 2 && self.frames[1].time <= oldest_needed {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn max_rewind_ms(&self) -> u64 {
+        self.max_rewind_ms
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // Time to rewind to for a client that is `view_delay_ms` behind `now`, or None when there
+    // is nothing to compensate
+    pub fn rewind_time(&self, now: u64, view_delay_ms: u64) -> Option<u64> {
+        let delay = view_delay_ms.min(self.max_rewind_ms);
+        if delay == 0 {
+            None
+        } else {
+            Some(now.saturating_sub(delay))
+        }
+    }
+
+    // The two frames around `time` and the interpolation factor between them
+    fn bracket(&self, time: u64) -> Option<(&HistoryFrame, &HistoryFrame, f32)> {
+        let first = self.frames.front()?;
+        if time <= first.time {
+            return Some((first, first, 0.0));
+        }
+
+        for (before, after) in self.frames.iter().zip(self.frames.iter().skip(1)) {
+            if time <= after.time {
+                let span = (after.time - before.time).max(1) as f32;
+                let t = (time - before.time) as f32 / span;
+                return Some((before, after, t));
+            }
+        }
+
+        let last = self.frames.back()?;
+        Some((last, last, 0.0))
+    }
+
+    // Interpolated position of a player at `time`
+    pub fn player_at(&self, player_id: u32, time: u64) -> Option<(f32, f32)> {
+        let (before, after, t) = self.bracket(time)?;
+        let from = before.players.get(&player_id)?;
+        let to = after.players.get(&player_id).unwrap_or(from);
+        Some(lerp(*from, *to, t))
+    }
+
+    // Interpolated position of the ball at `time`, if it was loose then
+    pub fn loose_ball_at(&self, time: u64) -> Option<(f32, f32)> {
+        let (before, after, t) = self.bracket(time)?;
+        if before.ball_owner.is_some() || after.ball_owner.is_some() {
+            return None;
+        }
+        Some(lerp(before.ball, after.ball, t))
+    }
+
+    // Who held the ball at `time`
+    pub fn ball_owner_at(&self, time: u64) -> Option<u32> {
+        let (before, after, t) = self.bracket(time)?;
+        if t < 0.5 { before.ball_owner } else { after.ball_owner }
+    }
+}
+
+fn lerp(from: (f32, f32), to: (f32, f32), t: f32) -> (f32, f32) {
+    (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(time: u64, player_x: f32, ball_owner: Option<u32>) -> HistoryFrame {
+        HistoryFrame {
+            time,
+            players: HashMap::from([(1, (player_x, 100.0))]),
+            ball: (player_x * 2.0, 50.0),
+            ball_owner,
+        }
+    }
+
+    #[test]
+    fn positions_are_interpolated_between_frames() {
+        let mut history = PositionHistory::new(DEFAULT_MAX_REWIND_MS);
+        history.record(frame(1000, 0.0, None));
+        history.record(frame(1016, 160.0, None));
+
+        assert_eq!(history.player_at(1, 1008), Some((80.0, 100.0)));
+        assert_eq!(history.loose_ball_at(1004), Some((80.0, 50.0)));
+        // Before the first frame and after the last one the nearest frame is used
+        assert_eq!(history.player_at(1, 900), Some((0.0, 100.0)));
+        assert_eq!(history.player_at(1, 2000), Some((160.0, 100.0)));
+        assert_eq!(history.player_at(2, 1008), None);
+    }
+
+    #[test]
+    fn held_ball_has_no_loose_position_and_owner_switches_halfway() {
+        let mut history = PositionHistory::new(DEFAULT_MAX_REWIND_MS);
+        history.record(frame(1000, 0.0, None));
+        history.record(frame(1016, 10.0, Some(1)));
+
+        assert_eq!(history.loose_ball_at(1004), None);
+        assert_eq!(history.ball_owner_at(1004), None);
+        assert_eq!(history.ball_owner_at(1012), Some(1));
+    }
+
+    #[test]
+    fn rewind_is_clamped_to_max_rewind() {
+        let history = PositionHistory::new(100);
+        assert_eq!(history.rewind_time(5000, 0), None);
+        assert_eq!(history.rewind_time(5000, 40), Some(4960));
+        assert_eq!(history.rewind_time(5000, 1000), Some(4900));
+    }
+
+    #[test]
+    fn frames_older_than_the_window_are_dropped() {
+        let mut history = PositionHistory::new(100);
+        for i in 0..20 {
+            history.record(frame(1000 + i * 16, i as f32, None));
+        }
+        let now = 1000 + 19 * 16;
+
+        // One frame older than the window is kept so the oldest rewind still interpolates
+        assert!(history.frames.len() < 20);
+        assert!(history.frames[0].time < now - 100);
+        assert!(history.frames[1].time > now - 100);
+        let oldest = history.rewind_time(now, 1000).unwrap();
+        let (x, _) = history.player_at(1, oldest).unwrap();
+        assert!(x > history.frames[0].players[&1].0 && x < history.frames[1].players[&1].0);
+    }
+}
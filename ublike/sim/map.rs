@@ -0,0 +1,162 @@
+// Arena maps: the walls and goals a game is played on, parsed from the array-of-objects
+// JSON format that editor.py exports. Loading maps from disk lives in the server's map module.
+
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MapObject {
+    #[serde(rename = "type")]
+    pub obj_type: String, // e.g., "wall" or "goal_red"
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+// Object types a map file is allowed to contain
+const KNOWN_OBJECT_TYPES: [&str; 5] = ["wall", "goal_red", "goal_blue", "goal_yellow", "goal_green"];
+
+#[derive(Debug)]
+pub struct GameMap {
+    pub id: String,
+    pub objects: Vec<MapObject>,
+}
+
+impl GameMap {
+    // Parse and validate a map from its JSON source
+    pub fn from_json(id: &str, json_str: &str) -> Result<Self, String> {
+        let objects: Vec<MapObject> = serde_json::from_str(json_str)
+            .map_err(|e| format!("Failed to parse map '{}': {}", id, e))?;
+
+        let map = Self {
+            id: id.to_string(),
+            objects,
+        };
+        map.validate()?;
+        Ok(map)
+    }
+
+    // Load a single map file, using the file stem as the map id
+    pub fn load_file(path: &Path) -> Result<Self, String> {
+        let id = path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("Invalid map file name: {}", path.display()))?;
+        let json_str = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read map file {}: {}", path.display(), e))?;
+        Self::from_json(id, &json_str)
+    }
+
+    // Reject maps the simulation can't play on
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, obj) in self.objects.iter().enumerate() {
+            if !KNOWN_OBJECT_TYPES.contains(&obj.obj_type.as_str()) {
+                return Err(format!("Map '{}': object {} has unknown type '{}'", self.id, index, obj.obj_type));
+            }
+            if !(obj.x.is_finite() && obj.y.is_finite() && obj.width.is_finite() && obj.height.is_finite()) {
+                return Err(format!("Map '{}': object {} has non-finite coordinates", self.id, index));
+            }
+            if obj.width <= 0.0 || obj.height <= 0.0 {
+                return Err(format!("Map '{}': object {} has non-positive size {}x{}", self.id, index, obj.width, obj.height));
+            }
+        }
+
+        // Every map needs at least two opposing goals for scoring and spawn placement
+        let has_red = self.objects.iter().any(|obj| obj.obj_type == "goal_red");
+        let has_blue = self.objects.iter().any(|obj| obj.obj_type == "goal_blue");
+        if !has_red || !has_blue {
+            return Err(format!("Map '{}' must contain at least one goal_red and one goal_blue", self.id));
+        }
+
+        Ok(())
+    }
+
+    pub fn walls(&self) -> impl Iterator<Item = &MapObject> {
+        self.objects.iter().filter(|obj| obj.obj_type == "wall")
+    }
+
+    pub fn goals(&self) -> impl Iterator<Item = &MapObject> {
+        self.objects.iter().filter(|obj| obj.obj_type.starts_with("goal_"))
+    }
+
+    // Soccer map = no Yellow or Green goals (only Red vs Blue)
+    pub fn is_soccer(&self) -> bool {
+        !self.objects.iter().any(|obj| obj.obj_type == "goal_yellow" || obj.obj_type == "goal_green")
+    }
+
+    // Bounding box of all goals as (min_x, min_y, max_x, max_y)
+    pub fn goal_bounds(&self) -> (f32, f32, f32, f32) {
+        let mut min_x = f32::INFINITY;
+        let mut min_y = f32::INFINITY;
+        let mut max_x = f32::NEG_INFINITY;
+        let mut max_y = f32::NEG_INFINITY;
+
+        for goal in self.goals() {
+            min_x = min_x.min(goal.x);
+            min_y = min_y.min(goal.y);
+            max_x = max_x.max(goal.x + goal.width);
+            max_y = max_y.max(goal.y + goal.height);
+        }
+
+        (min_x, min_y, max_x, max_y)
+    }
+
+    // Middle point between all goals, used for kickoff and spawn positions
+    pub fn goal_center(&self) -> (f32, f32) {
+        let (min_x, min_y, max_x, max_y) = self.goal_bounds();
+        ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(obj_type: &str, x: f32, y: f32) -> MapObject {
+        MapObject { obj_type: obj_type.to_string(), x, y, width: 40.0, height: 120.0 }
+    }
+
+    fn map_with(objects: Vec<MapObject>) -> GameMap {
+        GameMap { id: "test".to_string(), objects }
+    }
+
+    #[test]
+    fn bundled_maps_have_their_goals() {
+        let soccer = GameMap::from_json("soccer", include_str!("../maps/soccer.json")).unwrap();
+        assert!(soccer.is_soccer());
+        let corner_defense = GameMap::from_json("corner_defense", include_str!("../maps/corner_defense.json")).unwrap();
+        assert!(!corner_defense.is_soccer());
+        assert_eq!(corner_defense.goals().count(), 4);
+    }
+
+    #[test]
+    fn unknown_object_type_is_rejected() {
+        let json = r#"[
+            { "type": "goal_red", "x": 0, "y": 0, "width": 40, "height": 120 },
+            { "type": "goal_blue", "x": 100, "y": 0, "width": 40, "height": 120 },
+            { "type": "goal", "x": 200, "y": 0, "width": 40, "height": 120 }
+        ]"#;
+        let error = GameMap::from_json("test", json).unwrap_err();
+        assert!(error.contains("unknown type 'goal'"), "{}", error);
+    }
+
+    #[test]
+    fn non_finite_or_empty_objects_are_rejected() {
+        for bad in [f32::NAN, f32::INFINITY] {
+            let map = map_with(vec![object("goal_red", bad, 0.0), object("goal_blue", 100.0, 0.0)]);
+            assert!(map.validate().unwrap_err().contains("non-finite"));
+        }
+        let mut flat = object("wall", 0.0, 0.0);
+        flat.height = 0.0;
+        let map = map_with(vec![flat, object("goal_red", 0.0, 0.0), object("goal_blue", 100.0, 0.0)]);
+        assert!(map.validate().unwrap_err().contains("non-positive size"));
+    }
+
+    #[test]
+    fn maps_need_a_red_and_a_blue_goal() {
+        assert!(map_with(vec![object("goal_red", 0.0, 0.0)]).validate().is_err());
+        assert!(map_with(vec![object("goal_yellow", 0.0, 0.0), object("goal_green", 0.0, 0.0)]).validate().is_err());
+        assert!(map_with(vec![object("goal_red", 0.0, 0.0), object("goal_blue", 100.0, 0.0)]).validate().is_ok());
+        assert!(GameMap::from_json("test", "{}").is_err());
+    }
+}
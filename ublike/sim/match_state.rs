@@ -0,0 +1,167 @@
+// Match lifecycle: warmup until enough players are in, a kickoff countdown, timed halves with a
+// halftime break, golden-goal overtime when the leaders are level at full time, and a results
+// screen before the next match starts on the next map in the rotation.
+//
+//   Warmup -> Countdown -> Live -> Halftime -> Countdown -> Live -> (Overtime) -> Finished -> Countdown...
+//
+// The phase and its clock are simulation state: `Game::step` advances them and every snapshot
+// carries them. Goals only count while the match is live or in overtime; during warmup the ball
+// still goes in so players can practice, but the score and stats stand still.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchPhase {
+    Warmup,    // Waiting for enough players; goals don't count
+    Countdown, // Kickoff countdown before a half
+    Live,
+    Halftime,
+    Overtime,  // Golden goal: the next goal wins
+    Finished,  // Results are up until the next match starts
+}
+
+impl MatchPhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MatchPhase::Warmup => "warmup",
+            MatchPhase::Countdown => "countdown",
+            MatchPhase::Live => "live",
+            MatchPhase::Halftime => "halftime",
+            MatchPhase::Overtime => "overtime",
+            MatchPhase::Finished => "finished",
+        }
+    }
+
+    // Whether the ball can go in; warmup goals are only practice
+    pub fn goals_open(&self) -> bool {
+        matches!(self, MatchPhase::Warmup | MatchPhase::Live | MatchPhase::Overtime)
+    }
+
+    // Whether a goal scored in this phase changes the score
+    pub fn goals_count(&self) -> bool {
+        matches!(self, MatchPhase::Live | MatchPhase::Overtime)
+    }
+}
+
+// How a match is played; rooms can override any of these when they're created
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchSettings {
+    pub half_duration: f32,       // Seconds per half
+    pub halves: u32,
+    pub halftime_duration: f32,   // Seconds between halves
+    pub score_limit: Option<u32>, // A team reaching this many goals wins straight away
+    pub overtime: bool,           // Golden goal when the leaders are level at full time (otherwise a draw)
+    pub min_players: usize,       // Players (bots included) needed to start a match
+    pub results_duration: f32,    // Seconds the results stay up before the next match
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        Self {
+            half_duration: 150.0,
+            halves: 2,
+            halftime_duration: 15.0,
+            score_limit: Some(10),
+            overtime: true,
+            min_players: 2,
+            results_duration: 10.0,
+        }
+    }
+}
+
+impl MatchSettings {
+    // Clamp values that would stall the lifecycle or end matches instantly
+    pub fn sanitized(mut self) -> Self {
+        self.half_duration = self.half_duration.max(1.0);
+        self.halves = self.halves.max(1);
+        self.halftime_duration = self.halftime_duration.max(0.0);
+        self.score_limit = self.score_limit.filter(|limit| *limit > 0);
+        self.min_players = self.min_players.max(1);
+        self.results_duration = self.results_duration.max(0.0);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MatchState {
+    pub phase: MatchPhase,
+    pub half: u32,  // Current half, from 1; 0 during warmup
+    pub clock: f32, // Seconds left in the phase (seconds played in overtime)
+    #[serde(default)]
+    pub forced: bool, // The host started this match without waiting for min_players
+}
+
+impl Default for MatchState {
+    fn default() -> Self {
+        Self { phase: MatchPhase::Warmup, half: 0, clock: 0.0, forced: false }
+    }
+}
+
+impl MatchState {
+    pub fn to_json(self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "match_phase",
+            "phase": self.phase,
+            "half": self.half,
+            "clock": self.clock
+        })
+    }
+}
+
+// Why a match ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchEndReason {
+    FullTime,
+    ScoreLimit,
+    GoldenGoal,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized_settings_can_always_finish_a_match() {
+        let settings = MatchSettings {
+            half_duration: -5.0,
+            halves: 0,
+            halftime_duration: -1.0,
+            score_limit: Some(0),
+            overtime: true,
+            min_players: 0,
+            results_duration: -3.0,
+        }
+        .sanitized();
+        assert_eq!(settings, MatchSettings {
+            half_duration: 1.0,
+            halves: 1,
+            halftime_duration: 0.0,
+            score_limit: None,
+            overtime: true,
+            min_players: 1,
+            results_duration: 0.0,
+        });
+
+        assert_eq!(MatchSettings::default().sanitized(), MatchSettings::default());
+    }
+
+    #[test]
+    fn only_live_play_and_overtime_count_goals() {
+        let counting: Vec<MatchPhase> = [
+            MatchPhase::Warmup,
+            MatchPhase::Countdown,
+            MatchPhase::Live,
+            MatchPhase::Halftime,
+            MatchPhase::Overtime,
+            MatchPhase::Finished,
+        ]
+        .into_iter()
+        .filter(|phase| phase.goals_count())
+        .collect();
+        assert_eq!(counting, [MatchPhase::Live, MatchPhase::Overtime]);
+        assert!(MatchPhase::Warmup.goals_open() && !MatchPhase::Halftime.goals_open());
+    }
+}
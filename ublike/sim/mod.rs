@@ -0,0 +1,22 @@
+// Headless game simulation: players, ball, projectiles, goals and the maps they play on.
+//
+// Nothing in here touches tokio, warp, sockets or the wall clock. A game is created from a map
+// and an RNG seed and advanced with `Game::step`, which takes the tick's time and returns the
+// events that happened, so the same seed, map and inputs always give the same result. The
+// server (game.rs, lobby.rs) owns the loop and turns events into network messages; bots,
+// replays and tests can drive a `Game` directly. Only serde/serde_json are required, so this
+// module can be lifted into its own crate as is.
+
+pub mod ball;
+pub mod bot;
+pub mod collision;
+pub mod events;
+pub mod game;
+pub mod input_buffer;
+pub mod lag_compensation;
+pub mod map;
+pub mod match_state;
+pub mod player;
+pub mod replay;
+pub mod rng;
+pub mod stats;
@@ -1,12 +1,14 @@
 // This module will contain player-related structures and logic.
 
-use serde::Serialize;
-use crate::game::InputState;
-use crate::game::MAP_OBJECTS;
-use rand;
+use serde::{Deserialize, Serialize};
+use super::game::InputState;
+use super::input_buffer::InputBuffer;
+use super::map::GameMap;
+use super::rng::SimRng;
+use super::stats::PlayerStats;
 
 // Define team enum
-#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum Team {
     #[serde(rename = "Red")]
     Red,
@@ -50,45 +52,33 @@ pub struct Player {
     pub is_host: bool,
     pub rocket_cooldown: f32,
     pub pending_shot_id: Option<u32>,
+    pub view_delay_ms: u64, // How far behind the server this client's view is, for lag compensation
+    pub input_buffer: InputBuffer, // Commands waiting to be applied, one per tick
+    pub stats: PlayerStats, // This match's goals, assists, shots...
 }
 
 impl Player {
-    pub fn new(id: u32, team: Team, display_name: String) -> Self {
-        // Calculate the middle point between goals
-        let mut min_y = f32::INFINITY;
-        let mut max_y = f32::NEG_INFINITY;
-        let mut min_x = f32::INFINITY;
-        let mut max_x = f32::NEG_INFINITY;
-        
-        // Find the min and max coordinates of all goals
-        for goal in crate::game::MAP_OBJECTS.iter().filter(|obj| obj.obj_type.starts_with("goal")) {
-            min_y = min_y.min(goal.y);
-            max_y = max_y.max(goal.y + goal.height);
-            min_x = min_x.min(goal.x);
-            max_x = max_x.max(goal.x + goal.width);
-        }
-        
+    pub fn new(id: u32, team: Team, display_name: String, map: &GameMap, rng: &mut SimRng) -> Self {
         // Calculate the middle position between goals
-        let middle_x = (min_x + max_x) / 2.0;
-        let middle_y = (min_y + max_y) / 2.0;
+        let (middle_x, middle_y) = map.goal_center();
 
         // Set spawn position based on team
         let spawn_pos = match team {
             Team::Red => {
                 // Position red team players near the ball, slightly to the left
-                (middle_x - 150.0, middle_y + (rand::random::<f32>() - 0.5) * 100.0)
+                (middle_x - 150.0, middle_y + (rng.next_f32() - 0.5) * 100.0)
             },
             Team::Blue => {
                 // Position blue team players near the ball, slightly to the right
-                (middle_x + 150.0, middle_y + (rand::random::<f32>() - 0.5) * 100.0)
+                (middle_x + 150.0, middle_y + (rng.next_f32() - 0.5) * 100.0)
             },
             Team::Yellow => {
                 // Position yellow team players near the ball, slightly to the right
-                (middle_x + 150.0, middle_y + (rand::random::<f32>() - 0.5) * 100.0)
+                (middle_x + 150.0, middle_y + (rng.next_f32() - 0.5) * 100.0)
             },
             Team::Green => {
                 // Position green team players near the ball, slightly to the left
-                (middle_x - 150.0, middle_y + (rand::random::<f32>() - 0.5) * 100.0)
+                (middle_x - 150.0, middle_y + (rng.next_f32() - 0.5) * 100.0)
             },
         };
 
@@ -106,6 +96,9 @@ impl Player {
             is_host: false,
             rocket_cooldown: 0.0,
             pending_shot_id: None,
+            view_delay_ms: 0,
+            input_buffer: InputBuffer::new(),
+            stats: PlayerStats::default(),
         }
     }
     
@@ -115,6 +108,32 @@ impl Player {
         }
     }
 
+    // Record the server time of the state the client is currently looking at
+    pub fn set_view_time(&mut self, view_time: u64, now_ms: u64) {
+        self.view_delay_ms = now_ms.saturating_sub(view_time);
+    }
+
+    // Apply the next buffered input command for this tick. A pending reliable shot keeps its
+    // shoot flag and target until the shot has been processed.
+    pub fn apply_next_input(&mut self) {
+        let command = match self.input_buffer.next_command() {
+            Some(command) => command,
+            None => return,
+        };
+
+        self.input.left = command.left;
+        self.input.right = command.right;
+        self.input.up = command.up;
+        self.input.down = command.down;
+        self.input.boost = command.boost;
+        if self.pending_shot_id.is_none() {
+            self.input.shoot = command.shoot;
+            self.input.target_x = command.target_x;
+            self.input.target_y = command.target_y;
+        }
+        self.last_seq = command.seq;
+    }
+
     pub fn use_boost(&mut self, amount: f32) -> bool {
         if self.fuel >= amount {
             self.fuel -= amount;
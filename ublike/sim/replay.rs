@@ -0,0 +1,438 @@
+// Match recordings.
+//
+// A replay stores the game state at the moment recording started (map, RNG state as the seed,
+// players, ball, scores...) and then one entry per tick: how much time passed, the changes made
+// to the game between steps (joins, leaves, team switches, renames, lag-compensated ball
+// returns) and the input of every player whose input changed. Feeding those ticks back into a
+// `Game` restored from the initial state re-simulates the match exactly.
+//
+// On disk a replay is JSON Lines: a header line followed by one short line per tick.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use super::ball::Ball;
+use super::events::GameEvent;
+use super::game::{Game, Projectile};
+use super::lag_compensation::PositionHistory;
+use super::map::{GameMap, MapObject};
+use super::match_state::{MatchSettings, MatchState};
+use super::player::{Player, Ship, Team};
+use super::rng::SimRng;
+use super::stats::{PlayerStats, TouchHistory};
+
+pub const REPLAY_VERSION: u32 = 1;
+
+// Bits of RecordedInput::keys
+const KEY_LEFT: u8 = 1 << 0;
+const KEY_RIGHT: u8 = 1 << 1;
+const KEY_UP: u8 = 1 << 2;
+const KEY_DOWN: u8 = 1 << 3;
+const KEY_SHOOT: u8 = 1 << 4;
+const KEY_BOOST: u8 = 1 << 5;
+
+// A player's input as the simulation used it for one step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedInput {
+    pub id: u32,
+    pub keys: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_x: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_y: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shot_id: Option<u32>, // Reliable shot waiting to be processed
+    #[serde(default)]
+    pub view_delay_ms: u64,
+}
+
+impl RecordedInput {
+    pub fn capture(player: &Player) -> Self {
+        let input = &player.input;
+        let flags = [
+            (input.left, KEY_LEFT),
+            (input.right, KEY_RIGHT),
+            (input.up, KEY_UP),
+            (input.down, KEY_DOWN),
+            (input.shoot, KEY_SHOOT),
+            (input.boost, KEY_BOOST),
+        ];
+        Self {
+            id: player.id,
+            keys: flags.iter().filter(|(set, _)| *set).fold(0, |keys, (_, bit)| keys | bit),
+            target_x: input.target_x,
+            target_y: input.target_y,
+            shot_id: player.pending_shot_id,
+            view_delay_ms: player.view_delay_ms,
+        }
+    }
+
+    pub fn apply(&self, player: &mut Player) {
+        player.input.left = self.keys & KEY_LEFT != 0;
+        player.input.right = self.keys & KEY_RIGHT != 0;
+        player.input.up = self.keys & KEY_UP != 0;
+        player.input.down = self.keys & KEY_DOWN != 0;
+        player.input.shoot = self.keys & KEY_SHOOT != 0;
+        player.input.boost = self.keys & KEY_BOOST != 0;
+        player.input.target_x = self.target_x;
+        player.input.target_y = self.target_y;
+        player.pending_shot_id = self.shot_id;
+        player.view_delay_ms = self.view_delay_ms;
+    }
+}
+
+// Changes made to a game between two steps, in the order they happened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ReplayAction {
+    Join { id: u32, team: Team, name: String },
+    Leave { id: u32 },
+    SwitchTeam { id: u32, team: Team },
+    Rename { id: u32, name: String },
+    ReturnBall { id: u32 }, // Ball given back to a shooter by lag compensation
+    DropBall { id: u32 },   // Ball let go by a player whose connection dropped
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayTick {
+    pub dt_ms: u64, // Time since the previous step
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<ReplayAction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<RecordedInput>, // Only players whose input changed
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InitialPlayer {
+    pub id: u32,
+    pub team: Team,
+    pub name: String,
+    pub is_host: bool,
+    pub x: f32,
+    pub y: f32,
+    pub velocity: (f32, f32),
+    pub shoot_cooldown: f32,
+    pub grab_cooldown: f32,
+    pub rocket_cooldown: f32,
+    pub fuel: f32,
+    pub input: RecordedInput,
+    #[serde(default)]
+    pub stats: PlayerStats,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InitialState {
+    pub tick: u64,
+    pub time_ms: u64,
+    pub next_projectile_id: u32,
+    pub scores: [u32; 4], // Red, Blue, Yellow, Green
+    pub goal_cooldown: f32,
+    pub countdown_remaining: f32,
+    pub ball: Ball,
+    pub projectiles: Vec<Projectile>,
+    pub players: Vec<InitialPlayer>,
+    #[serde(default)]
+    pub match_settings: MatchSettings,
+    #[serde(default)]
+    pub match_state: MatchState,
+    #[serde(default)]
+    pub touches: TouchHistory,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: u32,
+    pub map_id: String,
+    pub map: Vec<MapObject>,
+    pub seed: u64,
+    pub fixed_dt: f32,
+    pub game_width: f32,
+    pub game_height: f32,
+    pub max_rewind_ms: u64,
+    pub initial: InitialState,
+}
+
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub ticks: Vec<ReplayTick>,
+}
+
+impl Replay {
+    pub fn to_jsonl(&self) -> String {
+        let mut out = serde_json::to_string(&self.header).unwrap_or_default();
+        out.push('\n');
+        for tick in &self.ticks {
+            out.push_str(&serde_json::to_string(tick).unwrap_or_default());
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn from_jsonl(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header: ReplayHeader = serde_json::from_str(lines.next().ok_or("Empty replay")?)
+            .map_err(|e| format!("Invalid replay header: {}", e))?;
+        if header.version != REPLAY_VERSION {
+            return Err(format!("Unsupported replay version {} (expected {})", header.version, REPLAY_VERSION));
+        }
+
+        let ticks = lines.enumerate()
+            .map(|(index, line)| serde_json::from_str(line)
+                .map_err(|e| format!("Invalid replay tick {}: {}", index + 1, e)))
+            .collect::<Result<Vec<ReplayTick>, String>>()?;
+
+        Ok(Self { header, ticks })
+    }
+
+    // Wall-clock time the match started at, in ms
+    pub fn started_at_ms(&self) -> u64 {
+        self.header.initial.time_ms
+    }
+}
+
+// Builds a replay while a game runs; owned by the game being recorded
+#[derive(Debug)]
+pub struct ReplayRecorder {
+    replay: Replay,
+    last_time_ms: u64,
+    pending_actions: Vec<ReplayAction>,
+    last_inputs: HashMap<u32, RecordedInput>, // Input each player was left with by the previous step
+    names: HashMap<u32, String>,
+}
+
+impl ReplayRecorder {
+    pub fn start(game: &Game, fixed_dt: f32, game_width: f32, game_height: f32) -> Self {
+        let players: Vec<InitialPlayer> = game.players.values()
+            .map(|player| InitialPlayer {
+                id: player.id,
+                team: player.team,
+                name: player.display_name.clone(),
+                is_host: player.is_host,
+                x: player.ship.x,
+                y: player.ship.y,
+                velocity: player.velocity,
+                shoot_cooldown: player.shoot_cooldown,
+                grab_cooldown: player.grab_cooldown,
+                rocket_cooldown: player.rocket_cooldown,
+                fuel: player.fuel,
+                input: RecordedInput::capture(player),
+                stats: player.stats.clone(),
+            })
+            .collect();
+
+        let last_inputs = players.iter().map(|player| (player.id, player.input.clone())).collect();
+        let names = players.iter().map(|player| (player.id, player.name.clone())).collect();
+
+        let header = ReplayHeader {
+            version: REPLAY_VERSION,
+            map_id: game.map.id.clone(),
+            map: game.map.objects.clone(),
+            seed: game.rng.state(),
+            fixed_dt,
+            game_width,
+            game_height,
+            max_rewind_ms: game.position_history.max_rewind_ms(),
+            initial: InitialState {
+                tick: game.tick,
+                time_ms: game.time_ms,
+                next_projectile_id: game.next_projectile_id,
+                scores: [game.team1_score, game.team2_score, game.team3_score, game.team4_score],
+                goal_cooldown: game.goal_cooldown,
+                countdown_remaining: game.countdown_remaining,
+                ball: game.ball.clone(),
+                projectiles: game.projectiles.clone(),
+                players,
+                match_settings: game.match_settings,
+                match_state: game.match_state,
+                touches: game.touches,
+            },
+        };
+
+        Self {
+            replay: Replay { header, ticks: Vec::new() },
+            last_time_ms: game.time_ms,
+            pending_actions: Vec::new(),
+            last_inputs,
+            names,
+        }
+    }
+
+    pub fn action(&mut self, action: ReplayAction) {
+        match &action {
+            ReplayAction::Join { id, name, .. } | ReplayAction::Rename { id, name } => {
+                self.names.insert(*id, name.clone());
+            }
+            ReplayAction::Leave { id } => {
+                self.names.remove(id);
+                self.last_inputs.remove(id);
+            }
+            _ => {}
+        }
+        self.pending_actions.push(action);
+    }
+
+    // Record a step once this tick's inputs have been applied
+    pub fn record_step(&mut self, players: &BTreeMap<u32, Player>, now_ms: u64) {
+        // Display names change outside the game (through input messages), so pick them up here
+        for player in players.values() {
+            if self.names.get(&player.id) != Some(&player.display_name) {
+                self.action(ReplayAction::Rename { id: player.id, name: player.display_name.clone() });
+            }
+        }
+
+        let inputs = players.values()
+            .map(RecordedInput::capture)
+            .filter(|input| self.last_inputs.get(&input.id) != Some(input))
+            .collect();
+
+        self.replay.ticks.push(ReplayTick {
+            dt_ms: now_ms.saturating_sub(self.last_time_ms),
+            actions: std::mem::take(&mut self.pending_actions),
+            inputs,
+        });
+        self.last_time_ms = now_ms;
+    }
+
+    // Remember the inputs a step left behind (shots clear their shoot flag) as the next baseline
+    pub fn end_step(&mut self, players: &BTreeMap<u32, Player>) {
+        self.last_inputs = players.values()
+            .map(|player| (player.id, RecordedInput::capture(player)))
+            .collect();
+    }
+
+    pub fn settings(&self) -> (f32, f32, f32) {
+        let header = &self.replay.header;
+        (header.fixed_dt, header.game_width, header.game_height)
+    }
+
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+// Re-simulates a replay tick by tick
+pub struct ReplayPlayer {
+    replay: Arc<Replay>,
+    map: Arc<GameMap>,
+    game: Game,
+    position: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Arc<Replay>) -> Result<Self, String> {
+        let map = GameMap {
+            id: replay.header.map_id.clone(),
+            objects: replay.header.map.clone(),
+        };
+        map.validate()?;
+        let map = Arc::new(map);
+        let game = Self::initial_game(&replay, &map);
+        Ok(Self { replay, map, game, position: 0 })
+    }
+
+    fn initial_game(replay: &Replay, map: &Arc<GameMap>) -> Game {
+        let header = &replay.header;
+        let initial = &header.initial;
+        let mut game = Game::new(map.clone(), header.seed);
+
+        game.position_history = PositionHistory::new(header.max_rewind_ms);
+        game.tick = initial.tick;
+        game.time_ms = initial.time_ms;
+        game.next_projectile_id = initial.next_projectile_id;
+        [game.team1_score, game.team2_score, game.team3_score, game.team4_score] = initial.scores;
+        game.goal_cooldown = initial.goal_cooldown;
+        game.countdown_remaining = initial.countdown_remaining;
+        game.ball = initial.ball.clone();
+        game.projectiles = initial.projectiles.clone();
+        game.match_settings = initial.match_settings;
+        game.match_state = initial.match_state;
+        game.touches = initial.touches;
+
+        for saved in &initial.players {
+            // Spawn with a throwaway RNG so restoring doesn't advance the game's own
+            let mut player = Player::new(saved.id, saved.team, saved.name.clone(), map, &mut SimRng::new(0));
+            player.is_host = saved.is_host;
+            player.ship = Ship { x: saved.x, y: saved.y };
+            player.velocity = saved.velocity;
+            player.shoot_cooldown = saved.shoot_cooldown;
+            player.grab_cooldown = saved.grab_cooldown;
+            player.rocket_cooldown = saved.rocket_cooldown;
+            player.fuel = saved.fuel;
+            player.stats = saved.stats.clone();
+            saved.input.apply(&mut player);
+            game.players.insert(saved.id, player);
+        }
+        game.recalculate_team_counts();
+        game
+    }
+
+    pub fn replay(&self) -> &Arc<Replay> {
+        &self.replay
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    // Number of ticks played so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.replay.ticks.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.replay.ticks.len()
+    }
+
+    // Play the next tick. Returns None at the end of the replay.
+    pub fn step(&mut self) -> Option<Vec<GameEvent>> {
+        let tick = self.replay.ticks.get(self.position)?;
+        self.position += 1;
+
+        for action in &tick.actions {
+            match action {
+                ReplayAction::Join { id, team, name } => {
+                    self.game.spawn_player(*id, *team, name.clone());
+                }
+                ReplayAction::Leave { id } => self.game.remove_player(*id),
+                ReplayAction::SwitchTeam { id, team } => self.game.set_player_team(*id, *team),
+                ReplayAction::Rename { id, name } => {
+                    if let Some(player) = self.game.players.get_mut(id) {
+                        player.set_display_name(name.clone());
+                    }
+                }
+                ReplayAction::ReturnBall { id } => {
+                    self.game.return_ball(*id);
+                }
+                ReplayAction::DropBall { id } => {
+                    self.game.release_held_ball(*id);
+                }
+            }
+        }
+        for input in &tick.inputs {
+            if let Some(player) = self.game.players.get_mut(&input.id) {
+                input.apply(player);
+            }
+        }
+
+        let header = &self.replay.header;
+        let now_ms = self.game.time_ms + tick.dt_ms;
+        Some(self.game.step(header.fixed_dt, header.game_width, header.game_height, now_ms))
+    }
+
+    // Jump to just after `position` ticks. Seeking backwards re-simulates from the start.
+    pub fn seek(&mut self, position: usize) {
+        let position = position.min(self.replay.ticks.len());
+        if position < self.position {
+            self.game = Self::initial_game(&self.replay, &self.map);
+            self.position = 0;
+        }
+        while self.position < position {
+            self.step();
+        }
+    }
+}
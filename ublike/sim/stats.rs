@@ -0,0 +1,83 @@
+// Per-player match statistics and the scoreboard built from them.
+//
+// Stats are simulation state: `Game::step` counts them only while goals count (live play and
+// overtime, not warmup), they're wiped when a match starts, and a replay carries them in its
+// initial state.
+// A goal goes to the last player who shot or held the ball; if the player before them was a
+// teammate, that player gets the assist. Putting the ball in your own team's goal counts as an
+// own goal instead.
+
+use serde::{Deserialize, Serialize};
+use super::player::{Player, Team};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerStats {
+    pub goals: u32,
+    pub own_goals: u32,
+    pub assists: u32,
+    pub shots: u32,          // Shots taken, including ones forced by the shot clock
+    pub rocket_hits: u32,    // Opponents caught in this player's rocket explosions
+    pub steals: u32,         // Balls knocked off an opponent with a rocket
+    pub possession: f32,     // Seconds spent holding the ball
+    pub distance: f32,       // Pixels travelled
+}
+
+// The last two different players to have the ball, used to credit goals and assists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TouchHistory {
+    pub last: Option<u32>,
+    pub previous: Option<u32>,
+}
+
+impl TouchHistory {
+    pub fn touch(&mut self, player_id: u32) {
+        if self.last != Some(player_id) {
+            self.previous = self.last;
+            self.last = Some(player_id);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+// One row of the scoreboard
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreboardEntry {
+    pub id: u32,
+    pub display_name: String,
+    pub team: Team,
+    pub bot: bool,
+    #[serde(flatten)]
+    pub stats: PlayerStats,
+}
+
+impl ScoreboardEntry {
+    pub fn new(player: &Player, bot: bool) -> Self {
+        Self {
+            id: player.id,
+            display_name: player.display_name.clone(),
+            team: player.team,
+            bot,
+            stats: player.stats.clone(),
+        }
+    }
+}
+
+// Order rows by team, then goals, assists and id
+pub fn sort_scoreboard(entries: &mut [ScoreboardEntry]) {
+    let team_order = |team: Team| match team {
+        Team::Red => 0,
+        Team::Blue => 1,
+        Team::Yellow => 2,
+        Team::Green => 3,
+    };
+    entries.sort_by(|a, b| {
+        team_order(a.team).cmp(&team_order(b.team))
+            .then(b.stats.goals.cmp(&a.stats.goals))
+            .then(b.stats.assists.cmp(&a.stats.assists))
+            .then(a.id.cmp(&b.id))
+    });
+}
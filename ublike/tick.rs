@@ -0,0 +1,148 @@
+// This module drives game loops at a fixed tick rate.
+//
+// Wall-clock time is accumulated and consumed one tick (1/TICK_RATE seconds) at a time, so
+// games run at real-time speed no matter how long a tick or a lock wait takes; a late tick is
+// followed by catch-up ticks without sleeping. Snapshots go out at SNAPSHOT_RATE, spread evenly
+// over the ticks. Both rates come from the environment and default to 30 Hz.
+
+use std::time::{Duration, Instant};
+use once_cell::sync::Lazy;
+
+pub const DEFAULT_TICK_RATE: u32 = 30;
+
+// A stall longer than this many ticks is skipped instead of fast-forwarded
+const MAX_CATCH_UP_TICKS: u32 = 5;
+
+pub struct TickConfig {
+    pub tick_rate: u32,     // Simulation ticks per second
+    pub snapshot_rate: u32, // Snapshots per second, at most one per tick
+}
+
+impl TickConfig {
+    pub fn from_env() -> Self {
+        let tick_rate = rate_from_env("TICK_RATE").unwrap_or(DEFAULT_TICK_RATE);
+        let snapshot_rate = rate_from_env("SNAPSHOT_RATE").unwrap_or(tick_rate).min(tick_rate);
+        Self { tick_rate, snapshot_rate }
+    }
+
+    // Simulated seconds per tick
+    pub fn fixed_dt(&self) -> f32 {
+        1.0 / self.tick_rate as f32
+    }
+}
+
+fn rate_from_env(name: &str) -> Option<u32> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .filter(|rate| *rate > 0)
+}
+
+pub static TICK_CONFIG: Lazy<TickConfig> = Lazy::new(|| {
+    let config = TickConfig::from_env();
+    println!("Tick rate: {} Hz, snapshot rate: {} Hz", config.tick_rate, config.snapshot_rate);
+    config
+});
+
+pub struct TickScheduler {
+    tick_duration: Duration,
+    tick_rate: u32,
+    snapshot_rate: u32,
+    accumulator: Duration,
+    last_time: Instant,
+    snapshot_accumulator: u32,
+}
+
+impl TickScheduler {
+    pub fn new(config: &TickConfig) -> Self {
+        Self {
+            tick_duration: Duration::from_secs(1) / config.tick_rate,
+            tick_rate: config.tick_rate,
+            snapshot_rate: config.snapshot_rate,
+            accumulator: Duration::ZERO,
+            last_time: Instant::now(),
+            snapshot_accumulator: 0,
+        }
+    }
+
+    // Wait until the next tick is due. Returns immediately while catching up.
+    pub async fn next_tick(&mut self) {
+        while !self.advance(Instant::now()) {
+            tokio::time::sleep(self.tick_duration - self.accumulator).await;
+        }
+    }
+
+    // Add the time since the last call to the backlog and take one tick from it if one is due
+    fn advance(&mut self, now: Instant) -> bool {
+        self.accumulator += now - self.last_time;
+        self.last_time = now;
+
+        let max_backlog = self.tick_duration * MAX_CATCH_UP_TICKS;
+        if self.accumulator > max_backlog {
+            println!("Tick scheduler fell behind by {:?}, skipping ticks", self.accumulator - max_backlog);
+            self.accumulator = max_backlog;
+        }
+
+        if self.accumulator >= self.tick_duration {
+            self.accumulator -= self.tick_duration;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Whether the tick that just ran should be followed by a snapshot
+    pub fn snapshot_due(&mut self) -> bool {
+        self.snapshot_accumulator += self.snapshot_rate;
+        if self.snapshot_accumulator >= self.tick_rate {
+            self.snapshot_accumulator -= self.tick_rate;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_rates(tick_rate: u32, snapshot_rate: u32) -> TickScheduler {
+        TickScheduler::new(&TickConfig { tick_rate, snapshot_rate })
+    }
+
+    // Ticks run when the clock reads `at`, counting catch-up ticks
+    fn ticks_at(scheduler: &mut TickScheduler, at: Instant) -> u32 {
+        let mut ticks = 0;
+        while scheduler.advance(at) {
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn late_ticks_catch_up_but_long_stalls_are_skipped() {
+        let mut scheduler = with_rates(30, 30);
+        let start = scheduler.last_time;
+        let tick = scheduler.tick_duration;
+
+        assert_eq!(ticks_at(&mut scheduler, start + tick / 2), 0);
+        assert_eq!(ticks_at(&mut scheduler, start + tick * 3), 3);
+        // A stall of 100 ticks only replays MAX_CATCH_UP_TICKS of them
+        assert_eq!(ticks_at(&mut scheduler, start + tick * 103), MAX_CATCH_UP_TICKS);
+        assert_eq!(ticks_at(&mut scheduler, start + tick * 104), 1);
+    }
+
+    #[test]
+    fn snapshots_are_spread_evenly_over_the_ticks() {
+        let mut scheduler = with_rates(60, 20);
+        let due: Vec<bool> = (0..6).map(|_| scheduler.snapshot_due()).collect();
+        assert_eq!(due, [false, false, true, false, false, true]);
+
+        let mut scheduler = with_rates(30, 30);
+        assert!((0..30).all(|_| scheduler.snapshot_due()));
+
+        let mut scheduler = with_rates(60, 25);
+        assert_eq!((0..120).filter(|_| scheduler.snapshot_due()).count(), 50);
+    }
+}
@@ -0,0 +1,147 @@
+// This module signs the join tickets the lobby hands out and checks them when a game socket
+// connects.
+//
+// A ticket says which game it's for, which lobby session asked for it, whether it's for a
+// spectator and when it expires, signed with HMAC-SHA256 so the game endpoint can trust it
+// without asking the lobby. The key comes from TICKET_SECRET, or is generated at startup
+// (tickets then stop working across restarts, which is fine since they only live for
+// TICKET_TTL_MS). Each ticket also carries a random nonce so a game can refuse a ticket it has
+// already admitted a connection with.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// How long a client has to connect to the game after the lobby sends its ticket
+pub const TICKET_TTL_MS: u64 = 60_000;
+
+const HMAC_BLOCK_SIZE: usize = 64;
+
+static TICKET_KEY: Lazy<Vec<u8>> = Lazy::new(|| match std::env::var("TICKET_SECRET") {
+    Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+    _ => rand::random::<[u8; 32]>().to_vec(),
+});
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinTicket {
+    pub game_id: String,
+    pub session: String, // Lobby client the ticket was issued to
+    pub spectator: bool,
+    pub nonce: String,
+    pub expires_at: u64, // Server time in ms
+}
+
+impl JoinTicket {
+    pub fn new(game_id: &str, session: &str, spectator: bool, now_ms: u64) -> Self {
+        Self {
+            game_id: game_id.to_string(),
+            session: session.to_string(),
+            spectator,
+            nonce: uuid::Uuid::new_v4().simple().to_string(),
+            expires_at: now_ms + TICKET_TTL_MS,
+        }
+    }
+
+    // The ticket as sent to the client: hex of the JSON payload, a dot, then hex of its signature
+    pub fn sign(&self) -> String {
+        let payload = serde_json::to_vec(self).expect("ticket serializes");
+        format!("{}.{}", to_hex(&payload), to_hex(&hmac_sha256(&TICKET_KEY, &payload)))
+    }
+
+    // Check a ticket's signature, game and expiry
+    pub fn verify(ticket: &str, game_id: &str, now_ms: u64) -> Result<Self, &'static str> {
+        let (payload, signature) = ticket.split_once('.').ok_or("Malformed join ticket")?;
+        let payload = from_hex(payload).ok_or("Malformed join ticket")?;
+        let signature = from_hex(signature).ok_or("Malformed join ticket")?;
+        if !constant_time_eq(&hmac_sha256(&TICKET_KEY, &payload), &signature) {
+            return Err("Invalid join ticket");
+        }
+
+        let ticket: Self = serde_json::from_slice(&payload).map_err(|_| "Malformed join ticket")?;
+        if ticket.game_id != game_id {
+            return Err("Join ticket is for another game");
+        }
+        if now_ms > ticket.expires_at {
+            return Err("Join ticket has expired");
+        }
+        Ok(ticket)
+    }
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; HMAC_BLOCK_SIZE];
+    if key.len() > HMAC_BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let inner_pad: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    let outer_pad: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    let inner = Sha256::new().chain_update(&inner_pad).chain_update(message).finalize();
+    Sha256::new().chain_update(&outer_pad).chain_update(inner).finalize().into()
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW_MS: u64 = 1_000_000;
+
+    #[test]
+    fn signed_ticket_verifies() {
+        let ticket = JoinTicket::new("game_1", "session", true, NOW_MS);
+        let verified = JoinTicket::verify(&ticket.sign(), "game_1", NOW_MS + 1_000).unwrap();
+        assert_eq!(verified.session, "session");
+        assert!(verified.spectator);
+        assert_eq!(verified.nonce, ticket.nonce);
+    }
+
+    #[test]
+    fn expired_ticket_is_refused() {
+        let signed = JoinTicket::new("game_1", "session", false, NOW_MS).sign();
+        assert!(JoinTicket::verify(&signed, "game_1", NOW_MS + TICKET_TTL_MS).is_ok());
+        let result = JoinTicket::verify(&signed, "game_1", NOW_MS + TICKET_TTL_MS + 1);
+        assert_eq!(result.err(), Some("Join ticket has expired"));
+    }
+
+    #[test]
+    fn ticket_for_another_game_is_refused() {
+        let signed = JoinTicket::new("game_1", "session", false, NOW_MS).sign();
+        let result = JoinTicket::verify(&signed, "game_2", NOW_MS);
+        assert_eq!(result.err(), Some("Join ticket is for another game"));
+    }
+
+    #[test]
+    fn tampered_ticket_is_refused() {
+        let signed = JoinTicket::new("game_1", "session", true, NOW_MS).sign();
+        let (_, signature) = signed.split_once('.').unwrap();
+
+        // Same signature on a payload that no longer claims to be a spectator
+        let mut ticket = JoinTicket::new("game_1", "session", true, NOW_MS);
+        ticket.spectator = false;
+        let forged = format!("{}.{}", to_hex(&serde_json::to_vec(&ticket).unwrap()), signature);
+        assert_eq!(JoinTicket::verify(&forged, "game_1", NOW_MS).err(), Some("Invalid join ticket"));
+
+        assert_eq!(JoinTicket::verify("not a ticket", "game_1", NOW_MS).err(), Some("Malformed join ticket"));
+        assert_eq!(JoinTicket::verify("abc.def", "game_1", NOW_MS).err(), Some("Malformed join ticket"));
+    }
+}
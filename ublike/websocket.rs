@@ -4,35 +4,25 @@ use warp::ws::{WebSocket, Message};
 use serde_json::json;
 use tokio::sync::Mutex;
 use std::sync::Arc;
-use crate::game::Game;
-use crate::player::Player;
-use crate::player::Team;
+use std::collections::HashMap;
+use crate::accounts::{account_for_token, ACCOUNTS};
+use crate::game::{now_ms, reconnect_grace_from_env, Game};
+use crate::sim::player::Player;
+use crate::sim::player::Team;
 // use crate::webrtc_signaling::{WebRTCSignalingManager, is_webrtc_message, parse_webrtc_message}; // Removed - WebTransport used instead
-use crate::dual_connection::{DualConnectionManager, MessageType};
+use crate::dual_connection::{DualConnectionManager, MessageType, PlayerSlot, ResumeClaim, SpectatorCamera};
+use crate::chat::{clean_name, handle_game_chat, quick_chat_presets, ChatLimiter, ChatRequest};
+use crate::host::{handle_host_command, migrate_host, HostCommand};
+use crate::moderation::Identity;
+use crate::protocol::{decode_client_frame, ClientFrame, InputMessage, WireFormat, PROTOCOL_VERSION};
 // use crate::webrtc_datachannel::{WebRTCDataChannelManager, is_datachannel_signaling, is_datachannel_input}; // Removed - WebTransport used instead
 use futures::{StreamExt, SinkExt};
 use serde::{Serialize, Deserialize};
 use warp::Filter;
 // use once_cell::sync::Lazy; // Unused import removed
-use chrono;
 
 // WebRTC signaling manager removed - WebTransport handles signaling internally
 
-#[derive(Deserialize, Debug)]
-pub struct InputMessage {
-    pub left: bool,
-    pub right: bool,
-    pub up: bool,
-    pub down: bool,
-    pub seq: u32,
-    pub shoot: Option<bool>,
-    pub boost: Option<bool>,
-    pub target_x: Option<f32>,
-    pub target_y: Option<f32>,
-    #[serde(default)]
-    pub display_name: String,
-}
-
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "type")]
 enum PingMessage {
@@ -50,10 +40,35 @@ struct TeamSwitchMessage {
     team: String,
 }
 
+// Sent by the client after init to pick the snapshot encoding
 #[derive(Deserialize, Debug)]
-struct ReliableShootMessage {
+struct WireFormatMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    format: String,
+    #[serde(default)]
+    version: u8,
+}
+
+// Acknowledges a received snapshot so later snapshots can be sent as deltas against it
+#[derive(Deserialize, Debug)]
+struct SnapshotAckMessage {
     #[serde(rename = "type")]
     message_type: String,
+    snapshot_id: u32,
+}
+
+// Sent by a spectator to follow a player (`follow: <id>`) or go back to the free camera (`follow: null`)
+#[derive(Deserialize, Debug)]
+struct SpectateMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    #[serde(default)]
+    follow: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReliableShootMessage {
     shot_id: u32,
     timestamp: u64,
     target_x: f32,
@@ -61,48 +76,276 @@ struct ReliableShootMessage {
     seq: u32,
 }
 
-pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr: Arc<DualConnectionManager>) {
+// Queue an input message from a fast channel (fast WebSocket or WebTransport) for a player
+pub fn apply_fast_input(player: &mut Player, input_msg: &InputMessage) {
+    player.input_buffer.push_all(input_msg.commands());
+    if let Some(view_time) = input_msg.view_time {
+        player.set_view_time(view_time, now_ms());
+    }
+}
+
+// Parse a message from the fast channel or a WebTransport datagram: a binary frame, a JSON
+// snapshot ack, or a JSON input message
+pub fn parse_fast_payload(payload: &[u8]) -> Option<ClientFrame> {
+    if payload.first() != Some(&b'{') {
+        return decode_client_frame(payload).ok();
+    }
+    if let Ok(ack) = serde_json::from_slice::<SnapshotAckMessage>(payload) {
+        if ack.message_type == "snapshot_ack" {
+            return Some(ClientFrame::SnapshotAck(ack.snapshot_id));
+        }
+    }
+    serde_json::from_slice::<InputMessage>(payload).ok().map(ClientFrame::Input)
+}
+
+// Handle a set_wire_format request from a player or spectator
+async fn negotiate_wire_format(client_id: u32, txt: &str, dual_mgr: &DualConnectionManager) {
+    if let Ok(format_msg) = serde_json::from_str::<WireFormatMessage>(txt) {
+        println!("Client {} requested wire format {} (type={}, version={})", 
+                 client_id, format_msg.format, format_msg.message_type, format_msg.version);
+        
+        // Unknown formats or protocol versions fall back to JSON
+        let format = match WireFormat::from_name(&format_msg.format) {
+            Some(WireFormat::Binary) if format_msg.version == PROTOCOL_VERSION => WireFormat::Binary,
+            _ => WireFormat::Json,
+        };
+        dual_mgr.set_wire_format(client_id, format).await;
+        
+        let reply = json!({
+            "type": "wire_format",
+            "format": format.name(),
+            "version": PROTOCOL_VERSION
+        });
+        let _ = dual_mgr.send_to_client(client_id, MessageType::WireFormat, reply).await;
+    }
+}
+
+// Send a message to the other humans on a player's team
+async fn notify_teammates(game: &Game, dual_mgr: &DualConnectionManager, player_id: u32, message_type: MessageType, data: serde_json::Value) {
+    let team = match game.players.get(&player_id) {
+        Some(player) => player.team,
+        None => return,
+    };
+    let teammates = game.players.values()
+        .filter(|player| player.team == team && player.id != player_id && !game.is_bot(player.id));
+    for teammate in teammates {
+        let _ = dual_mgr.send_to_client(teammate.id, message_type.clone(), data.clone()).await;
+    }
+}
+
+// Take a player out of the game for good once their connection is gone
+pub async fn remove_disconnected_player(game_lock: &mut Game, dual_mgr: &DualConnectionManager, player_id: u32) {
+    // Remove player and update team counts
+    if let Some(team) = game_lock.players.get(&player_id).map(|player| player.team) {
+        game_lock.remove_player(player_id);
+        println!("Player {} (team: {:?}) disconnected", player_id, team);
+    }
+    
+    // Spectators following this player go back to the free camera
+    dual_mgr.release_followers(player_id).await;
+    dual_mgr.remove_client(player_id).await;
+    
+    // Recalculate team counts to ensure they're accurate
+    game_lock.recalculate_team_counts();
+    
+    println!("Player {} disconnected. Game now has {} players (Red: {}, Blue: {}, Yellow: {}, Green: {})", 
+             player_id, 
+             game_lock.players.len(),
+             game_lock.red_team_count,
+             game_lock.blue_team_count,
+             game_lock.yellow_team_count,
+             game_lock.green_team_count);
+}
+
+// Remove a dropped player once their grace period is over, unless they resumed in time
+async fn expire_after_grace(game: Arc<Mutex<Game>>, dual_mgr: Arc<DualConnectionManager>, resume_token: String, player_id: u32, grace_secs: u64) {
+    tokio::time::sleep(tokio::time::Duration::from_secs(grace_secs)).await;
+    if dual_mgr.expire_resume(&resume_token).await {
+        println!("Player {} did not reconnect within {}s", player_id, grace_secs);
+        let mut game_lock = game.lock().await;
+        remove_disconnected_player(&mut game_lock, &dual_mgr, player_id).await;
+    }
+}
+
+// Send the current match's scoreboard to a player or spectator who asked for it
+async fn send_scoreboard(client_id: u32, game: &Arc<Mutex<Game>>, dual_mgr: &DualConnectionManager) {
+    let scoreboard = game.lock().await.scoreboard();
+    let _ = dual_mgr.send_to_client(client_id, MessageType::Scoreboard, scoreboard).await;
+}
+
+// Queue an input message from the reliable channel; the game loop applies one command per tick
+fn apply_input_message(player: &mut Player, input_msg: &InputMessage) {
+    let player_id = player.id;
+    
+    // Debug: Log input processing
+    if input_msg.left || input_msg.right || input_msg.up || input_msg.down {
+        println!("Server received movement input from player {}: left={}, right={}, up={}, down={}, seq={}", 
+                 player_id, input_msg.left, input_msg.right, input_msg.up, input_msg.down, input_msg.seq);
+    }
+    
+    // Log when a shoot command is received
+    if input_msg.shoot == Some(true) {
+        println!("Player {} is shooting", player_id);
+    }
+    // Log when a boost/projectile command is received
+    if input_msg.boost == Some(true) {
+        println!("Player {} is firing a projectile. Current cooldown: {}", player_id, player.rocket_cooldown);
+    }
+    
+    if player.input_buffer.push_all(input_msg.commands()) == 0 {
+        println!("Server REJECTED input from player {}: seq {} was already received", 
+                 player_id, input_msg.seq);
+    }
+    if let Some(view_time) = input_msg.view_time {
+        player.set_view_time(view_time, now_ms());
+    }
+    
+    // Update player display name if provided
+    if !input_msg.display_name.is_empty() && input_msg.display_name != player.display_name {
+        player.set_display_name(clean_name(&input_msg.display_name));
+    }
+}
+
+// How a player connection takes its place in the game
+pub enum Seat {
+    New(PlayerSlot), // A new player, in a slot reserved before the upgrade
+    // A dropped player, claimed with the `?resume=...` token from their last `init` message before
+    // the socket was upgraded. The connection takes over that player (same id, team and stats).
+    Resume(ResumeClaim),
+}
+
+// `origin` is the session and address the connection came from, which bans can target.
+pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr: Arc<DualConnectionManager>, token: Option<String>, seat: Seat, origin: Identity) {
     let (tx, mut rx) = ws.split();
     let tx = Arc::new(Mutex::new(tx));
+    
+    // A claimed player is lost if their grace period ends or they're kicked before the claim is
+    // finished; the connection is closed then rather than joining as a new player
+    let (resumed, slot) = match seat {
+        Seat::New(slot) => (None, slot),
+        Seat::Resume(claim) => {
+            let (player_id, identity) = (claim.player_id, claim.identity.clone());
+            let Some(slot) = claim.finish().await else {
+                println!("Player {} can no longer be resumed, closing the connection", player_id);
+                let _ = tx.lock().await.close().await;
+                return;
+            };
+            (Some((player_id, identity)), slot)
+        }
+    };
+    
+    // Play under the account the token belongs to (or the resumed player's account); an unknown
+    // token plays anonymously
+    let account = match &resumed {
+        Some((_, identity)) => match &identity.account_id {
+            Some(account_id) => ACCOUNTS.lock().await.info(account_id),
+            None => None,
+        },
+        None => account_for_token(token.as_ref()).await,
+    };
+    let reserved_team = match &account {
+        Some(account) => dual_mgr.reserved_team(&account.id).await,
+        None => None,
+    };
 
-    // Get a unique player ID for this connection
-    let (player_id, player_team, is_host) = {
+    // Get a unique player ID for this connection. The connection is registered before the game
+    // is unlocked, so a second connection for the same account always sees the first.
+    let (player_id, player_team, is_host, map_id, is_resumed, secrets) = {
         let mut game_lock = game.lock().await;
-        let id = game_lock.next_id;
-        game_lock.next_id += 1;
-        
-        println!("New reliable connection for player ID: {}", id);
         
-        // Assign the player to a team (red or blue)
-        let team = game_lock.assign_team();
-        println!("Player {} assigned to team: {:?}", id, team);
-        
-        // Determine if this player should be the host (first player)
-        let is_host = game_lock.players.is_empty();
-        println!("Player {} is host: {}", id, is_host);
+        let resumed_player = match &resumed {
+            Some((id, _)) => match game_lock.players.get(id) {
+                Some(player) => Some((player.id, player.team, player.is_host)),
+                None => {
+                    drop(game_lock);
+                    println!("Player {} left the game before they resumed, closing the connection", id);
+                    let _ = tx.lock().await.close().await;
+                    return;
+                }
+            },
+            None => None,
+        };
         
-        // Add the player to this specific game instance
-        let display_name = format!("Player_{}", id);
-        let mut player = Player::new(id, team, display_name);
-        player.is_host = is_host; // Set host status
-        game_lock.players.insert(id, player);
+        // An account plays at most once in a game, so it can't be on two teams at once
+        if let (None, Some(account)) = (&resumed, &account) {
+            if dual_mgr.has_player_account(&account.id).await {
+                drop(game_lock);
+                println!("Account {} is already playing in this game, closing the connection", account.id);
+                let error_msg = json!({
+                    "type": "error",
+                    "message": "Your account is already playing in this game"
+                });
+                let _ = tx.lock().await.send(Message::text(error_msg.to_string())).await;
+                let _ = tx.lock().await.close().await;
+                return;
+            }
+        }
         
-        // Note: We no longer store WebSocket senders directly in game.clients
-        // The dual connection manager handles all connections
+        let (id, team, is_host, is_resumed) = if let Some((id, team, was_host)) = resumed_player {
+            println!("Player {} resumed after their connection dropped", id);
+            
+            // Take the host role back if nobody picked it up while they were away
+            let is_host = was_host || !game_lock.players.values().any(|player| player.is_host);
+            if let Some(player) = game_lock.players.get_mut(&id) {
+                player.is_host = is_host;
+            }
+            let reconnected = json!({
+                "type": "player_reconnected",
+                "player_id": id
+            });
+            notify_teammates(&game_lock, &dual_mgr, id, MessageType::PlayerReconnected, reconnected).await;
+            (id, team, is_host, true)
+        } else {
+            let id = game_lock.allocate_player_id();
+            
+            println!("New reliable connection for player ID: {}", id);
+            
+            // Assign the player to a team (red or blue), taking a bot's place if every team is full.
+            // Players placed by the matchmaker go to the team it picked for them.
+            game_lock.make_room_for_human();
+            let team = match reserved_team {
+                Some(team) => game_lock.assign_preferred_team(team),
+                None => game_lock.assign_team(),
+            };
+            println!("Player {} assigned to team: {:?}", id, team);
+            
+            // Determine if this player should be the host (first human player, or the game has
+            // lost its host)
+            let is_host = !game_lock.players.values().any(|player| player.is_host);
+            println!("Player {} is host: {}", id, is_host);
+            
+            // Add the player to this specific game instance, named after their account if they have one
+            let display_name = match &account {
+                Some(account) => clean_name(&account.name),
+                None => format!("Player_{}", id),
+            };
+            let player = game_lock.spawn_player(id, team, display_name);
+            player.is_host = is_host; // Set host status
+            
+            // Note: We no longer store WebSocket senders directly in game.clients
+            // The dual connection manager handles all connections
+            
+            // Log the number of players in this game instance
+            println!("Game now has {} players (Red: {}, Blue: {}, Yellow: {}, Green: {})", 
+                     game_lock.players.len(), 
+                     game_lock.red_team_count, 
+                     game_lock.blue_team_count,
+                     game_lock.yellow_team_count,
+                     game_lock.green_team_count);
+            
+            (id, team, is_host, false)
+        };
         
-        // Log the number of players in this game instance
-        println!("Game now has {} players (Red: {}, Blue: {}, Yellow: {}, Green: {})", 
-                 game_lock.players.len(), 
-                 game_lock.red_team_count, 
-                 game_lock.blue_team_count,
-                 game_lock.yellow_team_count,
-                 game_lock.green_team_count);
+        // Add reliable connection to dual connection manager
+        let secrets = dual_mgr.add_reliable_connection(id, Arc::clone(&tx), slot).await;
+        let identity = Identity {
+            account_id: account.as_ref().map(|account| account.id.clone()),
+            ..origin
+        };
+        dual_mgr.set_identity(id, identity).await;
         
-        (id, team, is_host)
+        (id, team, is_host, game_lock.map.id.clone(), is_resumed, secrets)
     };
-    
-    // Add reliable connection to dual connection manager  
-    dual_mgr.add_reliable_connection(player_id, Arc::clone(&tx)).await;
 
     // Send initial player ID, team, and host status to the client
     {
@@ -117,13 +360,30 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
             "type": "init",
             "your_id": player_id,
             "team": team_str,
-            "is_host": is_host
+            "is_host": is_host,
+            "account": account,
+            "fast_secret": secrets.fast_secret,
+            "resume_token": secrets.resume_token, // Pass as ?resume=... to take this player back after a drop
+            "resumed": is_resumed,
+            "map_id": map_id,
+            "quick_chat": quick_chat_presets(),
+            "wire_formats": ["json", "binary"],
+            "protocol_version": PROTOCOL_VERSION
         });
         
         // Send via dual connection manager (reliable channel)
         let _ = dual_mgr.send_to_client(player_id, MessageType::PlayerJoin, init_msg).await;
+        
+        if token.is_some() && account.is_none() {
+            let error_msg = json!({
+                "type": "error",
+                "message": "Unknown account token, playing as a guest"
+            });
+            let _ = tx.lock().await.send(Message::text(error_msg.to_string())).await;
+        }
     }
 
+    let mut chat_limiter = ChatLimiter::new();
     while let Some(result) = rx.next().await {
         match result {
             Ok(msg) => {
@@ -141,15 +401,11 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                     
                     // Try to parse as ping message
                     if let Ok(ping_msg) = serde_json::from_str::<PingMessage>(txt) {
-                        match ping_msg {
-                            PingMessage::Ping { timestamp } => {
-                                // Respond immediately with a pong.
-                                let pong = PingMessage::Pong { timestamp };
-                                let pong_text = serde_json::to_string(&pong).unwrap();
-                                tx.lock().await.send(Message::text(pong_text)).await.unwrap();
-                                continue;
-                            }
-                            _ => {}
+                        if let PingMessage::Ping { timestamp } = ping_msg {
+                            // Respond immediately with a pong.
+                            let pong = PingMessage::Pong { timestamp };
+                            let pong_text = serde_json::to_string(&pong).unwrap();
+                            let _ = tx.lock().await.send(Message::text(pong_text)).await;
                         }
                         continue;
                     }
@@ -161,6 +417,16 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                             
                             let mut game = game.lock().await;
                             
+                            if game.teams_locked {
+                                let error_msg = json!({
+                                    "type": "error",
+                                    "message": "The host has locked the teams"
+                                });
+                                drop(game);
+                                let _ = tx.lock().await.send(Message::text(error_msg.to_string())).await;
+                                continue;
+                            }
+                            
                             // Debug: Print current team counts
                             println!("Current team counts - Red: {}, Blue: {}, Yellow: {}, Green: {}", 
                                      game.red_team_count, game.blue_team_count, game.yellow_team_count, game.green_team_count);
@@ -168,10 +434,10 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                             // Convert string team to enum
                             let new_team_str = team_msg.team.as_str();
                             let new_team = match new_team_str {
-                                "Red" => crate::player::Team::Red,
-                                "Blue" => crate::player::Team::Blue,
-                                "Yellow" => crate::player::Team::Yellow,
-                                "Green" => crate::player::Team::Green,
+                                "Red" => Team::Red,
+                                "Blue" => Team::Blue,
+                                "Yellow" => Team::Yellow,
+                                "Green" => Team::Green,
                                 _ => {
                                     // Invalid team, skip processing
                                     continue;
@@ -188,6 +454,9 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                             
                             // Only switch if it's a different team
                             if new_team != current_team {
+                                // A bot on the new team gives up its place for a human
+                                game.make_room_on_team(new_team);
+                                
                                 // Recalculate team counts to ensure they're accurate
                                 game.recalculate_team_counts();
                                 
@@ -203,25 +472,26 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                                         "type": "error",
                                         "message": format!("{:?} team is full", new_team)
                                     });
-                                    tx.lock().await.send(Message::text(error_msg.to_string())).await.unwrap();
+                                    drop(game);
+                                    let _ = tx.lock().await.send(Message::text(error_msg.to_string())).await;
                                     continue;
                                 }
                                 
                                 // Update team counts first - subtract from current team
                                 match current_team {
-                                    crate::player::Team::Red => {
+                                    Team::Red => {
                                         game.red_team_count = game.red_team_count.saturating_sub(1);
                                         println!("Decremented red team count to {}", game.red_team_count);
                                     },
-                                    crate::player::Team::Blue => {
+                                    Team::Blue => {
                                         game.blue_team_count = game.blue_team_count.saturating_sub(1);
                                         println!("Decremented blue team count to {}", game.blue_team_count);
                                     },
-                                    crate::player::Team::Yellow => {
+                                    Team::Yellow => {
                                         game.yellow_team_count = game.yellow_team_count.saturating_sub(1);
                                         println!("Decremented yellow team count to {}", game.yellow_team_count);
                                     },
-                                    crate::player::Team::Green => {
+                                    Team::Green => {
                                         game.green_team_count = game.green_team_count.saturating_sub(1);
                                         println!("Decremented green team count to {}", game.green_team_count);
                                     },
@@ -229,27 +499,27 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                                 
                                 // Update new team count - add to new team
                                 match new_team {
-                                    crate::player::Team::Red => {
+                                    Team::Red => {
                                         game.red_team_count += 1;
                                         println!("Incremented red team count to {}", game.red_team_count);
                                     },
-                                    crate::player::Team::Blue => {
+                                    Team::Blue => {
                                         game.blue_team_count += 1;
                                         println!("Incremented blue team count to {}", game.blue_team_count);
                                     },
-                                    crate::player::Team::Yellow => {
+                                    Team::Yellow => {
                                         game.yellow_team_count += 1;
                                         println!("Incremented yellow team count to {}", game.yellow_team_count);
                                     },
-                                    crate::player::Team::Green => {
+                                    Team::Green => {
                                         game.green_team_count += 1;
                                         println!("Incremented green team count to {}", game.green_team_count);
                                     },
                                 }
                                 
                                 // Now update the player's team
-                                if let Some(player) = game.players.get_mut(&player_id) {
-                                    player.team = new_team;
+                                if game.players.contains_key(&player_id) {
+                                    game.set_player_team(player_id, new_team);
                                     println!("Player {} successfully switched to {:?} team", player_id, new_team);
                                     println!("Updated team counts - Red: {}, Blue: {}, Yellow: {}, Green: {}", 
                                              game.red_team_count, game.blue_team_count, game.yellow_team_count, game.green_team_count);
@@ -264,33 +534,47 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                     
                     // WebRTC message processing removed - WebTransport handles ultra-low latency input instead
                     
-                    // Check for reset game message
-                    if txt.contains("\"type\":\"reset_game\"") || txt.contains("\"type\": \"reset_game\"") {
-                        println!("Received reset game request from player {}", player_id);
-                        
-                        // Check if the player is the host
-                        let is_player_host = {
-                            let game_lock = game.lock().await;
-                            match game_lock.players.get(&player_id) {
-                                Some(player) => player.is_host,
-                                None => false
-                            }
-                        };
-                        
-                        if is_player_host {
-                            println!("Player {} is host, processing reset game request", player_id);
-                            let mut game_lock = game.lock().await;
-                            game_lock.reset_game(Some(dual_mgr.clone()));
-                        } else {
-                            println!("Player {} is not host, ignoring reset game request", player_id);
-                            // Optionally send a message back to the client that they don't have permission
+                    // Team and all chat, typed or quick chat
+                    if let Ok(request) = serde_json::from_str::<ChatRequest>(txt) {
+                        if let Err(message) = handle_game_chat(player_id, request, &mut chat_limiter, &game, &dual_mgr).await {
+                            let error_msg = json!({
+                                "type": "error",
+                                "message": message
+                            });
+                            let _ = tx.lock().await.send(Message::text(error_msg.to_string())).await;
+                        }
+                        continue;
+                    }
+                    
+                    // Host commands: reset, kick, change map, start match, lock teams, change settings
+                    if let Ok(command) = serde_json::from_str::<HostCommand>(txt) {
+                        if let Err(message) = handle_host_command(player_id, command, &game, &dual_mgr).await {
                             let error_msg = json!({
                                 "type": "error",
-                                "message": "Only the host can reset the game"
+                                "message": message
                             });
-                            tx.lock().await.send(Message::text(error_msg.to_string())).await.unwrap();
+                            let _ = tx.lock().await.send(Message::text(error_msg.to_string())).await;
+                        }
+                        continue;
+                    }
+                    
+                    // Scoreboard requests
+                    if txt.contains("\"type\":\"scoreboard\"") || txt.contains("\"type\": \"scoreboard\"") {
+                        send_scoreboard(player_id, &game, &dual_mgr).await;
+                        continue;
+                    }
+                    
+                    // Snapshot encoding negotiation
+                    if txt.contains("\"type\":\"set_wire_format\"") || txt.contains("\"type\": \"set_wire_format\"") {
+                        negotiate_wire_format(player_id, txt, &dual_mgr).await;
+                        continue;
+                    }
+                    
+                    // Snapshot acknowledgements
+                    if txt.contains("\"type\":\"snapshot_ack\"") || txt.contains("\"type\": \"snapshot_ack\"") {
+                        if let Ok(ack) = serde_json::from_str::<SnapshotAckMessage>(txt) {
+                            dual_mgr.ack_snapshot(player_id, ack.snapshot_id).await;
                         }
-                        
                         continue;
                     }
                     
@@ -298,15 +582,27 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                     if txt.contains("\"type\":\"reliable_shoot\"") {
                         match serde_json::from_str::<ReliableShootMessage>(txt) {
                             Ok(shoot_msg) => {
-                                println!("Received reliable shoot command from player {}: shot_id={}, target=({}, {})", 
-                                         player_id, shoot_msg.shot_id, shoot_msg.target_x, shoot_msg.target_y);
+                                println!("Received reliable shoot command from player {}: shot_id={}, seq={}, target=({}, {})", 
+                                         player_id, shoot_msg.shot_id, shoot_msg.seq, shoot_msg.target_x, shoot_msg.target_y);
                                 
                                 let mut game_lock = game.lock().await;
                                 let mut ack_success = false;
                                 let mut ack_reason = "unknown_error";
                                 
+                                // The shot's timestamp is the server time of the state the shooter was looking at
+                                if let Some(player) = game_lock.players.get_mut(&player_id) {
+                                    player.set_view_time(shoot_msg.timestamp, now_ms());
+                                }
+                                
                                 // Check ball conditions first (before mutable borrow)
-                                let ball_available = game_lock.ball.grabbed && game_lock.ball.owner == Some(player_id);
+                                let mut ball_available = game_lock.ball.grabbed && game_lock.ball.owner == Some(player_id);
+                                
+                                // Lag compensation: the shooter still had the ball in the state they saw and it
+                                // has only been knocked loose since, so give it back for the shot
+                                if !ball_available && game_lock.return_ball_for_shot(player_id) {
+                                    println!("Lag compensation: returning ball to player {} for shot {}", player_id, shoot_msg.shot_id);
+                                    ball_available = true;
+                                }
                                 
                                 if let Some(player) = game_lock.players.get_mut(&player_id) {
                                     // Validate shot conditions
@@ -319,7 +615,6 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                                         player.input.shoot = true;
                                         player.input.target_x = Some(shoot_msg.target_x);
                                         player.input.target_y = Some(shoot_msg.target_y);
-                                        player.last_seq = shoot_msg.seq;
                                         
                                         // Store shot ID for tracking
                                         player.pending_shot_id = Some(shoot_msg.shot_id);
@@ -337,7 +632,7 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                                     "shot_id": shoot_msg.shot_id,
                                     "success": ack_success,
                                     "reason": ack_reason,
-                                    "timestamp": chrono::Utc::now().timestamp_millis() as u64
+                                    "timestamp": now_ms()
                                 });
                                 
                                 if let Err(e) = tx.lock().await.send(Message::text(ack_response.to_string())).await {
@@ -353,64 +648,34 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
                     
                     // Otherwise, parse as regular input.
                     match serde_json::from_str::<InputMessage>(txt) {
-                        Ok(input_msg) => {
+                        Ok(mut input_msg) => {
+                            // Players with an account keep their account name
+                            if account.is_some() {
+                                input_msg.display_name.clear();
+                            }
                             let mut game_lock = game.lock().await;
                             if let Some(player) = game_lock.players.get_mut(&player_id) {
-                                // Debug: Log input processing
-                                if input_msg.left || input_msg.right || input_msg.up || input_msg.down {
-                                    println!("Server received movement input from player {}: left={}, right={}, up={}, down={}, seq={}", 
-                                             player_id, input_msg.left, input_msg.right, input_msg.up, input_msg.down, input_msg.seq);
-                                }
-                                
-                                player.input.left = input_msg.left;
-                                player.input.right = input_msg.right;
-                                player.input.up = input_msg.up;
-                                player.input.down = input_msg.down;
-                                if let Some(shoot) = input_msg.shoot {
-                                    // Log when a shoot command is received
-                                    if shoot {
-                                        println!("Player {} is shooting", player_id);
-                                    }
-                                    player.input.shoot = shoot;
-                                }
-                                if let Some(boost) = input_msg.boost {
-                                    // Log when a boost/projectile command is received
-                                    if boost {
-                                        println!("Player {} is firing a projectile. Current cooldown: {}", player_id, player.rocket_cooldown);
-                                    } else {
-                                        // println!("Player {} released boost button", player_id); // Commented out to reduce spam
-                                    }
-                                    player.input.boost = boost;
-                                }
-                                player.input.target_x = input_msg.target_x;
-                                player.input.target_y = input_msg.target_y;
-                                
-                                // Update player display name if provided
-                                if !input_msg.display_name.is_empty() {
-                                    player.set_display_name(input_msg.display_name.clone());
-                                }
-                                
-                                if input_msg.seq > player.last_seq {
-                                    player.last_seq = input_msg.seq;
-                                } else {
-                                    println!("Server REJECTED input from player {}: received seq {} <= last_seq {}", 
-                                             player_id, input_msg.seq, player.last_seq);
-                                }
+                                apply_input_message(player, &input_msg);
                             }
                         },
                         Err(e) => println!("Failed to parse input: {:?}", e),
                     }
-                } else if msg.is_close() {
-                    // Handle ball state reset on disconnect
-                    let mut game_lock = game.lock().await;
-                    if let Some(_player) = game_lock.players.remove(&player_id) {
-                        if game_lock.ball.grabbed && game_lock.ball.owner == Some(player_id) {
-                            // Reset ball state
-                            game_lock.ball.grabbed = false;
-                            game_lock.ball.owner = None;
-                            game_lock.ball.grab_cooldown = 0.5; // Optional cooldown after dropping
-                        }
+                } else if msg.is_binary() {
+                    // Binary frames are compact input messages or snapshot acks (see protocol.rs)
+                    match decode_client_frame(msg.as_bytes()) {
+                        Ok(ClientFrame::Input(input_msg)) => {
+                            let mut game_lock = game.lock().await;
+                            if let Some(player) = game_lock.players.get_mut(&player_id) {
+                                apply_input_message(player, &input_msg);
+                            }
+                        },
+                        Ok(ClientFrame::SnapshotAck(snapshot_id)) => {
+                            dual_mgr.ack_snapshot(player_id, snapshot_id).await;
+                        },
+                        Err(e) => println!("Failed to decode binary frame from player {}: {}", player_id, e),
                     }
+                } else if msg.is_close() {
+                    // Player removal (which also drops the ball) happens after the loop
                     break;
                 }
             },
@@ -425,30 +690,164 @@ pub async fn handle_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr:
     
     // WebRTC cleanup removed - WebTransport connections managed by dual connection manager
     
-    game_lock.clients.remove(&player_id);
-    
-    // Remove player and update team counts
-    if let Some(player) = game_lock.players.remove(&player_id) {
-        // Decrement team count for the player's team
-        match player.team {
-            crate::player::Team::Red => game_lock.red_team_count = game_lock.red_team_count.saturating_sub(1),
-            crate::player::Team::Blue => game_lock.blue_team_count = game_lock.blue_team_count.saturating_sub(1),
-            crate::player::Team::Yellow => game_lock.yellow_team_count = game_lock.yellow_team_count.saturating_sub(1),
-            crate::player::Team::Green => game_lock.green_team_count = game_lock.green_team_count.saturating_sub(1),
+    // Keep the player (idle) for the grace period so they can resume, and tell their team
+    let grace_secs = reconnect_grace_from_env();
+    let resume_token = if grace_secs > 0 && game_lock.players.contains_key(&player_id) {
+        dual_mgr.suspend_client(player_id).await
+    } else {
+        None
+    };
+    migrate_host(&mut game_lock, &dual_mgr, player_id).await;
+    match resume_token {
+        Some(resume_token) => {
+            game_lock.disconnect_player(player_id);
+            println!("Player {} dropped, holding their place for {}s", player_id, grace_secs);
+            let reconnecting = json!({
+                "type": "player_reconnecting",
+                "player_id": player_id,
+                "grace_secs": grace_secs
+            });
+            notify_teammates(&game_lock, &dual_mgr, player_id, MessageType::PlayerReconnecting, reconnecting).await;
+            tokio::spawn(expire_after_grace(game.clone(), dual_mgr.clone(), resume_token, player_id, grace_secs));
         }
-        println!("Player {} (team: {:?}) disconnected", player_id, player.team);
+        None => remove_disconnected_player(&mut game_lock, &dual_mgr, player_id).await,
     }
+}
+
+// Whether a game WebSocket request asked to join as a spectator (`?spectate=true`)
+pub fn is_spectate_request(query: &HashMap<String, String>) -> bool {
+    matches!(query.get("spectate").map(String::as_str), Some("true") | Some("1"))
+}
+
+// Spectators get the same snapshots and events as players but have no ship, so they don't
+// take a team slot. They can follow a player or use a free camera.
+pub async fn handle_spectator_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr: Arc<DualConnectionManager>, origin: Identity) {
+    let (tx, mut rx) = ws.split();
+    let tx = Arc::new(Mutex::new(tx));
+
+    let (spectator_id, players, map_id) = {
+        let mut game_lock = game.lock().await;
+        let id = game_lock.allocate_player_id();
+        
+        let players: Vec<serde_json::Value> = game_lock.players.values()
+            .map(|player| json!({
+                "id": player.id,
+                "display_name": player.display_name,
+                "team": player.team
+            }))
+            .collect();
+        (id, players, game_lock.map.id.clone())
+    };
     
-    // Recalculate team counts to ensure they're accurate
-    game_lock.recalculate_team_counts();
+    let fast_secret = dual_mgr.add_spectator_connection(spectator_id, Arc::clone(&tx)).await;
+    dual_mgr.set_identity(spectator_id, origin).await;
+    println!("Spectator {} joined ({} spectators)", spectator_id, dual_mgr.spectator_count());
+
+    let init_msg = json!({
+        "type": "init",
+        "your_id": spectator_id,
+        "spectator": true,
+        "camera": SpectatorCamera::Free.to_json(),
+        "players": players,
+        "fast_secret": fast_secret,
+        "map_id": map_id,
+        "quick_chat": quick_chat_presets(),
+        "wire_formats": ["json", "binary"],
+        "protocol_version": PROTOCOL_VERSION
+    });
+    let _ = dual_mgr.send_to_client(spectator_id, MessageType::PlayerJoin, init_msg).await;
+
+    let mut chat_limiter = ChatLimiter::new();
+    while let Some(result) = rx.next().await {
+        let msg = match result {
+            Ok(msg) => msg,
+            Err(e) => {
+                eprintln!("Error receiving message from spectator {}: {:?}", spectator_id, e);
+                break;
+            }
+        };
+        
+        if msg.is_text() {
+            let txt = msg.to_str().unwrap_or_default();
+            
+            if txt == "ping" {
+                let _ = tx.lock().await.send(Message::text("pong")).await;
+                continue;
+            }
+            
+            if let Ok(PingMessage::Ping { timestamp }) = serde_json::from_str::<PingMessage>(txt) {
+                let pong_text = serde_json::to_string(&PingMessage::Pong { timestamp }).unwrap();
+                let _ = tx.lock().await.send(Message::text(pong_text)).await;
+                continue;
+            }
+            
+            if txt.contains("\"type\":\"spectate\"") || txt.contains("\"type\": \"spectate\"") {
+                let spectate_msg = match serde_json::from_str::<SpectateMessage>(txt) {
+                    Ok(spectate_msg) => spectate_msg,
+                    Err(e) => {
+                        println!("Failed to parse spectate message from spectator {}: {:?}", spectator_id, e);
+                        continue;
+                    }
+                };
+                
+                let camera = match spectate_msg.follow {
+                    Some(player_id) if game.lock().await.players.contains_key(&player_id) => SpectatorCamera::Follow(player_id),
+                    Some(player_id) => {
+                        let error_msg = json!({
+                            "type": "error",
+                            "message": format!("Player {} is not in this game", player_id)
+                        });
+                        let _ = tx.lock().await.send(Message::text(error_msg.to_string())).await;
+                        continue;
+                    }
+                    None => SpectatorCamera::Free,
+                };
+                println!("Spectator {} camera: {:?} (type={})", spectator_id, camera, spectate_msg.message_type);
+                dual_mgr.set_spectator_camera(spectator_id, camera).await;
+                let _ = dual_mgr.send_to_client(spectator_id, MessageType::SpectatorCamera, camera.to_json()).await;
+                continue;
+            }
+            
+            if txt.contains("\"type\":\"scoreboard\"") || txt.contains("\"type\": \"scoreboard\"") {
+                send_scoreboard(spectator_id, &game, &dual_mgr).await;
+                continue;
+            }
+            
+            if let Ok(request) = serde_json::from_str::<ChatRequest>(txt) {
+                if let Err(message) = handle_game_chat(spectator_id, request, &mut chat_limiter, &game, &dual_mgr).await {
+                    let error_msg = json!({
+                        "type": "error",
+                        "message": message
+                    });
+                    let _ = tx.lock().await.send(Message::text(error_msg.to_string())).await;
+                }
+                continue;
+            }
+            
+            if txt.contains("\"type\":\"set_wire_format\"") || txt.contains("\"type\": \"set_wire_format\"") {
+                negotiate_wire_format(spectator_id, txt, &dual_mgr).await;
+                continue;
+            }
+            
+            if txt.contains("\"type\":\"snapshot_ack\"") || txt.contains("\"type\": \"snapshot_ack\"") {
+                if let Ok(ack) = serde_json::from_str::<SnapshotAckMessage>(txt) {
+                    dual_mgr.ack_snapshot(spectator_id, ack.snapshot_id).await;
+                }
+                continue;
+            }
+            
+            // Spectators have no ship, so anything else (e.g. input) is ignored
+        } else if msg.is_binary() {
+            if let Ok(ClientFrame::SnapshotAck(snapshot_id)) = decode_client_frame(msg.as_bytes()) {
+                dual_mgr.ack_snapshot(spectator_id, snapshot_id).await;
+            }
+        } else if msg.is_close() {
+            break;
+        }
+    }
     
-    println!("Player {} disconnected. Game now has {} players (Red: {}, Blue: {}, Yellow: {}, Green: {})", 
-             player_id, 
-             game_lock.players.len(),
-             game_lock.red_team_count,
-             game_lock.blue_team_count,
-             game_lock.yellow_team_count,
-             game_lock.green_team_count);
+    dual_mgr.remove_client(spectator_id).await;
+    println!("Spectator {} disconnected ({} spectators)", spectator_id, dual_mgr.spectator_count());
 }
 
 pub fn with_game(game: Arc<Mutex<Game>>) -> impl warp::Filter<Extract = (Arc<Mutex<Game>>,), Error = std::convert::Infallible> + Clone {
@@ -456,68 +855,83 @@ pub fn with_game(game: Arc<Mutex<Game>>) -> impl warp::Filter<Extract = (Arc<Mut
 }
 
 // WebTransport-optimized connection handling
-// Handle fast channel connections (for low-latency data)
+// Handle fast channel connections (for low-latency data). The client opens with
+// `{"client_id": N, "secret": "..."}`, using the id and `fast_secret` from its reliable `init`
+// message; a wrong secret, or a client that already has a fast channel, is turned away.
 pub async fn handle_fast_connection(ws: WebSocket, game: Arc<Mutex<Game>>, dual_mgr: Arc<DualConnectionManager>) {
     println!("Fast channel connection request received - handler called");
     let (tx, mut rx) = ws.split();
+    let tx = Arc::new(Mutex::new(tx));
     
     // Wait for client to identify themselves
     println!("Waiting for fast channel handshake...");
-    if let Some(result) = rx.next().await {
-        if let Ok(msg) = result {
-            if msg.is_text() {
-                let txt = msg.to_str().unwrap_or_default();
-                
-                // Parse client ID from handshake message
-                if let Ok(handshake) = serde_json::from_str::<serde_json::Value>(txt) {
-                    if let Some(client_id) = handshake.get("client_id").and_then(|v| v.as_u64()) {
-                        let client_id = client_id as u32;
-                        println!("Fast channel handshake from client {}", client_id);
-                        
-                        // Add fast connection to dual connection manager
-                        dual_mgr.add_fast_connection(client_id, tx).await;
-                        
-                        // Process fast channel messages
-                        while let Some(result) = rx.next().await {
-                            match result {
-                                Ok(msg) => {
+    if let Some(Ok(msg)) = rx.next().await {
+        if msg.is_text() {
+            let txt = msg.to_str().unwrap_or_default();
+            
+            // Parse client ID and secret from handshake message
+            if let Ok(handshake) = serde_json::from_str::<serde_json::Value>(txt) {
+                let client_id = handshake.get("client_id").and_then(|v| v.as_u64());
+                let secret = handshake.get("secret").and_then(|v| v.as_str()).unwrap_or_default();
+                if let Some(client_id) = client_id {
+                    let client_id = client_id as u32;
+                    println!("Fast channel handshake from client {}", client_id);
+                    
+                    // Add fast connection to dual connection manager
+                    if let Err(reason) = dual_mgr.add_fast_connection(client_id, secret, Arc::clone(&tx)).await {
+                        println!("Rejected fast channel for client {}: {}", client_id, reason);
+                        let error_msg = json!({
+                            "type": "error",
+                            "message": reason
+                        });
+                        let mut tx = tx.lock().await;
+                        let _ = tx.send(Message::text(error_msg.to_string())).await;
+                        let _ = tx.close().await;
+                        return;
+                    }
+                    
+                    // Process fast channel messages
+                    while let Some(result) = rx.next().await {
+                        match result {
+                            Ok(msg) => {
+                                if msg.is_text() || msg.is_binary() {
                                     if msg.is_text() {
-                                        let txt = msg.to_str().unwrap_or_default();
-                                        println!("Fast channel received ANY message from client {}: {}", client_id, txt);
-                                        
-                                        // Parse and process input messages
-                                        if let Ok(input_msg) = serde_json::from_str::<InputMessage>(txt) {
+                                        println!("Fast channel received ANY message from client {}: {}", client_id, msg.to_str().unwrap_or_default());
+                                    }
+                                    
+                                    // Parse and process input messages and snapshot acks
+                                    match parse_fast_payload(msg.as_bytes()) {
+                                        Some(ClientFrame::Input(input_msg)) => {
                                             // Process input immediately with game state
                                             let mut game_state = game.lock().await;
                                             if let Some(player) = game_state.players.get_mut(&client_id) {
-                                                player.input.left = input_msg.left;
-                                                player.input.right = input_msg.right;
-                                                player.input.up = input_msg.up;
-                                                player.input.down = input_msg.down;
-                                                player.input.boost = input_msg.boost.unwrap_or(false);
-                                                player.input.target_x = input_msg.target_x;
-                                                player.input.target_y = input_msg.target_y;
-                                                player.input.shoot = input_msg.shoot.unwrap_or(false);
+                                                apply_fast_input(player, &input_msg);
                                             }
-                                        } else {
-                                            println!("Fast channel received non-input message from client {}: {}", client_id, txt);
+                                        }
+                                        Some(ClientFrame::SnapshotAck(snapshot_id)) => {
+                                            dual_mgr.ack_snapshot(client_id, snapshot_id).await;
+                                        }
+                                        None => {
+                                            println!("Fast channel received non-input message from client {}", client_id);
                                         }
                                     }
                                 }
-                                Err(e) => {
-                                    println!("Fast channel error for client {}: {:?}", client_id, e);
-                                    break;
-                                }
+                            }
+                            Err(e) => {
+                                println!("Fast channel error for client {}: {:?}", client_id, e);
+                                break;
                             }
                         }
-                        
-                        println!("Fast channel disconnected for client {}", client_id);
-                        return;
                     }
+                    
+                    // Let the client open a new fast channel after this one drops
+                    dual_mgr.remove_fast_connection(client_id).await;
+                    println!("Fast channel disconnected for client {}", client_id);
+                    return;
                 }
-                
-                println!("Invalid fast channel handshake: {}", txt);
             }
+            
+            println!("Invalid fast channel handshake: {}", txt);
         }
     }
     
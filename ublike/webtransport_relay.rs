@@ -1,34 +1,178 @@
-// Simplified WebTransport Relay - Placeholder for ultra-low latency gaming
-// This will be a simple placeholder that compiles with current wtransport version
+// WebTransport Relay - HTTP/3 WebTransport server for ultra-low latency gaming
+//
+// Clients open a session on `https://host:PORT/wt?client_id=N&secret=S` (default game) or
+// `https://host:PORT/game/{GAME_ID}/wt?client_id=N&secret=S` (lobby game) after receiving their
+// id and `fast_secret` in the reliable `init` message. The session is bound to that player's DualConnection, which
+// then sends PositionUpdate/BallPosition/ProjectileUpdate as unreliable datagrams. Datagrams
+// from the client are treated as input messages or snapshot acks (JSON or binary), the same
+// as the fast WebSocket channel.
 
-// use std::sync::Arc; // Unused import removed  
-// use tokio::sync::Mutex; // Unused import removed
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use once_cell::sync::OnceCell;
+use wtransport::{Endpoint, Identity, ServerConfig};
+use wtransport::endpoint::IncomingSession;
+use crate::dual_connection::DualConnectionManager;
+use crate::game::{Game, GLOBAL_GAME};
+use crate::lobby::LobbyManager;
+use crate::protocol::ClientFrame;
+use crate::websocket::{apply_fast_input, parse_fast_payload};
+
+// SHA-256 of the server certificate, for browsers using `serverCertificateHashes` with self-signed certs
+pub static CERT_HASH: OnceCell<String> = OnceCell::new();
 
 pub struct WebTransportRelay {
     port: u16,
+    default_dual_mgr: Arc<DualConnectionManager>,
+    lobby: Arc<Mutex<LobbyManager>>,
 }
 
 impl WebTransportRelay {
-    pub fn new(port: u16) -> Self {
-        Self { port }
+    pub fn new(port: u16, default_dual_mgr: Arc<DualConnectionManager>, lobby: Arc<Mutex<LobbyManager>>) -> Self {
+        Self { port, default_dual_mgr, lobby }
     }
-    
+
+    // Use WEBTRANSPORT_CERT/WEBTRANSPORT_KEY PEM files when provided, otherwise a self-signed
+    // certificate for local testing (browsers accept it through serverCertificateHashes)
+    async fn load_identity() -> Result<Identity, Box<dyn std::error::Error>> {
+        match (std::env::var("WEBTRANSPORT_CERT"), std::env::var("WEBTRANSPORT_KEY")) {
+            (Ok(cert_path), Ok(key_path)) => {
+                println!("🔐 Loading WebTransport certificate from {}", cert_path);
+                Ok(Identity::load_pemfiles(cert_path, key_path).await?)
+            }
+            _ => {
+                println!("🔐 No WebTransport certificate configured, generating a self-signed one");
+                Ok(Identity::self_signed(["localhost", "127.0.0.1", "::1"])?)
+            }
+        }
+    }
+
     pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        println!("🚀 WebTransport Relay (Placeholder) would start on port {}...", self.port);
-        println!("⚠️ This is a simplified version - full implementation requires wtransport API compatibility fixes");
-        println!("💡 For now, use the hybrid approach: WebSocket for reliable data + fast WebSocket channel for critical data");
-        
-        // For now, just start a simple task that shows it's "running"
+        println!("🚀 Starting WebTransport Relay on port {}...", self.port);
+
+        let identity = Self::load_identity().await?;
+        let cert_hash = identity.certificate_chain().as_slice()[0]
+            .hash()
+            .fmt(wtransport::tls::Sha256DigestFmt::DottedHex);
+        println!("🔑 WebTransport certificate hash: {}", cert_hash);
+        let _ = CERT_HASH.set(cert_hash);
+
+        let config = ServerConfig::builder()
+            .with_bind_default(self.port)
+            .with_identity(identity)
+            .keep_alive_interval(Some(std::time::Duration::from_secs(3)))
+            .build();
+        let endpoint = Endpoint::server(config)?;
+
+        let default_dual_mgr = self.default_dual_mgr.clone();
+        let lobby = self.lobby.clone();
         tokio::spawn(async move {
             loop {
-                tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
-                println!("📡 WebTransport Relay placeholder is running (use WebSocket + fast channel for now)");
+                let incoming = endpoint.accept().await;
+                tokio::spawn(handle_session(incoming, default_dual_mgr.clone(), lobby.clone()));
             }
         });
-        
-        println!("✅ WebTransport Relay placeholder initialized");
-        
+
+        println!("✅ WebTransport Relay listening on https://0.0.0.0:{}", self.port);
+
         // Don't block here - return immediately so the main server can continue
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+// Parse `/wt?client_id=N&secret=S` or `/game/{GAME_ID}/wt?client_id=N&secret=S` into
+// (game id, client id, secret)
+fn parse_session_path(path: &str) -> Option<(Option<String>, u32, String)> {
+    let (route, query) = path.split_once('?')?;
+    let param = |name: &str| query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value);
+    let client_id = param("client_id").and_then(|value| value.parse::<u32>().ok())?;
+    let secret = param("secret").unwrap_or_default().to_string();
+
+    let segments: Vec<&str> = route.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["wt"] => Some((None, client_id, secret)),
+        ["game", game_id, "wt"] => Some((Some(game_id.to_string()), client_id, secret)),
+        _ => None,
+    }
+}
+
+async fn handle_session(incoming: IncomingSession, default_dual_mgr: Arc<DualConnectionManager>, lobby: Arc<Mutex<LobbyManager>>) {
+    let request = match incoming.await {
+        Ok(request) => request,
+        Err(e) => {
+            println!("⚠️ WebTransport connection failed: {:?}", e);
+            return;
+        }
+    };
+
+    let (game_id, client_id, secret) = match parse_session_path(request.path()) {
+        Some(parsed) => parsed,
+        None => {
+            println!("⚠️ WebTransport session with invalid path: {}", request.path());
+            request.not_found().await;
+            return;
+        }
+    };
+
+    // Find the game and connection manager this session belongs to
+    let target: Option<(Arc<Mutex<Game>>, Arc<DualConnectionManager>)> = match &game_id {
+        None => Some((GLOBAL_GAME.clone(), default_dual_mgr)),
+        Some(id) => {
+            let lobby = lobby.lock().await;
+            lobby.games.get(id).map(|instance| (instance.game.clone(), instance.dual_mgr.clone()))
+        }
+    };
+    let (game, dual_mgr) = match target {
+        Some(target) => target,
+        None => {
+            println!("⚠️ WebTransport session for unknown game {:?}", game_id);
+            request.not_found().await;
+            return;
+        }
+    };
+
+    // Only bind to players and spectators that already have a reliable connection in this game
+    // and sent the secret it was given
+    if let Err(reason) = dual_mgr.authorize_webtransport(client_id, &secret).await {
+        println!("⚠️ WebTransport session rejected for client {} in game {:?}: {}", client_id, game_id, reason);
+        request.forbidden().await;
+        return;
+    }
+
+    let connection = match request.accept().await {
+        Ok(connection) => connection,
+        Err(e) => {
+            println!("⚠️ Failed to accept WebTransport session for client {}: {:?}", client_id, e);
+            return;
+        }
+    };
+
+    dual_mgr.add_webtransport_connection(client_id, connection.clone()).await;
+
+    // Incoming datagrams are input messages or snapshot acks, handled exactly like the fast WebSocket channel
+    loop {
+        match connection.receive_datagram().await {
+            Ok(datagram) => match parse_fast_payload(datagram.payload().as_ref()) {
+                Some(ClientFrame::Input(input_msg)) => {
+                    let mut game_state = game.lock().await;
+                    if let Some(player) = game_state.players.get_mut(&client_id) {
+                        apply_fast_input(player, &input_msg);
+                    }
+                }
+                Some(ClientFrame::SnapshotAck(snapshot_id)) => {
+                    dual_mgr.ack_snapshot(client_id, snapshot_id).await;
+                }
+                None => {}
+            },
+            Err(e) => {
+                println!("WebTransport session closed for client {}: {:?}", client_id, e);
+                break;
+            }
+        }
+    }
+
+    dual_mgr.remove_webtransport_connection(client_id).await;
+}